@@ -1,4 +1,5 @@
 extern crate ordered_float;
+extern crate rand;
 
 use aabb::AABB;
 use shape::*;
@@ -125,22 +126,80 @@ impl<'a> BVH<'a> {
       right: right,
     }
   }
+
+  // filterで除外されない物体との交差 (引数は物体の番号)
+  pub fn intersect_filter<F>(&self, ray: &Ray, filter: F) -> Option<Intersection> where F: Fn(usize) -> bool {
+    let mut candidate = Vec::new();
+    self.root.may_intersect(ray, &mut candidate);
+    candidate.iter().filter( |&&i| filter(i) ).fold(None, |nearest: Option<Intersection>, &i| {
+      match self.list[i].intersect(&ray) {
+        // 最も近い交差より手前のときだけ不透明度を評価する
+        Some(mut intersection) if nearest.as_ref().map( |n| intersection.distance < n.distance ).unwrap_or(true) => {
+          // 不透明度にしたがって確率的に面を通り抜ける
+          if intersection.material.has_opacity() {
+            let opacity = intersection.material.opacity(&intersection.point(ray));
+            if opacity < 1.0 && rand::random::<f32>() >= opacity {
+              return nearest
+            }
+          }
+          intersection.object = i;
+          Some(intersection)
+        },
+        _ => nearest,
+      }
+    })
+  }
 }
 
 impl<'a> Shape for BVH<'a> {
   fn intersect(&self, ray: &Ray) -> Option<Intersection> {
-    let mut candidate = Vec::new();
-    self.root.may_intersect(ray, &mut candidate);
-    candidate.iter().flat_map( |&i| {
-      self.list[i].intersect(&ray)
-    }).min_by(
-      |a, b| {
-        a.distance.partial_cmp(&b.distance).unwrap()
-      },
-    )
+    self.intersect_filter(ray, |_| true)
   }
 
   fn aabb(&self) -> &AABB {
     self.root.aabb()
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::Arc;
+  use triangle::Triangle;
+  use material::material::*;
+  use texture::texture::*;
+  use math::vector::*;
+
+  fn quad(z: f32, material: Arc<Material + Send + Sync>) -> Box<SurfaceShape + Send + Sync> {
+    box Triangle::new(
+      Vector3::new(-1.0, -1.0, z),
+      Vector3::new(1.0, -1.0, z),
+      Vector3::new(0.0, 1.0, z),
+      material,
+    )
+  }
+
+  #[test]
+  fn opacity_mask_test() {
+    let white = Arc::new(LambertianMaterial {
+      emission: Vector3::zero(),
+      albedo: Arc::new(ConstantTexture { value: Vector3::new(1.0, 1.0, 1.0) }),
+    });
+    // 手前の面は完全に透明
+    let cutout = Arc::new(MaskMaterial {
+      base: white.clone(),
+      opacity: Arc::new(ConstantTexture { value: Vector3::zero() }),
+    });
+    let list = vec![quad(1.0, cutout), quad(0.0, white)];
+    let bvh = BVH::new(&list);
+    let ray = Ray {
+      origin: Vector3::new(0.0, 0.0, 5.0),
+      direction: Vector3::new(0.0, 0.0, -1.0),
+      differential: None,
+    };
+    let i = bvh.intersect(&ray).unwrap();
+    assert!((i.distance - 5.0).abs() < 1e-4, "{}", i.distance);
+    // 不透明なマテリアルは不透明度を評価しない
+    assert!(list[0].material().has_opacity() && !i.material.has_opacity());
+  }
+}
@@ -3,8 +3,9 @@ extern crate rand;
 use constant::*;
 use math::vector::*;
 use math::matrix::*;
-use ray::Ray;
+use ray::{Ray, RayDifferential};
 use sample::Sample;
+use sky::CubeFaces;
 
 pub trait Camera {
   fn sample(&self, x: usize, y: usize) -> (Sample<Ray>, f32);
@@ -91,6 +92,19 @@ impl IdealPinholeCamera {
     }
   }
 
+  fn differential(&self, direction: Vector3) -> RayDifferential {
+    // センサー上の点が1画素ずれたときの (正規化前の) 方向ベクトルの変化
+    let dx = self.right * (self.sensor_size[0] / self.resolution[0] as f32);
+    let dy = -self.up * (self.sensor_size[1] / self.resolution[1] as f32);
+    RayDifferential {
+      origin: (Vector3::zero(), Vector3::zero()),
+      direction: (
+        RayDifferential::normalized_direction(direction, dx),
+        RayDifferential::normalized_direction(direction, dy),
+      ),
+    }
+  }
+
   fn geometry_term(&self, _direction: Vector3) -> f32 {
     1.0
   }
@@ -100,9 +114,11 @@ impl Camera for IdealPinholeCamera {
   fn sample(&self, x: usize, y: usize) -> (Sample<Ray>, f32) {
     let sensor_sample = self.sample_sensor(x, y);
     let aperture_sample = self.sample_aperture();
+    let direction = aperture_sample.value - sensor_sample.value;
     let ray = Ray {
       origin: aperture_sample.value,
-      direction: (aperture_sample.value - sensor_sample.value).normalize(),
+      direction: direction.normalize(),
+      differential: Some(self.differential(direction)),
     };
     let direction_to_sensor = ray.direction;
     (
@@ -128,11 +144,20 @@ impl Camera for IdealPinholeCamera {
       sensor_diagonal: sensor_diagonal,
       fov: fov,
       xfov: xfov,
-      f_number: 1.0 / 0.0,
+      f_number: INF,
     }
   }
 }
 
+// 全方位の画像の配置
+#[derive(Debug, Clone, Copy)]
+pub enum PanoramaLayout {
+  // 緯度経度 (画像の中心が正面)
+  Equirectangular,
+  // キューブマップ (IBLと同じ並び, カメラ座標系で -Z が正面)
+  Cube,
+}
+
 #[derive(Debug)]
 pub struct OmnidirectionalCamera {
   // カメラの方向を基準とした正規直交基底
@@ -143,11 +168,18 @@ pub struct OmnidirectionalCamera {
   pub resolution: [usize; 2],
   // 入射口の中心座標(m)
   pub aperture_position: Vector3,
+  pub layout: PanoramaLayout,
+  // 瞳孔間距離 (シーンと同じ長さの単位, 指定されると上に左目, 下に右目の画像を並べるODSになる)
+  pub ipd: Option<f32>,
+  // キューブマップの1面の大きさと面ごとの画像上の位置
+  cube: Option<(usize, [(usize, usize, bool); 6])>,
 }
 
 impl OmnidirectionalCamera {
   pub fn new(
     matrix: Matrix4,
+    layout: PanoramaLayout,
+    ipd: Option<f32>,
     resolution: [usize; 2],
   ) -> OmnidirectionalCamera {
     let aperture_position: Vector3 = matrix.row(3).into();
@@ -155,28 +187,322 @@ impl OmnidirectionalCamera {
     let forward = &matrix * Vector3::new(0.0, 0.0, -1.0);
     let right = &matrix * Vector3::new(1.0, 0.0, 0.0);
     let up = &matrix * Vector3::new(0.0, 1.0, 0.0);
+    if ipd.is_some() && resolution[1] % 2 != 0 {
+      panic!(format!("Stereo panorama height must be even, but {} is given.", resolution[1]));
+    }
+    let eye_height = if ipd.is_some() { resolution[1] / 2 } else { resolution[1] };
+    let cube = match layout {
+      PanoramaLayout::Equirectangular => None,
+      PanoramaLayout::Cube => Some(CubeFaces::arrangement(resolution[0], eye_height).unwrap_or_else( ||
+        panic!(format!("Unsupported cube map resolution `{}x{}`.", resolution[0], eye_height))
+      )),
+    };
     OmnidirectionalCamera {
       forward: forward,
       right: right,
       up: up,
       resolution: resolution,
       aperture_position: aperture_position,
+      layout: layout,
+      ipd: ipd,
+      cube: cube,
+    }
+  }
+
+  // 片目の画像上の座標(0~1) -> 入射の方向 (キューブマップの空白ではNone)
+  fn direction(&self, u: f32, v: f32) -> Option<Vector3> {
+    match self.cube {
+      None => {
+        // 経度 (画像の中心が正面, 右向きが正), 天頂からの角度
+        let p = (u - 0.5) * PI * 2.0;
+        let t = v * PI;
+        // IBLと同じく上方向を天頂とする
+        let horizontal = self.forward * p.cos() + self.right * p.sin();
+        Some(self.up * t.cos() + horizontal * t.sin())
+      },
+      Some((size, ref placement)) => {
+        // 面の単位での座標
+        let x = u * (self.resolution[0] / size) as f32;
+        let y = v * placement_rows(placement) as f32;
+        let (col, row) = (x.floor() as usize, y.floor() as usize);
+        placement.iter().position( |&(c, r, _)| c == col && r == row ).map( |face| {
+          let (s, t) = ((x - col as f32) * 2.0 - 1.0, (y - row as f32) * 2.0 - 1.0);
+          let (s, t) = if placement[face].2 { (-s, -t) } else { (s, t) };
+          // カメラ座標系 (右, 上, 後ろ) の方向をワールド座標にする
+          let d = CubeFaces::unproject(face, s, t);
+          (self.right * d.x + self.up * d.y - self.forward * d.z).normalize()
+        })
+      },
+    }
+  }
+
+  // 目の位置 (-1: 左, 0: 中央, 1: 右), 方向 -> レイの始点
+  fn origin(&self, eye: f32, direction: Vector3) -> Vector3 {
+    match self.ipd {
+      None => self.aperture_position,
+      Some(ipd) => {
+        // 視線を水平面に射影した方向に垂直に目をずらす (天頂, 天底では中央)
+        let side = direction.cross(self.up);
+        let side = if side.norm() > EPS { side.normalize() } else { Vector3::zero() };
+        self.aperture_position + side * (eye * ipd / 2.0)
+      },
     }
   }
 }
 
+// キューブマップの面の並びの行数
+fn placement_rows(placement: &[(usize, usize, bool); 6]) -> usize {
+  placement.iter().map( |&(_, r, _)| r + 1 ).max().unwrap()
+}
+
 impl Camera for OmnidirectionalCamera {
   fn sample(&self, x: usize, y: usize) -> (Sample<Ray>, f32) {
     // 画素内の1点を一様分布でサンプリング(0~1の乱数)
-    let u = rand::random::<f32>();
-    let v = rand::random::<f32>();
-    // センサー中心を基準とした平面座標でのサンプリング点の座標(m)
-    let p = (x as f32 + u) / self.resolution[0] as f32 * PI * 2.0;
-    let t = (y as f32 + v) / self.resolution[1] as f32 * PI;
-    let direction = Vector3::new(t.sin() * p.cos(), t.sin() * p.sin(), t.cos());
+    let sx = x as f32 + rand::random::<f32>();
+    let sy = y as f32 + rand::random::<f32>();
+    // ステレオでは上半分が左目, 下半分が右目
+    let (eye, eye_height, sy) = match self.ipd {
+      None => (0.0, self.resolution[1] as f32, sy),
+      Some(_) => {
+        let h = (self.resolution[1] / 2) as f32;
+        if sy < h { (-1.0, h, sy) } else { (1.0, h, sy - h) }
+      },
+    };
+    let width = self.resolution[0] as f32;
+    let maybe_direction = self.direction(sx / width, sy / eye_height);
+    let direction = maybe_direction.unwrap_or(self.forward);
+    let origin = self.origin(eye, direction);
+    // 1画素ずれたときの始点と方向ベクトルの変化 (差分で近似する)
+    let diff = |d: Option<Vector3>| d.map( |d| (self.origin(eye, d) - origin, d - direction) )
+      .unwrap_or((Vector3::zero(), Vector3::zero()));
+    let (dox, ddx) = diff(self.direction((sx + 1.0) / width, sy / eye_height));
+    let (doy, ddy) = diff(self.direction(sx / width, (sy + 1.0) / eye_height));
+    let ray = Ray {
+      origin: origin,
+      direction: direction,
+      differential: Some(RayDifferential {
+        origin: (dox, doy),
+        direction: (ddx, ddy),
+      }),
+    };
+    (
+      Sample {
+        value: ray,
+        pdf: 1.0,
+      },
+      // キューブマップの空白には光が届かない
+      if maybe_direction.is_some() { 1.0 } else { 0.0 },
+    )
+  }
+
+  fn sensor_sensitivity(&self) -> f32 {
+    1.0
+  }
+
+  fn info(&self) -> CameraInfo {
+    unimplemented!()
+  }
+}
+
+// 魚眼レンズの射影方式
+#[derive(Debug, Clone, Copy)]
+pub enum FisheyeProjection {
+  // 等距離射影 (r = f θ)
+  Equidistant,
+  // 等立体角射影 (r = 2f sin(θ/2))
+  Equisolid,
+  // 立体射影 (r = 2f tan(θ/2))
+  Stereographic,
+}
+
+impl FisheyeProjection {
+  // イメージサークルの半径で正規化した像高, 画角の半分 -> 光軸からの角度
+  pub fn theta(&self, r: f32, max_theta: f32) -> f32 {
+    match *self {
+      FisheyeProjection::Equidistant => r * max_theta,
+      FisheyeProjection::Equisolid => 2.0 * (r * (max_theta / 2.0).sin()).min(1.0).asin(),
+      FisheyeProjection::Stereographic => 2.0 * (r * (max_theta / 2.0).tan()).atan(),
+    }
+  }
+
+  // 画角の半分 -> イメージサークルの半径を1としたときの焦点距離
+  pub fn focal_length(&self, max_theta: f32) -> f32 {
+    match *self {
+      FisheyeProjection::Equidistant => 1.0 / max_theta,
+      FisheyeProjection::Equisolid => 1.0 / (2.0 * (max_theta / 2.0).sin()),
+      FisheyeProjection::Stereographic => 1.0 / (2.0 * (max_theta / 2.0).tan()),
+    }
+  }
+}
+
+// 立体射影の画角の上限 (度, 360°では像高が発散する)
+const STEREOGRAPHIC_MAX_FOV: f32 = 350.0;
+
+// 魚眼レンズのカメラ (画像の短辺に内接するイメージサークルに射影する)
+#[derive(Debug)]
+pub struct FisheyeCamera {
+  // カメラの方向を基準とした正規直交基底
+  pub forward: Vector3,
+  pub right: Vector3,
+  pub up: Vector3,
+  // センサーの解像度
+  pub resolution: [usize; 2],
+  // 入射口の中心座標(m)
+  pub aperture_position: Vector3,
+  // 画角の半分(rad)
+  pub max_theta: f32,
+  pub projection: FisheyeProjection,
+}
+
+impl FisheyeCamera {
+  pub fn new(
+    matrix: Matrix4,
+    fov: f32,
+    projection: FisheyeProjection,
+    resolution: [usize; 2],
+  ) -> FisheyeCamera {
+    if fov <= 0.0 || fov > 360.0 {
+      panic!(format!("Fisheye fov must be in (0, 360], but {} is given.", fov));
+    }
+    let fov = match projection {
+      FisheyeProjection::Stereographic => fov.min(STEREOGRAPHIC_MAX_FOV),
+      _ => fov,
+    };
+    let aperture_position: Vector3 = matrix.row(3).into();
+    // カメラの入射の方向を基準(forward)に正規直交基底
+    let forward = &matrix * Vector3::new(0.0, 0.0, -1.0);
+    let right = &matrix * Vector3::new(1.0, 0.0, 0.0);
+    let up = &matrix * Vector3::new(0.0, 1.0, 0.0);
+    FisheyeCamera {
+      forward: forward,
+      right: right,
+      up: up,
+      resolution: resolution,
+      aperture_position: aperture_position,
+      max_theta: fov * PI / 360.0,
+      projection: projection,
+    }
+  }
+
+  // 画像上の座標(画素) -> 入射の方向 (イメージサークルの外ではNone)
+  fn direction(&self, sx: f32, sy: f32) -> Option<Vector3> {
+    let radius = self.resolution[0].min(self.resolution[1]) as f32 / 2.0;
+    let dx = sx - self.resolution[0] as f32 / 2.0;
+    let dy = self.resolution[1] as f32 / 2.0 - sy;
+    let r = (dx * dx + dy * dy).sqrt() / radius;
+    if r > 1.0 {
+      return None
+    }
+    let theta = self.projection.theta(r, self.max_theta);
+    let phi = dy.atan2(dx);
+    let side = self.right * phi.cos() + self.up * phi.sin();
+    Some(self.forward * theta.cos() + side * theta.sin())
+  }
+}
+
+impl Camera for FisheyeCamera {
+  fn sample(&self, x: usize, y: usize) -> (Sample<Ray>, f32) {
+    // 画素内の1点を一様分布でサンプリング(0~1の乱数)
+    let sx = x as f32 + rand::random::<f32>();
+    let sy = y as f32 + rand::random::<f32>();
+    let maybe_direction = self.direction(sx, sy);
+    let direction = maybe_direction.unwrap_or(self.forward);
+    // 1画素ずれたときの方向ベクトルの変化 (差分で近似する)
+    let diff = |d: Option<Vector3>| d.map( |d| d - direction ).unwrap_or(Vector3::zero());
     let ray = Ray {
       origin: self.aperture_position,
       direction: direction,
+      differential: Some(RayDifferential {
+        origin: (Vector3::zero(), Vector3::zero()),
+        direction: (
+          diff(self.direction(sx + 1.0, sy)),
+          diff(self.direction(sx, sy + 1.0)),
+        ),
+      }),
+    };
+    (
+      Sample {
+        value: ray,
+        pdf: 1.0,
+      },
+      // イメージサークルの外には光が届かない
+      if maybe_direction.is_some() { 1.0 } else { 0.0 },
+    )
+  }
+
+  fn sensor_sensitivity(&self) -> f32 {
+    1.0
+  }
+
+  fn info(&self) -> CameraInfo {
+    // 長さはイメージサークルの半径を1とする, 横方向もイメージサークルの縁までしか写らない
+    let fov = self.max_theta * 360.0 / PI;
+    CameraInfo {
+      focal_length: self.projection.focal_length(self.max_theta),
+      sensor_diagonal: 2.0,
+      fov: fov,
+      xfov: fov,
+      f_number: INF,
+    }
+  }
+}
+
+// 平行投影のカメラ
+#[derive(Debug)]
+pub struct OrthographicCamera {
+  // カメラの方向を基準とした正規直交基底
+  pub forward: Vector3,
+  pub right: Vector3,
+  pub up: Vector3,
+  // 画面の中心座標(m)
+  pub position: Vector3,
+  // センサーの解像度
+  pub resolution: [usize; 2],
+  // 写る範囲の大きさ(m)
+  pub view_size: [f32; 2],
+}
+
+impl OrthographicCamera {
+  pub fn new(
+    matrix: Matrix4,
+    width: f32,
+    resolution: [usize; 2],
+  ) -> OrthographicCamera {
+    let position: Vector3 = matrix.row(3).into();
+    // カメラの入射の方向を基準(forward)に正規直交基底
+    let forward = &matrix * Vector3::new(0.0, 0.0, -1.0);
+    let right = &matrix * Vector3::new(1.0, 0.0, 0.0);
+    let up = &matrix * Vector3::new(0.0, 1.0, 0.0);
+    let height = width * resolution[1] as f32 / resolution[0] as f32;
+    OrthographicCamera {
+      forward: forward,
+      right: right,
+      up: up,
+      position: position,
+      resolution: resolution,
+      view_size: [width, height],
+    }
+  }
+}
+
+impl Camera for OrthographicCamera {
+  fn sample(&self, x: usize, y: usize) -> (Sample<Ray>, f32) {
+    // 画素内の1点を一様分布でサンプリング(0~1の乱数)
+    let u = rand::random::<f32>();
+    let v = rand::random::<f32>();
+    // 画面の中心を基準とした平面座標でのサンプリング点の座標(m)
+    let px = (((x as f32 + u) / self.resolution[0] as f32) - 0.5) * self.view_size[0];
+    let py = (((y as f32 + v) / self.resolution[1] as f32) - 0.5) * self.view_size[1];
+    // 1画素ずれたときのレイの始点の変化 (方向は変わらない)
+    let dx = self.right * (self.view_size[0] / self.resolution[0] as f32);
+    let dy = -self.up * (self.view_size[1] / self.resolution[1] as f32);
+    let ray = Ray {
+      origin: self.position + self.right * px - self.up * py,
+      direction: self.forward,
+      differential: Some(RayDifferential {
+        origin: (dx, dy),
+        direction: (Vector3::zero(), Vector3::zero()),
+      }),
     };
     (
       Sample {
@@ -192,7 +518,14 @@ impl Camera for OmnidirectionalCamera {
   }
 
   fn info(&self) -> CameraInfo {
-    unimplemented!()
+    // 焦点距離, 画角は無限遠, 0とみなす
+    CameraInfo {
+      focal_length: INF,
+      sensor_diagonal: (self.view_size[0].powi(2) + self.view_size[1].powi(2)).sqrt(),
+      fov: 0.0,
+      xfov: 0.0,
+      f_number: INF,
+    }
   }
 }
 
@@ -299,6 +632,19 @@ impl PinholeCamera {
     }
   }
 
+  fn differential(&self, direction: Vector3) -> RayDifferential {
+    // センサー上の点が1画素ずれたときの (正規化前の) 方向ベクトルの変化
+    let dx = self.right * (self.sensor_size[0] / self.resolution[0] as f32);
+    let dy = -self.up * (self.sensor_size[1] / self.resolution[1] as f32);
+    RayDifferential {
+      origin: (Vector3::zero(), Vector3::zero()),
+      direction: (
+        RayDifferential::normalized_direction(direction, dx),
+        RayDifferential::normalized_direction(direction, dy),
+      ),
+    }
+  }
+
   fn geometry_term(&self, direction: Vector3) -> f32 {
     // cos項
     let cos_term = direction.dot(self.forward);
@@ -313,9 +659,11 @@ impl Camera for PinholeCamera {
   fn sample(&self, x: usize, y: usize) -> (Sample<Ray>, f32) {
     let sensor_sample = self.sample_sensor(x, y);
     let aperture_sample = self.sample_aperture();
+    let direction = aperture_sample.value - sensor_sample.value;
     let ray = Ray {
       origin: aperture_sample.value,
-      direction: (aperture_sample.value - sensor_sample.value).normalize(),
+      direction: direction.normalize(),
+      differential: Some(self.differential(direction)),
     };
     let direction_to_sensor = ray.direction;
     (
@@ -444,6 +792,23 @@ impl LensCamera {
     }
   }
 
+  fn differential(&self, direction: Vector3, sensor_center: Vector3) -> RayDifferential {
+    // センサー上の点が1画素ずれたときのオブジェクトプレーン上の点の変化 (開口部上の点は固定)
+    let k = self.focus_distance / sensor_center.dot(self.forward);
+    let object_plane_differential = |dc: Vector3| {
+      dc * k - sensor_center * (k * dc.dot(self.forward) / sensor_center.dot(self.forward))
+    };
+    let dx = object_plane_differential(self.right * (self.sensor_size[0] / self.resolution[0] as f32));
+    let dy = object_plane_differential(-self.up * (self.sensor_size[1] / self.resolution[1] as f32));
+    RayDifferential {
+      origin: (Vector3::zero(), Vector3::zero()),
+      direction: (
+        RayDifferential::normalized_direction(direction, dx),
+        RayDifferential::normalized_direction(direction, dy),
+      ),
+    }
+  }
+
   fn geometry_term(&self, direction: Vector3) -> f32 {
     // cos項
     let cos_term = direction.dot(self.forward);
@@ -462,9 +827,11 @@ impl Camera for LensCamera {
     let sensor_center = self.aperture_position - sensor_sample.value;
     // 開口部中心からオブジェクトプレーン上
     let object_plane = sensor_center * (self.focus_distance / sensor_center.dot(self.forward));
+    let direction = self.aperture_position + object_plane - aperture_sample.value;
     let ray = Ray {
       origin: aperture_sample.value,
-      direction: (self.aperture_position + object_plane - aperture_sample.value).normalize(),
+      direction: direction.normalize(),
+      differential: Some(self.differential(direction, sensor_center)),
     };
     (
       Sample {
@@ -506,3 +873,119 @@ pub struct CameraInfo {
   pub xfov: f32,
   pub f_number: f32,
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn ods_test() {
+    // 正面が -Z, 瞳孔間距離 0.1 の上下ステレオ
+    let camera = OmnidirectionalCamera::new(
+      Matrix4::unit(),
+      PanoramaLayout::Equirectangular,
+      Some(0.1),
+      [8, 8],
+    );
+    let forward = camera.direction(0.5, 0.5).unwrap();
+    assert!((forward - Vector3::new(0.0, 0.0, -1.0)).norm() < EPS, "{}", forward);
+    let right = camera.direction(0.75, 0.5).unwrap();
+    assert!((right - Vector3::new(1.0, 0.0, 0.0)).norm() < EPS, "{}", right);
+    // 左目は視線の左側, 右目は右側にずれる
+    assert!((camera.origin(-1.0, forward) - Vector3::new(-0.05, 0.0, 0.0)).norm() < EPS);
+    assert!((camera.origin(1.0, right) - Vector3::new(0.0, 0.0, 0.05)).norm() < EPS);
+    // 天頂では両目が中央に寄る
+    assert!(camera.origin(1.0, Vector3::new(0.0, 1.0, 0.0)).norm() < EPS);
+    // 上半分は左目, 下半分は右目
+    let (top, _) = camera.sample(4, 2);
+    let (bottom, _) = camera.sample(4, 6);
+    assert!(top.value.origin.x < 0.0, "{}", top.value.origin);
+    assert!(bottom.value.origin.x > 0.0, "{}", bottom.value.origin);
+  }
+
+  #[test]
+  fn cube_panorama_test() {
+    // 横長の十字形 (中央の行は -X, +Z, +X, -Z)
+    let camera = OmnidirectionalCamera::new(
+      Matrix4::unit(),
+      PanoramaLayout::Cube,
+      None,
+      [8, 6],
+    );
+    let back = camera.direction(3.0 / 8.0, 0.5).unwrap();
+    assert!((back - Vector3::new(0.0, 0.0, 1.0)).norm() < EPS, "{}", back);
+    let forward = camera.direction(7.0 / 8.0, 0.5).unwrap();
+    assert!((forward - Vector3::new(0.0, 0.0, -1.0)).norm() < EPS, "{}", forward);
+    let up = camera.direction(3.0 / 8.0, 1.0 / 6.0).unwrap();
+    assert!((up - Vector3::new(0.0, 1.0, 0.0)).norm() < EPS, "{}", up);
+    // 十字形の空白
+    assert!(camera.direction(0.1, 0.1).is_none());
+  }
+
+  #[test]
+  fn fisheye_projection_test() {
+    let max_theta = 0.8 * PI;
+    for &projection in &[
+      FisheyeProjection::Equidistant,
+      FisheyeProjection::Equisolid,
+      FisheyeProjection::Stereographic,
+    ] {
+      // 中心は光軸, イメージサークルの縁は画角の半分
+      assert!(projection.theta(0.0, max_theta).abs() < EPS, "{:?}", projection);
+      assert!((projection.theta(1.0, max_theta) - max_theta).abs() < EPS, "{:?}", projection);
+    }
+    // 等立体角射影では像の面積が立体角に比例する
+    let theta = FisheyeProjection::Equisolid.theta(0.5, PI / 2.0);
+    assert!(((1.0 - theta.cos()) - 0.25).abs() < EPS, "{}", theta);
+    // 途中の像高も射影の式 r = f g(θ) と一致する
+    let theta = 0.3 * PI;
+    let radii = [
+      (FisheyeProjection::Equidistant, theta),
+      (FisheyeProjection::Equisolid, 2.0 * (theta / 2.0).sin()),
+      (FisheyeProjection::Stereographic, 2.0 * (theta / 2.0).tan()),
+    ];
+    for &(projection, r) in &radii {
+      let r = r * projection.focal_length(max_theta);
+      assert!((projection.theta(r, max_theta) - theta).abs() < EPS, "{:?} {}", projection, r);
+    }
+  }
+
+  #[test]
+  fn fisheye_test() {
+    // 上を向いた全天の等距離射影
+    let camera = FisheyeCamera::new(
+      Matrix4::unit(),
+      180.0,
+      FisheyeProjection::Equidistant,
+      [8, 8],
+    );
+    let center = camera.direction(4.0, 4.0).unwrap();
+    assert!((center - Vector3::new(0.0, 0.0, -1.0)).norm() < EPS, "{}", center);
+    // 縁の右側は右向き, 半径の半分は光軸から45°
+    let edge = camera.direction(8.0, 4.0).unwrap();
+    assert!((edge - Vector3::new(1.0, 0.0, 0.0)).norm() < EPS, "{}", edge);
+    let half = camera.direction(4.0, 2.0).unwrap();
+    assert!((half - Vector3::new(0.0, 1.0, -1.0).normalize()).norm() < EPS, "{}", half);
+    // イメージサークルの外
+    assert!(camera.direction(0.1, 0.1).is_none());
+    let (_, g_term) = camera.sample(0, 0);
+    assert_eq!(g_term, 0.0);
+    let info = camera.info();
+    assert!((info.fov - 180.0).abs() < EPS && (info.focal_length - 2.0 / PI).abs() < EPS);
+  }
+
+  #[test]
+  fn orthographic_test() {
+    // z軸の負の方向を向いた幅4mの平行投影
+    let camera = OrthographicCamera::new(Matrix4::unit(), 4.0, [4, 2]);
+    let (left, _) = camera.sample(0, 0);
+    let (right, _) = camera.sample(3, 1);
+    // すべてのレイは平行で, 始点は写る範囲に収まる
+    assert!((left.value.direction - Vector3::new(0.0, 0.0, -1.0)).norm() < EPS);
+    assert!((right.value.direction - left.value.direction).norm() < EPS);
+    assert!(left.value.origin.x >= -2.0 && left.value.origin.x <= -1.0, "{}", left.value.origin);
+    assert!(left.value.origin.y >= 0.0 && left.value.origin.y <= 1.0, "{}", left.value.origin);
+    assert!(right.value.origin.x >= 1.0 && right.value.origin.x <= 2.0, "{}", right.value.origin);
+    assert!(right.value.origin.y >= -1.0 && right.value.origin.y <= 0.0, "{}", right.value.origin);
+  }
+}
@@ -1,27 +1,46 @@
 extern crate tobj;
 extern crate time;
 extern crate toml;
+extern crate rand;
 
 use std::sync::Arc;
 use camera::*;
 use math::vector::*;
 use math::matrix::*;
+use constant::*;
 use material::material::*;
+use texture::texture::*;
 use scene::Scene;
 use shape::SurfaceShape;
+use ray::{Ray, RayKind};
+use intersection::Intersection;
+use util::*;
+use std::ops::Range;
 use triangle::Triangle;
 use sphere::Sphere;
-use objects::Objects;
+use objects::{Objects, LightSelection, Visibility};
 use std::path::Path;
 use sky::*;
+use light::*;
+use ies::IesProfile;
+use portal::Portal;
 use std::fs::File;
 use std::collections::HashMap;
 use std::io::prelude::*;
-use scene_loader::{Config, HasTransform};
+use scene_loader::{Config, HasName, HasTransform, Vec3};
 use scene_loader::Mesh as CMesh;
 use scene_loader::Material as CMaterial;
 use scene_loader::Sky as CSky;
+use scene_loader::SkyFilter as CSkyFilter;
+use scene_loader::SkyLayout as CSkyLayout;
+use scene_loader::Light as CLight;
 use scene_loader::Camera as CCamera;
+use scene_loader::FisheyeProjection as CFisheyeProjection;
+use scene_loader::PanoramaLayout as CPanoramaLayout;
+use scene_loader::Texture as CTexture;
+use scene_loader::Color as CColor;
+use scene_loader::Scalar as CScalar;
+use scene_loader::Mapping as CMapping;
 
 pub struct Description {
   pub config: Config,
@@ -50,88 +69,238 @@ impl Description {
     match self.config.camera {
       CCamera::IdealPinhole { fov, .. } => box IdealPinholeCamera::new(matrix, fov, [width, height]),
       CCamera::ThinLens { fov, focus_distance, f_number, .. } => box LensCamera::new(matrix, fov, focus_distance, f_number, [width, height]),
-      CCamera::Omnidirectional { .. } => box OmnidirectionalCamera::new(matrix, [width, height]),
+      CCamera::Omnidirectional { layout, ipd, .. } => box OmnidirectionalCamera::new(
+        matrix,
+        match layout {
+          CPanoramaLayout::Equirectangular => PanoramaLayout::Equirectangular,
+          CPanoramaLayout::Cube => PanoramaLayout::Cube,
+        },
+        ipd,
+        [width, height],
+      ),
+      CCamera::Fisheye { fov, projection, .. } => box FisheyeCamera::new(
+        matrix,
+        fov,
+        match projection {
+          CFisheyeProjection::Equidistant => FisheyeProjection::Equidistant,
+          CFisheyeProjection::Equisolid => FisheyeProjection::Equisolid,
+          CFisheyeProjection::Stereographic => FisheyeProjection::Stereographic,
+        },
+        [width, height],
+      ),
+      CCamera::Orthographic { width: view_width, .. } => box OrthographicCamera::new(matrix, view_width, [width, height]),
     }
   }
 
   pub fn scene<'a>(&'a self) -> Scene<'a> {
+    // 空に付随する光源 (太陽)
+    let mut sky_lights = Vec::new();
     let sky = self.config.sky.as_ref().map( |v| match *v {
       CSky::Uniform { color } => box UniformSky {
         emission: color.into(),
       } as Box<Sky + Send + Sync>,
-      CSky::Ibl { ref path, longitude_offset } => box IBLSky::new(path, longitude_offset),
+      CSky::Ibl { ref path, longitude_offset, ref transform, filter, intensity, layout } => box IBLSky::new(
+        path,
+        longitude_offset,
+        transform.matrix(),
+        match filter {
+          CSkyFilter::Nearest => SkyFilter::Nearest,
+          CSkyFilter::Bilinear => SkyFilter::Bilinear,
+          CSkyFilter::Bicubic => SkyFilter::Bicubic,
+        },
+        intensity,
+        layout.map( |l| match l {
+          CSkyLayout::Equirectangular => SkyLayout::Equirectangular,
+          CSkyLayout::Cube => SkyLayout::Cube,
+        }),
+      ),
+      CSky::Simple { meridian, horizon } => box SimpleSky {
+        meridian: meridian.into(),
+        horizon: horizon.into(),
+      },
+      CSky::Preetham { sun_direction, turbidity, ground_albedo, intensity, sun } => {
+        let sky = PreethamSky::new(sun_direction.into(), turbidity, ground_albedo.into(), intensity);
+        if sun {
+          sky_lights.push(box SunLight {
+            direction: sky.sun_direction(),
+            radiance: sky.sun_radiance(),
+            cos_radius: SUN_ANGULAR_RADIUS.cos(),
+          } as Box<Light + Send + Sync>);
+        }
+        box sky
+      },
     } ).unwrap_or(box UniformSky {
       emission: Vector3::zero(),
     });
     println!("polygons: {}", self.loader.instances.len());
     let start_time = time::now();
-    let objects = Objects::new(&self.loader.instances);
+    let selection = match self.config.renderer.light_selection.as_ref().map( |v| v.as_str() ).unwrap_or("power") {
+      "power" => LightSelection::Power,
+      "bvh" => LightSelection::Bvh,
+      other => panic!(format!("Unknown light selection `{}`.", other)),
+    };
+    let objects = Objects::new(&self.loader.instances, self.loader.visibility.clone(), selection);
     let end_time = time::now();
     println!(
       "bvh construction: {}s",
       (end_time - start_time).num_milliseconds() as f32 / 1000.0
     );
+    self.loader.check_light_orientation(&objects);
+    let mut lights: Vec<Box<Light + Send + Sync>> = self.config.light().iter().filter_map( |l| match *l {
+      CLight::Area { .. } | CLight::Portal { .. } => None,
+      CLight::Point { position, intensity, ref ies, direction, tangent, .. } => Some(box PointLight {
+        position: position.into(),
+        intensity: intensity.into(),
+        profile: ies.as_ref().map( |path| Arc::new(IesProfile::load(path)) ),
+        axis: direction.map( |d| Vector3::from(d).normalize() ).unwrap_or(Vector3::new(0.0, -1.0, 0.0)),
+        reference: tangent.map( |t| t.into() ).unwrap_or(Vector3::zero()),
+      } as Box<Light + Send + Sync>),
+      CLight::Spot { position, direction, intensity, angle, falloff, ref ies, tangent, .. } => Some(box SpotLight::new(
+        position.into(),
+        direction.into(),
+        intensity.into(),
+        angle,
+        falloff,
+        ies.as_ref().map( |path| Arc::new(IesProfile::load(path)) ),
+        tangent.map( |t| t.into() ).unwrap_or(Vector3::zero()),
+      )),
+      CLight::Directional { direction, irradiance, .. } => Some(box DirectionalLight {
+        direction: Vector3::from(direction).normalize(),
+        irradiance: irradiance.into(),
+      }),
+    }).collect();
+    lights.append(&mut sky_lights);
+    // 空を重点サンプリングできる場合は開口部のサンプリングと半分ずつ混ぜる
+    let portal = if self.loader.portals.is_empty() {
+      None
+    } else {
+      let fraction = if sky.can_sample() { 0.5 } else { 1.0 };
+      Some(Portal::new(self.loader.portals.clone(), fraction))
+    };
     Scene {
       depth: self.config.renderer.depth.unwrap_or(5),
       depth_limit: self.config.renderer.depth_limit.unwrap_or(64),
       sky: sky,
       objects: objects,
+      lights: lights,
+      light_links: self.light_links(),
+      portal: portal,
       no_direct_emitter: self.config.renderer.no_direct_emitter.unwrap_or(false),
     }
   }
+
+  // 光源ごとに照らす物体 (include, exclude の指定がない光源はすべてを照らす)
+  fn light_links(&self) -> LightLinks {
+    let objects = self.config.object();
+    let find = |name: &String| objects.iter().position( |o| o.name == Some(name) )
+      .ok_or(format!("Object named `{}` is not found.", name)).unwrap();
+    let receivers = |light: &CLight| {
+      let (include, exclude) = light.link();
+      if include.is_none() && exclude.is_none() {
+        return None
+      }
+      let mut receivers = vec![include.is_none(); objects.len()];
+      for name in include.into_iter().flat_map( |v| v.iter() ) {
+        receivers[find(name)] = true;
+      }
+      for name in exclude.into_iter().flat_map( |v| v.iter() ) {
+        receivers[find(name)] = false;
+      }
+      Some(receivers)
+    };
+    // 物体として表されない光源はシーンの光源と同じ順に並べる
+    let lights = self.config.light().iter().filter( |l| match **l {
+      CLight::Area { .. } | CLight::Portal { .. } => false,
+      _ => true,
+    }).map( |l| receivers(l) ).collect();
+    let emitters = objects.iter().map( |o| self.config.light().iter().find( |l| match **l {
+      CLight::Area { ref object, .. } => o.name == Some(object),
+      _ => false,
+    }).and_then( |l| receivers(l) ) ).collect();
+    LightLinks {
+      object: self.loader.object.clone(),
+      lights: lights,
+      emitters: emitters,
+    }
+  }
 }
 
+type Textures = HashMap<String, Arc<Texture + Send + Sync>>;
+
 struct Loader {
   instances: Vec<Box<SurfaceShape + Send + Sync>>,
+  // 片面の光源の名前とinstancesでの範囲
+  lights: Vec<(String, Range<usize>)>,
+  // instancesごとの可視性
+  visibility: Vec<Visibility>,
+  // instancesごとのシーンの記述での物体の番号
+  object: Vec<usize>,
+  // 開口部の三角形
+  portals: Vec<[Vector3; 3]>,
 }
 
+// 光源の向きを調べるレイの数
+const LIGHT_ORIENTATION_SAMPLES: usize = 64;
+
 impl Loader {
   fn new(config: &Config) -> Loader {
     let mut instances = Vec::new();
+    let mut lights = Vec::new();
+    let mut visibility = Vec::new();
+    let mut object = Vec::new();
+    let mut portals = Vec::new();
+    // 画像は物体ごとに読み込み直さないように先に読み込んでおく
+    let images = config.texture().iter().filter_map( |t| match *t {
+      CTexture::Image { ref name, ref path, gamma } => {
+        Some((name.clone(), Arc::new(ImageTexture::load(path, gamma)) as Arc<Texture + Send + Sync>))
+      },
+      _ => None,
+    }).collect();
     let obj = Self::load_obj(config.object().iter().map( |o| o.mesh ).collect());
-    for o in config.object() {
+    for (index, o) in config.object().into_iter().enumerate() {
       let transform = o.matrix();
-      let emission = o.emission.unwrap_or(Vector3::zero());
-      let material = o.material.map( |m| {
-        match *m  {
-          CMaterial::Lambert { albedo, .. } => {
-            Arc::new(LambertianMaterial {
-              albedo: albedo.into(),
-              emission: emission,
-            }) as Arc<Material + Send + Sync>
-          },
-          CMaterial::Phong { reflectance, alpha, .. } => {
-            Arc::new(PhongMaterial {
-              reflectance: reflectance.into(),
-              roughness: alpha,
-            })
-          },
-          CMaterial::BlinnPhong { reflectance, alpha, .. } => {
-            Arc::new(BlinnPhongMaterial {
-              reflectance: reflectance.into(),
-              roughness: alpha,
-            })
-          },
-          CMaterial::Ggx { reflectance, roughness, ior, .. } => {
-            Arc::new(GGXMaterial {
-              reflectance: reflectance.into(),
-              roughness: roughness,
-              ior: ior,
-            })
-          },
-          CMaterial::IdealRefraction { reflectance, absorbtance, ior, .. } => {
-            Arc::new(IdealRefractionMaterial {
-              reflectance: reflectance.into(),
-              absorbtance: absorbtance,
-              ior: ior,
-            })
-          }
+      if o.portal {
+        // 開口部は形状だけを使い, シーンの物体にはしない
+        match *o.mesh {
+          CMesh::Obj { ref name, .. } => portals.append(&mut Self::portal(&obj.get(name).unwrap().0, &transform)),
+          CMesh::Sphere { ref name, .. } => panic!(format!("Portal `{}` must be an obj mesh.", name)),
         }
-      });
+        continue
+      }
+      let textures = Self::textures(config, &images, &transform);
+      // 物体に不透明度が指定されている場合はマテリアルを切り抜く
+      let opacity = o.opacity.map( |name| Self::find_texture(&textures, name) );
+      // 光源に指定されている場合はマテリアルの放射を置き換える
+      let emission = o.emission;
+      // 配光は光源の物体全体の光度なので面積で割って放射輝度にする
+      let profile = o.ies.map( |path| (Arc::new(IesProfile::load(path)), Self::area(o.mesh, &obj, &transform)) );
+      let wrap = |material: Arc<Material + Send + Sync>| {
+        let material = match emission {
+          Some(emission) => Arc::new(EmissiveMaterial {
+            base: material,
+            emission: Arc::new(ConstantTexture { value: emission }),
+            scale: 1.0,
+            two_sided: o.two_sided,
+            profile: profile.clone(),
+          }) as Arc<Material + Send + Sync>,
+          None => material,
+        };
+        match opacity {
+          Some(ref opacity) => Arc::new(MaskMaterial {
+            base: material,
+            opacity: opacity.clone(),
+          }) as Arc<Material + Send + Sync>,
+          None => material,
+        }
+      };
+      let material = o.material.map( |m| wrap(Self::material(config, &textures, m)) );
+      let start = instances.len();
       match *o.mesh {
-        CMesh::Obj { ref name, .. } => {
+        CMesh::Obj { ref name, ref path } => {
           let value = obj.get(name).unwrap();
-          let mut m = Self::obj(&value.0, &value.1, &transform, material, emission);
+          // MTLのテクスチャはobjファイルからの相対パス
+          let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+          let mut m = Self::obj(&value.0, &value.1, dir, &transform, material, &wrap);
           instances.append(&mut m);
         },
         CMesh::Sphere { ref radius, ref name } => {
@@ -141,12 +310,456 @@ impl Loader {
           instances.push(box sphere);
         },
       }
+      if o.emission.is_some() && !o.two_sided {
+        lights.push((o.name.cloned().unwrap_or_default(), start..instances.len()));
+      }
+      let count = instances.len() - start;
+      visibility.extend(vec![Visibility {
+        camera: o.visible_to_camera,
+        reflection: o.visible_in_reflections,
+        shadow: o.casts_shadow,
+      }; count]);
+      object.extend(vec![index; count]);
     }
     Loader {
       instances: instances,
+      lights: lights,
+      visibility: visibility,
+      object: object,
+      portals: portals,
+    }
+  }
+
+  // 片面の光源の表側に何もなく裏側にだけ物体がある場合は警告する (面の向きが反転している可能性が高い)
+  fn check_light_orientation(&self, objects: &Objects) {
+    for &(ref name, ref range) in &self.lights {
+      let shapes = &self.instances[range.clone()];
+      if shapes.is_empty() {
+        continue
+      }
+      // 光源自身との交差は数えない
+      let is_other = |i: &Intersection| !range.contains(&i.object);
+      let mut front = 0;
+      let mut back = 0;
+      for _ in 0..LIGHT_ORIENTATION_SAMPLES {
+        let shape = &shapes[rand::random::<usize>() % shapes.len()];
+        let point = shape.sample().value;
+        let (u, v) = point.normal.orthonormal_basis();
+        let d = Sampler::hemisphere_cos_importance();
+        let direction = u * d.x + v * d.y + point.normal * d.z;
+        let hit = |direction: Vector3| objects.intersect(&Ray {
+          origin: point.position,
+          direction: direction,
+          differential: None,
+        }, RayKind::Reflection).map( |i| is_other(&i) ).unwrap_or(false);
+        if hit(direction) { front += 1 }
+        if hit(-direction) { back += 1 }
+      }
+      if front == 0 && back > 0 {
+        println!(
+          "warning: area light `{}` faces away from all geometry. Its normals may be flipped; consider `two-sided = true`.",
+          name,
+        );
+      }
+    }
+  }
+
+  fn textures(config: &Config, images: &Textures, transform: &Matrix4) -> Textures {
+    config.texture().iter().map( |t| {
+      let texture = match *t {
+        CTexture::Image { ref name, .. } => images[name].clone(),
+        _ => Self::procedural(t, transform),
+      };
+      (t.name(), texture)
+    }).collect()
+  }
+
+  fn procedural(t: &CTexture, transform: &Matrix4) -> Arc<Texture + Send + Sync> {
+    let texture = |mapping: CMapping, scale: f32, colors: (Vec3, Vec3), pattern: Box<Pattern + Send + Sync>| {
+      let mapping = match mapping {
+        CMapping::Uv => Mapping::Uv,
+        CMapping::World => Mapping::World,
+        // 物体の座標変換の逆変換でローカル座標に戻す
+        CMapping::Object => Mapping::Object(transform.inverse()),
+        CMapping::Normal => Mapping::Normal,
+      };
+      Arc::new(ProceduralTexture {
+        mapping: mapping,
+        scale: scale,
+        pattern: pattern,
+        colors: (colors.0.into(), colors.1.into()),
+      }) as Arc<Texture + Send + Sync>
+    };
+    match *t {
+      CTexture::Image { .. } => unreachable!(),
+      CTexture::Checker { mapping, scale, colors, .. } => {
+        texture(mapping, scale, colors, box CheckerPattern)
+      },
+      CTexture::Noise { mapping, scale, colors, octaves, seed, .. } => {
+        texture(mapping, scale, colors, box NoisePattern {
+          perlin: Perlin::new(seed),
+          octaves: octaves.unwrap_or(4),
+        })
+      },
+      CTexture::Voronoi { mapping, scale, colors, seed, .. } => {
+        texture(mapping, scale, colors, box VoronoiPattern {
+          seed: seed,
+        })
+      },
+      CTexture::Gradient { mapping, scale, colors, axis, .. } => {
+        texture(mapping, scale, colors, box GradientPattern {
+          axis: axis.map( |v| v.into() ).unwrap_or(Vector3::new(1.0, 0.0, 0.0)),
+        })
+      },
+      CTexture::Wood { mapping, scale, colors, distortion, seed, .. } => {
+        texture(mapping, scale, colors, box WoodPattern {
+          perlin: Perlin::new(seed),
+          distortion: distortion,
+        })
+      },
+      CTexture::Marble { mapping, scale, colors, distortion, seed, .. } => {
+        texture(mapping, scale, colors, box MarblePattern {
+          perlin: Perlin::new(seed),
+          distortion: distortion,
+        })
+      },
+    }
+  }
+
+  fn find_texture(textures: &Textures, name: &str) -> Arc<Texture + Send + Sync> {
+    textures.get(name)
+      .ok_or(format!("Texture named `{}` is not found.", name))
+      .unwrap()
+      .clone()
+  }
+
+  fn color(textures: &Textures, c: &CColor) -> Arc<Texture + Send + Sync> {
+    match *c {
+      CColor::Value(v) => Arc::new(ConstantTexture { value: v.into() }),
+      CColor::Texture(ref name) => Self::find_texture(textures, name),
+    }
+  }
+
+  fn scalar(textures: &Textures, s: &CScalar) -> Arc<Texture + Send + Sync> {
+    match *s {
+      CScalar::Value(v) => Arc::new(ConstantTexture { value: Vector3::new(v, v, v) }),
+      CScalar::Texture(ref name) => Self::find_texture(textures, name),
+    }
+  }
+
+  fn material(config: &Config, textures: &Textures, m: &CMaterial) -> Arc<Material + Send + Sync> {
+    Self::material_in(config, textures, m, &mut Vec::new())
+  }
+
+  // 参照しているマテリアルを読む (pathはたどってきたマテリアルの名前)
+  fn base_material(config: &Config, textures: &Textures, name: &str, path: &mut Vec<String>) -> Arc<Material + Send + Sync> {
+    let base = config.find_material_by_name(name).unwrap();
+    Self::material_in(config, textures, base, path)
+  }
+
+  fn material_in(config: &Config, textures: &Textures, m: &CMaterial, path: &mut Vec<String>) -> Arc<Material + Send + Sync> {
+    // 参照が循環していると再帰が終わらない
+    let name = m.name();
+    if let Some(start) = path.iter().position( |v| v == &name ) {
+      panic!("Material reference cycle `{} -> {}` is found.", path[start..].join(" -> "), name);
+    }
+    path.push(name);
+    let material = Self::material_of(config, textures, m, path);
+    path.pop();
+    material
+  }
+
+  fn material_of(config: &Config, textures: &Textures, m: &CMaterial, path: &mut Vec<String>) -> Arc<Material + Send + Sync> {
+    match *m {
+      CMaterial::Lambert { ref albedo, .. } => {
+        Arc::new(LambertianMaterial {
+          albedo: Self::color(textures, albedo),
+          emission: Vector3::zero(),
+        })
+      },
+      CMaterial::Phong { ref reflectance, alpha, .. } => {
+        Arc::new(PhongMaterial {
+          reflectance: Self::color(textures, reflectance),
+          roughness: alpha,
+        })
+      },
+      CMaterial::BlinnPhong { ref reflectance, alpha, .. } => {
+        Arc::new(BlinnPhongMaterial {
+          reflectance: Self::color(textures, reflectance),
+          roughness: alpha,
+        })
+      },
+      CMaterial::Ggx { ref reflectance, ref roughness, ior, .. } => {
+        Arc::new(GGXMaterial {
+          reflectance: Self::color(textures, reflectance),
+          roughness: Self::scalar(textures, roughness),
+          ior: ior,
+        })
+      },
+      CMaterial::IdealRefraction { reflectance, absorbtance, ior, .. } => {
+        Arc::new(IdealRefractionMaterial {
+          reflectance: reflectance.into(),
+          absorbtance: absorbtance,
+          ior: ior,
+        })
+      },
+      CMaterial::Coated { ref base, ior, roughness, tint, .. } => {
+        Arc::new(CoatedMaterial::new(
+          Self::base_material(config, textures, base, path),
+          ior,
+          roughness,
+          tint.map( |v| v.into() ).unwrap_or(Vector3::new(1.0, 1.0, 1.0)),
+        ))
+      },
+      CMaterial::Mix { ref materials, ref weight, .. } => {
+        let a = Self::base_material(config, textures, &materials.0, path);
+        let b = Self::base_material(config, textures, &materials.1, path);
+        Arc::new(MixMaterial {
+          materials: (a, b),
+          weight: Self::scalar(textures, weight),
+        })
+      },
+      CMaterial::ThinDielectric { reflectance, tint, ior, .. } => {
+        Arc::new(ThinDielectricMaterial {
+          reflectance: reflectance.into(),
+          tint: tint.map( |v| v.into() ).unwrap_or(Vector3::new(1.0, 1.0, 1.0)),
+          ior: ior,
+        })
+      },
+      CMaterial::Translucent { albedo, transmission, .. } => {
+        // 反射と透過をあわせてエネルギーが増えないようにする
+        let sum = Vector3::from(albedo) + Vector3::from(transmission);
+        if sum.x > 1.0 || sum.y > 1.0 || sum.z > 1.0 {
+          panic!("Translucent material `{}` must have albedo + transmission <= 1, but {} is given.", m.name(), sum);
+        }
+        Arc::new(TranslucentMaterial {
+          albedo: albedo.into(),
+          transmission: transmission.into(),
+        })
+      },
+      CMaterial::Subsurface { albedo, mean_free_path, ior, .. } => {
+        Arc::new(SubsurfaceMaterial::new(albedo.into(), mean_free_path.into(), ior))
+      },
+      CMaterial::Measured { ref path, .. } => {
+        Arc::new(MeasuredMaterial::load(path))
+      },
+      CMaterial::NormalMap { ref base, ref texture, strength, .. } => {
+        Arc::new(NormalMapMaterial {
+          base: Self::base_material(config, textures, base, path),
+          map: Self::find_texture(textures, texture),
+          strength: strength,
+        })
+      },
+      CMaterial::Bump { ref base, ref texture, scale, .. } => {
+        Arc::new(BumpMaterial {
+          base: Self::base_material(config, textures, base, path),
+          map: Self::find_texture(textures, texture),
+          scale: scale,
+        })
+      },
+      CMaterial::Mask { ref base, ref texture, .. } => {
+        Arc::new(MaskMaterial {
+          base: Self::base_material(config, textures, base, path),
+          opacity: Self::find_texture(textures, texture),
+        })
+      },
+      CMaterial::Emissive { ref base, ref emission, scale, two_sided, .. } => {
+        Arc::new(EmissiveMaterial {
+          base: Self::base_material(config, textures, base, path),
+          emission: Self::color(textures, emission),
+          scale: scale,
+          two_sided: two_sided,
+          profile: None,
+        })
+      },
     }
   }
 
+  fn mtl(v: &tobj::Material, dir: &Path, images: &mut HashMap<(String, bool), Option<Arc<ImageTexture>>>) -> Arc<Material + Send + Sync> {
+    // 画像はMTL間で共有する (線形な値として読むかどうかも区別する)
+    // 見つからない画像は警告してテクスチャなしとして扱う
+    let mut image = |param: &str, linear: bool| {
+      let (path, _) = Self::mtl_texture_option(param);
+      let path = dir.join(path).to_string_lossy().into_owned();
+      images.entry((path.clone(), linear)).or_insert_with( || {
+        if Path::new(&path).is_file() {
+          Some(Arc::new(ImageTexture::load(&path, if linear { Some(1.0) } else { None })))
+        } else {
+          println!("warning: texture `{}` referenced by material `{}` is not found. It is ignored.", path, v.name);
+          None
+        }
+      }).clone()
+    };
+    let param = |key: &str| v.unknown_param.get(key).map( |p| p.as_str() ).filter( |p| !p.is_empty() );
+    let color = |value: Vector3, texture: &str, image: &mut FnMut(&str, bool) -> Option<Arc<ImageTexture>>| {
+      match if texture.is_empty() { None } else { image(texture, false) } {
+        None => Arc::new(ConstantTexture { value: value }) as Arc<Texture + Send + Sync>,
+        // 値が指定されていない場合はテクスチャの色をそのままつかう
+        Some(texture) => if value.sqr_norm() == 0.0 {
+          texture as Arc<Texture + Send + Sync>
+        } else {
+          Arc::new(ScaledTexture { texture: texture, scale: value })
+        },
+      }
+    };
+    let max = |v: Vector3| v.x.max(v.y).max(v.z);
+    let kd: Vector3 = v.diffuse[..].into();
+    let ks: Vector3 = v.specular[..].into();
+    let material = match v.illumination_model {
+      // 屈折をともなう照明モデルは誘電体
+      Some(4) | Some(6) | Some(7) | Some(9) => {
+        Arc::new(IdealRefractionMaterial {
+          reflectance: param("Tf").map( |p| Self::mtl_vector(p) ).unwrap_or(Vector3::new(1.0, 1.0, 1.0)),
+          absorbtance: 0.0,
+          ior: if v.optical_density > 1.0 { v.optical_density } else { 1.5 },
+        }) as Arc<Material + Send + Sync>
+      },
+      _ if max(ks) > 0.0 && v.shininess > 0.0 => {
+        // 拡散反射と光沢反射をそれぞれの反射率の比で混ぜる
+        let w = max(ks) / (max(kd) + max(ks));
+        let specular = Arc::new(BlinnPhongMaterial {
+          reflectance: Arc::new(ScaledTexture {
+            texture: color(ks, &v.specular_texture, &mut image),
+            scale: Vector3::new(1.0, 1.0, 1.0) / w,
+          }),
+          roughness: v.shininess,
+        }) as Arc<Material + Send + Sync>;
+        if w >= 1.0 {
+          specular
+        } else {
+          let diffuse = Arc::new(LambertianMaterial {
+            albedo: Arc::new(ScaledTexture {
+              texture: color(kd, &v.diffuse_texture, &mut image),
+              scale: Vector3::new(1.0, 1.0, 1.0) / (1.0 - w),
+            }),
+            emission: Vector3::zero(),
+          });
+          Arc::new(MixMaterial {
+            materials: (diffuse, specular),
+            weight: Arc::new(ConstantTexture { value: Vector3::new(w, w, w) }),
+          })
+        }
+      },
+      _ => {
+        Arc::new(LambertianMaterial {
+          albedo: color(kd, &v.diffuse_texture, &mut image),
+          emission: Vector3::zero(),
+        })
+      },
+    };
+    // 放射 ([[light]] の指定があればさらに外側で置き換えられる)
+    let ke = param("Ke").map( |p| Self::mtl_vector(p) ).unwrap_or(Vector3::zero());
+    let material = match param("map_Ke").filter( |&map_ke| image(map_ke, false).is_some() ) {
+      Some(map_ke) => Arc::new(EmissiveMaterial {
+        base: material,
+        emission: color(ke, map_ke, &mut image),
+        scale: 1.0,
+        two_sided: false,
+        profile: None,
+      }) as Arc<Material + Send + Sync>,
+      None if ke.sqr_norm() > 0.0 => Arc::new(EmissiveMaterial {
+        base: material,
+        emission: Arc::new(ConstantTexture { value: ke }),
+        scale: 1.0,
+        two_sided: false,
+        profile: None,
+      }),
+      None => material,
+    };
+    // 法線マップ, バンプマップ
+    let norm = param("norm").and_then( |norm| image(norm, true) );
+    let bump = param("map_Bump").or(param("map_bump")).or(param("bump"))
+      .and_then( |bump| image(bump, true).map( |map| (bump, map) ) );
+    let material = match (norm, bump) {
+      (Some(map), _) => {
+        Arc::new(NormalMapMaterial {
+          base: material,
+          map: map,
+          strength: 1.0,
+        }) as Arc<Material + Send + Sync>
+      },
+      (None, Some((bump, map))) => {
+        let average = map.average();
+        // 法線マップがバンプマップとして書き出されていることが多いので平均の色で判別する
+        if average.z > 0.7 && (average.x - 0.5).abs() < 0.15 && (average.y - 0.5).abs() < 0.15 {
+          Arc::new(NormalMapMaterial {
+            base: material,
+            map: map,
+            strength: 1.0,
+          }) as Arc<Material + Send + Sync>
+        } else {
+          // MTLの高さには単位がないので1をシーンの1/100とみなす
+          let (_, bm) = Self::mtl_texture_option(bump);
+          Arc::new(BumpMaterial {
+            base: material,
+            map: map,
+            scale: bm.unwrap_or(1.0) * 0.01,
+          })
+        }
+      },
+      (None, None) => material,
+    };
+    // 透明度 (誘電体は屈折で扱う)
+    let dissolve = match param("Tr") {
+      Some(tr) if v.dissolve == 1.0 => 1.0 - Self::mtl_vector(tr).x,
+      _ => v.dissolve,
+    };
+    let is_dielectric = match v.illumination_model { Some(4) | Some(6) | Some(7) | Some(9) => true, _ => false };
+    let dissolve_texture = if v.dissolve_texture.is_empty() { None } else { image(&v.dissolve_texture, true) };
+    if is_dielectric || (dissolve >= 1.0 && dissolve_texture.is_none()) {
+      return material
+    }
+    let opacity = match dissolve_texture {
+      None => Arc::new(ConstantTexture { value: Vector3::new(dissolve, dissolve, dissolve) }) as Arc<Texture + Send + Sync>,
+      Some(texture) => Arc::new(ScaledTexture {
+        texture: texture,
+        scale: Vector3::new(dissolve, dissolve, dissolve),
+      }),
+    };
+    Arc::new(MaskMaterial {
+      base: material,
+      opacity: opacity,
+    })
+  }
+
+  // "r g b" または "v" -> 色
+  fn mtl_vector(param: &str) -> Vector3 {
+    let v = param.split_whitespace().map( |s|
+      s.parse::<f32>().expect(&format!("Invalid MTL parameter `{}`.", param))
+    ).collect::<Vec<_>>();
+    match v.len() {
+      1 => Vector3::new(v[0], v[0], v[0]),
+      3 => Vector3::new(v[0], v[1], v[2]),
+      _ => panic!(format!("Invalid MTL parameter `{}`.", param)),
+    }
+  }
+
+  // "-bm 0.5 -s 1 1 1 file.png" -> (ファイル名, バンプの倍率)
+  fn mtl_texture_option(param: &str) -> (String, Option<f32>) {
+    let mut tokens = param.split_whitespace().peekable();
+    let mut bm = None;
+    while let Some(&option) = tokens.peek() {
+      // オプションの引数の数 (必須の数, 省略できる数値の引数を含めた最大の数)
+      let (required, count) = match option {
+        "-bm" | "-boost" | "-texres" | "-imfchan" | "-blendu" | "-blendv" | "-clamp" | "-cc" | "-type" => (1, 1),
+        "-mm" => (2, 2),
+        // u [v [w]]
+        "-o" | "-s" | "-t" => (1, 3),
+        _ => break,
+      };
+      tokens.next();
+      let mut args = (0..required).filter_map( |_| tokens.next() ).collect::<Vec<_>>();
+      while args.len() < count && tokens.peek().map( |v| v.parse::<f32>().is_ok() ).unwrap_or(false) {
+        args.push(tokens.next().unwrap());
+      }
+      if option == "-bm" {
+        bm = args.get(0).and_then( |v| v.parse().ok() );
+      }
+    }
+    // ファイル名は空白を含むことがある
+    (tokens.collect::<Vec<_>>().join(" "), bm)
+  }
+
   fn load_obj(mesh: Vec<&CMesh>) -> HashMap<String, (Vec<tobj::Model>, Vec<tobj::Material>)> {
     let mut obj = HashMap::new();
     for m in mesh {
@@ -161,16 +774,49 @@ impl Loader {
     obj
   }
 
-  fn obj(models: &Vec<tobj::Model>, materials: &Vec<tobj::Material>, transform: &Matrix4, default_material: Option<Arc<Material + Sync + Send>>, emission: Vector3) -> Vec<Box<SurfaceShape + Sync + Send>> {
-    let material = materials.iter().map( |v|
-      Arc::new(LambertianMaterial {
-        emission: emission,
-        albedo: v.diffuse[..].into(),
-      }) as Arc<Material + Sync + Send>
-    ).collect::<Vec<_>>();
+  // 物体の表面積
+  fn area(mesh: &CMesh, obj: &HashMap<String, (Vec<tobj::Model>, Vec<tobj::Material>)>, transform: &Matrix4) -> f32 {
+    match *mesh {
+      CMesh::Obj { ref name, .. } => Self::portal(&obj.get(name).unwrap().0, transform).iter().map( |t|
+        (t[1] - t[0]).cross(t[2] - t[0]).norm() / 2.0
+      ).sum(),
+      CMesh::Sphere { radius, .. } => 4.0 * PI * radius * radius,
+    }
+  }
+
+  fn portal(models: &Vec<tobj::Model>, transform: &Matrix4) -> Vec<[Vector3; 3]> {
+    models.iter().flat_map( |m| {
+      let position = |index: u32| {
+        let index = index as usize;
+        transform * Vector3::new(
+          m.mesh.positions[index * 3],
+          m.mesh.positions[index * 3 + 1],
+          m.mesh.positions[index * 3 + 2],
+        )
+      };
+      m.mesh.indices.chunks(3).map( |f| [position(f[0]), position(f[1]), position(f[2])] ).collect::<Vec<_>>()
+    }).collect()
+  }
+
+  fn obj<F>(models: &Vec<tobj::Model>, materials: &Vec<tobj::Material>, dir: &Path, transform: &Matrix4, default_material: Option<Arc<Material + Sync + Send>>, wrap: &F) -> Vec<Box<SurfaceShape + Sync + Send>>
+    where F: Fn(Arc<Material + Send + Sync>) -> Arc<Material + Send + Sync>
+  {
+    // マテリアルが指定されている場合はMTLを読まない
+    let mut images = HashMap::new();
+    let material = if default_material.is_some() {
+      Vec::new()
+    } else {
+      materials.iter().map( |v| wrap(Self::mtl(v, dir, &mut images)) ).collect::<Vec<_>>()
+    };
     let mut instances: Vec<Box<SurfaceShape + Sync + Send>> = Vec::with_capacity(
       models.iter().map( |m| m.mesh.indices.len() / 3).sum()
     );
+    // 法線は座標変換の逆転置行列で変換する
+    let inverse = transform.inverse();
+    let transform_normal = |n: Vector3| {
+      let n = Vector4::new(n.x, n.y, n.z, 0.0);
+      Vector3::new(inverse.col(0).dot(n), inverse.col(1).dot(n), inverse.col(2).dot(n)).normalize()
+    };
     for m in models {
       let mat = match default_material.clone() {
         None => m.mesh.material_id
@@ -179,20 +825,209 @@ impl Loader {
           .unwrap(),
         Some(v) => v,
       };
+      let has_texcoords = !m.mesh.texcoords.is_empty();
+      let has_normals = !m.mesh.normals.is_empty();
+      let mut triangles = Vec::with_capacity(m.mesh.indices.len() / 3);
+      // 頂点ごとの接線 (接する面の接線の面積による重み付き和)
+      let mut tangents = vec![Vector3::zero(); m.mesh.positions.len() / 3];
       for f in 0..m.mesh.indices.len() / 3 {
         let mut polygon = [Vector3::zero(); 3];
+        let mut texcoords = [(0.0, 0.0); 3];
+        let mut normals = [Vector3::zero(); 3];
         for i in 0..3 {
-          let index: usize = f * 3 + i;
+          let index = m.mesh.indices[f * 3 + i] as usize;
+          if has_texcoords {
+            texcoords[i] = (
+              m.mesh.texcoords[index * 2],
+              m.mesh.texcoords[index * 2 + 1],
+            );
+          }
+          if has_normals {
+            normals[i] = transform_normal(m.mesh.normals[index * 3 .. index * 3 + 3].into());
+          }
           let potition = Vector3::new(
-            m.mesh.positions[m.mesh.indices[index] as usize * 3],
-            m.mesh.positions[m.mesh.indices[index] as usize * 3 + 1],
-            m.mesh.positions[m.mesh.indices[index] as usize * 3 + 2],
+            m.mesh.positions[index * 3],
+            m.mesh.positions[index * 3 + 1],
+            m.mesh.positions[index * 3 + 2],
           );
           polygon[i] = transform * potition;
         }
-        instances.push(box Triangle::new(polygon[0], polygon[1], polygon[2], mat.clone()));
+        let mut triangle = Triangle::new(polygon[0], polygon[1], polygon[2], mat.clone());
+        if has_texcoords {
+          triangle.set_texcoords(texcoords);
+          let tangent = triangle.dpdu().normalize() * triangle.area;
+          for i in 0..3 {
+            let index = m.mesh.indices[f * 3 + i] as usize;
+            tangents[index] = tangents[index] + tangent;
+          }
+        }
+        if has_normals {
+          triangle.set_normals(normals);
+        }
+        triangles.push(triangle);
+      }
+      for (f, mut triangle) in triangles.into_iter().enumerate() {
+        if has_texcoords {
+          let index = |i: usize| m.mesh.indices[f * 3 + i] as usize;
+          triangle.set_tangents([tangents[index(0)], tangents[index(1)], tangents[index(2)]]);
+        }
+        instances.push(box triangle);
       }
     }
     instances
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use intersection::SurfacePoint;
+  use constant::*;
+
+  fn materials(toml_str: &str) -> Config {
+    toml::from_str(toml_str).unwrap()
+  }
+
+  #[test]
+  #[should_panic(expected = "Material reference cycle `a -> b -> a` is found.")]
+  fn material_cycle_test() {
+    let config = materials(r#"
+      [renderer]
+      samples = 1
+      [film]
+      resolution = [1, 1]
+      output = "hdr"
+      [camera]
+      type = "ideal-pinhole"
+      fov = 60
+      [[material]]
+      name = "a"
+      type = "coated"
+      base = "b"
+      ior = 1.5
+      roughness = 0.0
+      [[material]]
+      name = "b"
+      type = "bump"
+      base = "a"
+      texture = "t"
+      scale = 1.0
+    "#);
+    let m = config.find_material_by_name("a").unwrap();
+    Loader::material(&config, &HashMap::new(), m);
+  }
+
+  #[test]
+  #[should_panic(expected = "Translucent material `leaf` must have albedo + transmission <= 1")]
+  fn translucent_energy_test() {
+    let config = materials(r#"
+      [renderer]
+      samples = 1
+      [film]
+      resolution = [1, 1]
+      output = "hdr"
+      [camera]
+      type = "ideal-pinhole"
+      fov = 60
+      [[material]]
+      name = "leaf"
+      type = "translucent"
+      albedo = [0.7, 0.7, 0.7]
+      transmission = [0.5, 0.2, 0.2]
+    "#);
+    let m = config.find_material_by_name("leaf").unwrap();
+    Loader::material(&config, &HashMap::new(), m);
+  }
+
+  #[test]
+  fn mtl_energy_test() {
+    // 反射率の和が1を超えても成分を選んでロシアンルーレットで打ち切った推定は正しい反射率に収束する
+    let mut images = HashMap::new();
+    let point = SurfacePoint::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0), (0.0, 0.0));
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    let out_ = Vector3::new(0.0, 0.0, 1.0);
+    let mut m = tobj::Material::empty();
+    m.diffuse = [0.8, 0.8, 0.8];
+    m.specular = [0.5, 0.5, 0.5];
+    m.shininess = 10.0;
+    let material = Loader::mtl(&m, Path::new(""), &mut images);
+    let specular = BlinnPhongMaterial {
+      reflectance: Arc::new(ConstantTexture { value: Vector3::new(0.5, 0.5, 0.5) }),
+      roughness: 10.0,
+    };
+    let count = 200000;
+    let estimate = |lobe: &Material| {
+      let sample = lobe.sample(out_, n, &point);
+      if sample.pdf <= 0.0 {
+        return 0.0
+      }
+      lobe.brdf(out_, sample.value, n, &point).x * sample.value.dot(n).max(0.0) / sample.pdf
+    };
+    let albedo = (0..count).map( |_| {
+      let lobe = material.select(&point).unwrap();
+      let continue_rr_prob = lobe.weight().min(1.0);
+      if rand::random::<f32>() >= continue_rr_prob {
+        return 0.0
+      }
+      estimate(&*lobe) / continue_rr_prob
+    }).sum::<f32>() / count as f32;
+    let expected = 0.8 + (0..count).map( |_| estimate(&specular) ).sum::<f32>() / count as f32;
+    assert!((albedo - expected).abs() < 0.02, "{} {}", albedo, expected);
+  }
+
+  #[test]
+  fn mtl_texture_option_test() {
+    assert_eq!(Loader::mtl_texture_option("wood.png"), ("wood.png".to_string(), None));
+    assert_eq!(
+      Loader::mtl_texture_option("-bm 0.5 -s 1 1 1 my texture.png"),
+      ("my texture.png".to_string(), Some(0.5)),
+    );
+    // 省略できる引数は数値のときだけ読む
+    assert_eq!(Loader::mtl_texture_option("-s 2 wood.png"), ("wood.png".to_string(), None));
+    assert_eq!(Loader::mtl_texture_option("-o 0.5 0.5 -bm 2 wood.png"), ("wood.png".to_string(), Some(2.0)));
+  }
+
+  #[test]
+  fn mtl_translation_test() {
+    let mut images = HashMap::new();
+    let point = SurfacePoint::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0), (0.0, 0.0));
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    let out_ = Vector3::new(0.0, 0.0, 1.0);
+    // Keは放射になる
+    let mut m = tobj::Material::empty();
+    m.diffuse = [0.5, 0.5, 0.5];
+    m.unknown_param.insert("Ke".to_string(), "1 2 3".to_string());
+    let material = Loader::mtl(&m, Path::new(""), &mut images);
+    assert!((material.emission(&point) - Vector3::new(1.0, 2.0, 3.0)).norm() < EPS);
+    // 光沢反射を混ぜても拡散反射の寄与は変わらない
+    let mut m = tobj::Material::empty();
+    m.diffuse = [0.5, 0.5, 0.5];
+    m.specular = [0.5, 0.5, 0.5];
+    m.shininess = 100.0;
+    let material = Loader::mtl(&m, Path::new(""), &mut images);
+    let in_ = Vector3::new(1.0, 0.0, 0.1).normalize();
+    let brdf = material.brdf(out_, in_, n, &point);
+    assert!((brdf.x - 0.5 / PI).abs() < 1e-3, "{}", brdf);
+    // 屈折の照明モデルは誘電体, dは不透明度になる
+    let mut m = tobj::Material::empty();
+    m.illumination_model = Some(7);
+    m.optical_density = 1.33;
+    m.unknown_param.insert("Ke".to_string(), "1 1 1".to_string());
+    let material = Loader::mtl(&m, Path::new(""), &mut images);
+    assert!(material.is_delta(out_, -out_, n));
+    assert!((material.emission(&point) - Vector3::new(1.0, 1.0, 1.0)).norm() < EPS);
+    let mut m = tobj::Material::empty();
+    m.dissolve = 0.25;
+    let material = Loader::mtl(&m, Path::new(""), &mut images);
+    assert_eq!(material.opacity(&point), 0.25);
+    // 見つからない画像は無視して定数の色をつかう
+    let mut m = tobj::Material::empty();
+    m.diffuse = [0.5, 0.5, 0.5];
+    m.diffuse_texture = "missing.png".to_string();
+    m.unknown_param.insert("map_Bump".to_string(), "missing_bump.png".to_string());
+    let material = Loader::mtl(&m, Path::new(""), &mut images);
+    let brdf = material.brdf(out_, out_, n, &point);
+    assert!((brdf.x - 0.5 / PI).abs() < EPS, "{}", brdf);
+    assert!((material.normal(&point) - n).norm() < EPS);
+  }
+}
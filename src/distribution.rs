@@ -0,0 +1,253 @@
+use sample::Sample;
+
+// 区分的に一定な1次元の確率分布
+pub struct Distribution1D {
+  func: Vec<f32>,
+  cdf: Vec<f32>,
+  // 関数の[0, 1]での積分値
+  pub integral: f32,
+}
+
+impl Distribution1D {
+  pub fn new(func: Vec<f32>) -> Distribution1D {
+    let n = func.len();
+    let mut cdf = Vec::with_capacity(n + 1);
+    cdf.push(0.0);
+    for i in 0..n {
+      let prev = cdf[i];
+      cdf.push(prev + func[i].max(0.0) / n as f32);
+    }
+    let integral = cdf[n];
+    if integral > 0.0 {
+      for v in cdf.iter_mut() {
+        *v /= integral;
+      }
+    } else {
+      // 関数がすべて0のときは一様分布
+      for (i, v) in cdf.iter_mut().enumerate() {
+        *v = i as f32 / n as f32;
+      }
+    }
+    Distribution1D {
+      func: func,
+      cdf: cdf,
+      integral: integral,
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.func.len()
+  }
+
+  fn offset(&self, u: f32) -> usize {
+    // cdf[i] <= u < cdf[i + 1] となるiを二分探索
+    let mut lo = 0;
+    let mut hi = self.func.len();
+    while hi - lo > 1 {
+      let mid = (lo + hi) / 2;
+      if self.cdf[mid] <= u {
+        lo = mid;
+      } else {
+        hi = mid;
+      }
+    }
+    lo
+  }
+
+  // 0 <= u < 1 の一様乱数から区間[0, 1)上の点をサンプリング
+  pub fn sample_continuous(&self, u: f32) -> Sample<f32> {
+    let i = self.offset(u);
+    let width = self.cdf[i + 1] - self.cdf[i];
+    let du = if width > 0.0 { (u - self.cdf[i]) / width } else { 0.0 };
+    Sample {
+      value: ((i as f32 + du) / self.len() as f32).min(1.0 - 1e-6),
+      pdf: self.pdf(i),
+    }
+  }
+
+  // 0 <= u < 1 の一様乱数から要素をひとつ選ぶ (確率密度は確率質量)
+  pub fn sample_discrete(&self, u: f32) -> Sample<usize> {
+    let i = self.offset(u);
+    Sample {
+      value: i,
+      pdf: self.cdf[i + 1] - self.cdf[i],
+    }
+  }
+
+  // i番目の区間の確率密度
+  pub fn pdf(&self, i: usize) -> f32 {
+    if self.integral > 0.0 {
+      self.func[i].max(0.0) / self.integral
+    } else {
+      1.0
+    }
+  }
+
+  // 区間[0, 1)上の点xでの確率密度
+  pub fn pdf_at(&self, x: f32) -> f32 {
+    let i = ((x * self.len() as f32) as usize).min(self.len() - 1);
+    self.pdf(i)
+  }
+}
+
+// 重みに比例して要素を定数時間で選ぶエイリアス法のテーブル [Vose 1991]
+pub struct AliasTable {
+  // 各区画で自身を選ぶ確率
+  threshold: Vec<f32>,
+  // 自身を選ばなかったときの要素
+  alias: Vec<usize>,
+  // 各要素の確率質量
+  pmf: Vec<f32>,
+}
+
+impl AliasTable {
+  pub fn new(weight: Vec<f32>) -> AliasTable {
+    let n = weight.len();
+    let sum = weight.iter().map( |w| w.max(0.0) ).sum::<f32>();
+    // 重みがすべて0のときは一様
+    let pmf = weight.iter().map( |w| if sum > 0.0 { w.max(0.0) / sum } else { 1.0 / n as f32 } ).collect::<Vec<_>>();
+    let mut scaled = pmf.iter().map( |p| p * n as f32 ).collect::<Vec<_>>();
+    let mut threshold = vec![1.0; n];
+    let mut alias = (0..n).collect::<Vec<_>>();
+    let (mut small, mut large): (Vec<usize>, Vec<usize>) = (0..n).partition( |&i| scaled[i] < 1.0 );
+    // 1に満たない区画を1を超える要素で埋める
+    while let (Some(s), Some(&l)) = (small.pop(), large.last()) {
+      threshold[s] = scaled[s];
+      alias[s] = l;
+      scaled[l] -= 1.0 - scaled[s];
+      if scaled[l] < 1.0 {
+        large.pop();
+        small.push(l);
+      }
+    }
+    AliasTable {
+      threshold: threshold,
+      alias: alias,
+      pmf: pmf,
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.pmf.len()
+  }
+
+  // 0 <= u < 1 の一様乱数から要素をひとつ選ぶ (確率密度は確率質量)
+  pub fn sample(&self, u: f32) -> Sample<usize> {
+    let n = self.len();
+    let x = u * n as f32;
+    let i = (x as usize).min(n - 1);
+    let value = if x - (i as f32) < self.threshold[i] { i } else { self.alias[i] };
+    Sample {
+      value: value,
+      pdf: self.pmf[value],
+    }
+  }
+
+  pub fn pmf(&self, i: usize) -> f32 {
+    self.pmf[i]
+  }
+}
+
+// 区分的に一定な2次元の確率分布 (周辺分布と条件付き分布に分解する)
+pub struct Distribution2D {
+  // 行ごとの条件付き分布
+  conditional: Vec<Distribution1D>,
+  // 行の周辺分布
+  marginal: Distribution1D,
+}
+
+impl Distribution2D {
+  // func は width x height の行優先の値
+  pub fn new(func: Vec<f32>, width: usize, height: usize) -> Distribution2D {
+    assert!(func.len() == width * height);
+    let conditional = func.chunks(width).map( |row| Distribution1D::new(row.to_vec()) ).collect::<Vec<_>>();
+    let marginal = Distribution1D::new(conditional.iter().map( |d| d.integral ).collect());
+    Distribution2D {
+      conditional: conditional,
+      marginal: marginal,
+    }
+  }
+
+  // 0 <= u < 1 の一様乱数2つから [0, 1)^2 上の点をサンプリング
+  pub fn sample_continuous(&self, u0: f32, u1: f32) -> Sample<(f32, f32)> {
+    let v = self.marginal.sample_continuous(u1);
+    let row = ((v.value * self.conditional.len() as f32) as usize).min(self.conditional.len() - 1);
+    let u = self.conditional[row].sample_continuous(u0);
+    Sample {
+      value: (u.value, v.value),
+      pdf: u.pdf * v.pdf,
+    }
+  }
+
+  // [0, 1)^2 上の点での確率密度
+  pub fn pdf_at(&self, u: f32, v: f32) -> f32 {
+    let row = ((v * self.conditional.len() as f32) as usize).min(self.conditional.len() - 1);
+    self.conditional[row].pdf_at(u) * self.marginal.pdf_at(v)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use constant::*;
+
+  #[test]
+  fn sample_continuous_test() {
+    let d = Distribution1D::new(vec![1.0, 3.0, 0.0, 4.0]);
+    let s = d.sample_continuous(0.0);
+    assert!(s.value.abs() < EPS);
+    // 2番目の区間は累積分布で1/8から1/2
+    let s = d.sample_continuous(0.3);
+    assert!(s.value >= 0.25 && s.value < 0.5, "{}", s.value);
+    assert!((s.pdf - 3.0 / 2.0).abs() < EPS, "{}", s.pdf);
+    // 値が0の区間はサンプリングされない
+    for i in 0..100 {
+      let s = d.sample_continuous(i as f32 / 100.0);
+      assert!(s.value < 0.5 || s.value >= 0.75, "{}", s.value);
+    }
+  }
+
+  #[test]
+  fn sample_discrete_test() {
+    let d = Distribution1D::new(vec![1.0, 3.0, 0.0, 4.0]);
+    let s = d.sample_discrete(0.99);
+    assert!(s.value == 3);
+    assert!((s.pdf - 0.5).abs() < EPS);
+  }
+
+  #[test]
+  fn distribution_2d_test() {
+    // 右下の要素だけが大きい
+    let d = Distribution2D::new(vec![1.0, 1.0, 1.0, 5.0], 2, 2);
+    assert!((d.pdf_at(0.75, 0.75) - 2.5).abs() < EPS);
+    assert!((d.pdf_at(0.25, 0.25) - 0.5).abs() < EPS);
+    let s = d.sample_continuous(0.9, 0.9);
+    assert!(s.value.0 >= 0.5 && s.value.1 >= 0.5);
+    assert!((s.pdf - d.pdf_at(s.value.0, s.value.1)).abs() < EPS);
+  }
+
+  #[test]
+  fn alias_table_test() {
+    let weight = vec![1.0, 3.0, 0.0, 4.0];
+    let table = AliasTable::new(weight.clone());
+    // 区間を細かく刻んで選ばれた割合が確率質量に一致する
+    let n = 8000;
+    let mut count = vec![0; weight.len()];
+    for k in 0..n {
+      let s = table.sample((k as f32 + 0.5) / n as f32);
+      assert!((s.pdf - weight[s.value] / 8.0).abs() < EPS);
+      count[s.value] += 1;
+    }
+    for i in 0..weight.len() {
+      assert!((count[i] as f32 / n as f32 - weight[i] / 8.0).abs() < EPS, "{:?}", count);
+    }
+  }
+
+  #[test]
+  fn zero_function_test() {
+    let d = Distribution1D::new(vec![0.0, 0.0]);
+    let s = d.sample_continuous(0.75);
+    assert!((s.value - 0.75).abs() < EPS);
+    assert!((s.pdf - 1.0).abs() < EPS);
+  }
+}
@@ -0,0 +1,198 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use math::vector::*;
+use constant::*;
+use util::OrthonormalBasis;
+
+// IES LM-63 の配光データ (Type C)
+// 鉛直角 0° を照射軸 (器具の真下), 水平角 0° を基準の接線方向とする
+pub struct IesProfile {
+  // 鉛直角 (度, 昇順)
+  vertical: Vec<f32>,
+  // 水平角 (度, 昇順)
+  horizontal: Vec<f32>,
+  // 水平角ごとの鉛直角についての光度 (cd, ファイルの倍率を掛けたもの)
+  candela: Vec<f32>,
+  // 光度の最大値
+  max: f32,
+}
+
+impl IesProfile {
+  pub fn load(path: &str) -> IesProfile {
+    let file = File::open(path).expect(&format!("File `{}` is not found.", path));
+    let mut bytes = Vec::new();
+    BufReader::new(file).read_to_end(&mut bytes).unwrap();
+    // ヘッダのキーワードには Latin-1 の文字が含まれることがある
+    let text = String::from_utf8_lossy(&bytes);
+    Self::parse(&text).unwrap_or_else( |e| panic!(format!("File `{}` is not an IES file: {}", path, e)) )
+  }
+
+  pub fn parse(text: &str) -> Result<IesProfile, String> {
+    // キーワード行を読み飛ばして TILT= 以降の数値を読む
+    let mut lines = text.lines();
+    let tilt = loop {
+      match lines.next() {
+        Some(line) if line.trim_start().starts_with("TILT=") => break line.trim_start()[5..].trim().to_string(),
+        Some(_) => continue,
+        None => return Err("TILT is not found".to_string()),
+      }
+    };
+    let rest = lines.collect::<Vec<_>>().join(" ");
+    let mut values = rest
+      .split( |c: char| c.is_whitespace() || c == ',' )
+      .filter( |s| !s.is_empty() )
+      .map( |s| s.parse::<f32>().map_err( |_| format!("invalid number `{}`", s) ) );
+    let mut next = || values.next().unwrap_or(Err("unexpected end of file".to_string()));
+    if tilt == "INCLUDE" {
+      // ランプの傾きによる補正は使わないので読み飛ばす
+      next()?;
+      let n = next()? as usize;
+      for _ in 0..n * 2 {
+        next()?;
+      }
+    }
+    let _lamps = next()?;
+    let _lumens = next()?;
+    let multiplier = next()?;
+    let n_vertical = next()? as usize;
+    let n_horizontal = next()? as usize;
+    let photometric_type = next()? as usize;
+    if photometric_type != 1 {
+      return Err(format!("photometric type {} is not supported", photometric_type))
+    }
+    // 単位, 発光部の寸法, 安定器係数, 予備, 消費電力
+    for _ in 0..7 {
+      next()?;
+    }
+    if n_vertical == 0 || n_horizontal == 0 {
+      return Err("no angles".to_string())
+    }
+    let vertical = (0..n_vertical).map( |_| next() ).collect::<Result<Vec<_>, _>>()?;
+    let horizontal = (0..n_horizontal).map( |_| next() ).collect::<Result<Vec<_>, _>>()?;
+    let candela = (0..n_vertical * n_horizontal).map( |_| next().map( |v| (v * multiplier).max(0.0) ) ).collect::<Result<Vec<_>, _>>()?;
+    let max = candela.iter().cloned().fold(0.0, f32::max);
+    if max == 0.0 {
+      return Err("all candela values are zero".to_string())
+    }
+    Ok(IesProfile {
+      vertical: vertical,
+      horizontal: horizontal,
+      candela: candela,
+      max: max,
+    })
+  }
+
+  pub fn max(&self) -> f32 {
+    self.max
+  }
+
+  // 照射軸からの角度, 基準方向からの方位角 (ともに度) -> 光度 (cd)
+  pub fn value(&self, theta: f32, phi: f32) -> f32 {
+    let first = self.vertical[0];
+    let last = self.vertical[self.vertical.len() - 1];
+    if theta < first - EPS || theta > last + EPS {
+      // 測定範囲外には光が出ない
+      return 0.0
+    }
+    // 水平角の対称性にしたがって範囲内に折り返す
+    let phi = phi.rem_euclid(360.0);
+    let phi = match self.horizontal[self.horizontal.len() - 1] as i32 {
+      // 軸対称
+      0 => 0.0,
+      // 4象限で対称
+      90 => {
+        let p = if phi > 180.0 { 360.0 - phi } else { phi };
+        if p > 90.0 { 180.0 - p } else { p }
+      },
+      // 0°-180° の面で対称
+      180 => if phi > 180.0 { 360.0 - phi } else { phi },
+      _ => phi,
+    };
+    let (h0, h1, th) = Self::interval(&self.horizontal, phi);
+    let (v0, v1, tv) = Self::interval(&self.vertical, theta);
+    let n = self.vertical.len();
+    let at = |h: usize, v: usize| self.candela[h * n + v];
+    let a = at(h0, v0) * (1.0 - tv) + at(h0, v1) * tv;
+    let b = at(h1, v0) * (1.0 - tv) + at(h1, v1) * tv;
+    a * (1.0 - th) + b * th
+  }
+
+  // 照らす方向, 照射軸, 水平角の基準方向 -> 光度 (cd)
+  pub fn value_in(&self, direction: Vector3, axis: Vector3, reference: Vector3) -> f32 {
+    let w = axis.normalize();
+    // 基準方向を照射軸に垂直にする
+    let r = reference - w * reference.dot(w);
+    let u = if r.sqr_norm() > 0.0 { r.normalize() } else { w.orthonormal_basis().0 };
+    let v = w.cross(u);
+    let d = direction.normalize();
+    let theta = d.dot(w).max(-1.0).min(1.0).acos() * 180.0 / PI;
+    let phi = d.dot(v).atan2(d.dot(u)) * 180.0 / PI;
+    self.value(theta, phi)
+  }
+
+  // 昇順の角度の列と角度 -> 挟む2つの添字と補間の重み
+  fn interval(angles: &Vec<f32>, x: f32) -> (usize, usize, f32) {
+    let n = angles.len();
+    if n == 1 || x <= angles[0] {
+      return (0, 0, 0.0)
+    }
+    if x >= angles[n - 1] {
+      return (n - 1, n - 1, 0.0)
+    }
+    let i = angles.iter().rposition( |&a| a <= x ).unwrap();
+    let t = (x - angles[i]) / (angles[i + 1] - angles[i]);
+    (i, i + 1, t)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const PROFILE: &str = "IESNA:LM-63-2002
+[TEST] sample
+[LUMINAIRE] downlight
+TILT=NONE
+1 1000 2 3 2 1 2 0 0 0
+1 1 50
+0 45 90
+0 180
+100 50 0
+100 60 0
+";
+
+  #[test]
+  fn parse_test() {
+    let profile = IesProfile::parse(PROFILE).unwrap();
+    // 倍率を掛けた光度を鉛直角, 水平角について線形補間する
+    assert_eq!(profile.max(), 200.0);
+    assert!((profile.value(0.0, 0.0) - 200.0).abs() < EPS);
+    assert!((profile.value(22.5, 0.0) - 150.0).abs() < EPS);
+    assert!((profile.value(45.0, 90.0) - 110.0).abs() < EPS);
+    // 0°-180° の面で対称
+    assert!((profile.value(45.0, 270.0) - 110.0).abs() < EPS);
+    assert!((profile.value(45.0, 180.0) - 120.0).abs() < EPS);
+    // 測定範囲外
+    assert_eq!(profile.value(120.0, 0.0), 0.0);
+  }
+
+  #[test]
+  fn direction_test() {
+    let profile = IesProfile::parse(PROFILE).unwrap();
+    let down = Vector3::new(0.0, -1.0, 0.0);
+    let reference = Vector3::new(1.0, 0.0, 0.0);
+    assert!((profile.value_in(down, down, reference) - 200.0).abs() < EPS);
+    let side = Vector3::new(-1.0, -1.0, 0.0);
+    assert!((profile.value_in(side, down, reference) - 120.0).abs() < EPS);
+    // 基準方向を回すと水平角も回る
+    let side = Vector3::new(0.0, -1.0, 1.0);
+    assert!((profile.value_in(side, down, Vector3::new(0.0, 0.0, -1.0)) - 120.0).abs() < EPS);
+    assert_eq!(profile.value_in(-down, down, reference), 0.0);
+  }
+
+  #[test]
+  fn invalid_test() {
+    assert!(IesProfile::parse("IESNA:LM-63-2002\n").is_err());
+    assert!(IesProfile::parse("TILT=NONE\n1 1000 1 3").is_err());
+  }
+}
@@ -1,10 +1,102 @@
 use std::sync::Arc;
-use math::vector::Vector3;
+use math::vector::*;
 use material::material::Material;
+use ray::Ray;
+use util::OrthonormalBasis;
 
 pub struct Intersection {
   pub position: Vector3,
   pub distance: f32,
+  // 幾何的な法線 (物体の内外の判定につかう)
   pub normal: Vector3,
+  // 陰影計算用の法線 (頂点法線の補間)
+  pub shading_normal: Vector3,
+  // 法線マップの基準となる接線
+  pub tangent: Vector3,
+  // テクスチャ座標
+  pub uv: (f32, f32),
+  // テクスチャ座標についての位置の偏微分
+  pub dpdu: Vector3,
+  pub dpdv: Vector3,
   pub material: Arc<dyn Material + Send + Sync>,
+  // 交差した物体のシーン全体での番号 (BVHで設定する)
+  pub object: usize,
+}
+
+impl Intersection {
+  // 交差したレイの広がりからテクスチャ座標の偏微分を求めた表面上の点
+  pub fn point(&self, ray: &Ray) -> SurfacePoint {
+    let mut point = SurfacePoint::new(self.position, self.shading_normal, self.uv);
+    point.dpdu = self.dpdu;
+    point.dpdv = self.dpdv;
+    point.tangent = self.tangent;
+    if let Some(differential) = ray.differential {
+      let (dpdx, dpdy) = differential.footprint(ray, self.distance, self.normal);
+      point.duvdx = self.uv_differential(dpdx);
+      point.duvdy = self.uv_differential(dpdy);
+    }
+    point
+  }
+
+  fn uv_differential(&self, dp: Vector3) -> (f32, f32) {
+    // dp = dpdu * du + dpdv * dv を最小二乗法で解く
+    let a = self.dpdu.dot(self.dpdu);
+    let b = self.dpdu.dot(self.dpdv);
+    let c = self.dpdv.dot(self.dpdv);
+    let det = a * c - b * b;
+    if det.abs() < 1e-12 {
+      return (0.0, 0.0)
+    }
+    let pu = self.dpdu.dot(dp);
+    let pv = self.dpdv.dot(dp);
+    ((c * pu - b * pv) / det, (a * pv - b * pu) / det)
+  }
+}
+
+// マテリアルのパラメータ(テクスチャ)を評価する表面上の点
+#[derive(Debug, Clone, Copy)]
+pub struct SurfacePoint {
+  // 座標
+  pub position: Vector3,
+  // 陰影計算用の法線
+  pub normal: Vector3,
+  // テクスチャ座標
+  pub uv: (f32, f32),
+  // 画面のx方向, y方向についてのテクスチャ座標の偏微分 (フィルタリングの範囲)
+  pub duvdx: (f32, f32),
+  pub duvdy: (f32, f32),
+  // テクスチャ座標についての位置の偏微分 (バンプマップ用)
+  pub dpdu: Vector3,
+  pub dpdv: Vector3,
+  // 接線 (法線マップ用)
+  pub tangent: Vector3,
+}
+
+impl SurfacePoint {
+  pub fn new(position: Vector3, normal: Vector3, uv: (f32, f32)) -> SurfacePoint {
+    let (u, v) = normal.orthonormal_basis();
+    SurfacePoint {
+      position: position,
+      normal: normal,
+      uv: uv,
+      duvdx: (0.0, 0.0),
+      duvdy: (0.0, 0.0),
+      dpdu: u,
+      dpdv: v,
+      tangent: u,
+    }
+  }
+
+  // 法線マップの接空間の基底 (接線, 従法線)
+  pub fn tangent_frame(&self) -> (Vector3, Vector3) {
+    // 接線を法線に直交化する
+    let t = self.tangent - self.normal * self.normal.dot(self.tangent);
+    if t.sqr_norm() < 1e-12 {
+      return self.normal.orthonormal_basis()
+    }
+    let t = t.normalize();
+    let b = self.normal.cross(t);
+    // テクスチャ座標が反転している場合は従法線も反転する
+    if b.dot(self.dpdv) < 0.0 { (t, -b) } else { (t, b) }
+  }
 }
@@ -0,0 +1,284 @@
+extern crate rand;
+
+use math::vector::*;
+use constant::*;
+use ray::Ray;
+use util::OrthonormalBasis;
+use std::sync::Arc;
+use ies::IesProfile;
+
+// 光源上の1点への接続
+pub struct LightSample {
+  // 照らされる点から光源への方向
+  pub direction: Vector3,
+  // 光源までの距離 (平行光源は無限遠)
+  pub distance: f32,
+  // 照らされる点に届く放射照度 (光源の方向に垂直な面で測る)
+  pub irradiance: Vector3,
+}
+
+// シーンの物体として表されない光源 (点光源, 平行光源, 太陽)
+pub trait Light {
+  // 照らされる点 -> 光源への接続 (放射照度は確率密度で割ったもの)
+  fn sample(&self, position: Vector3) -> LightSample;
+  // 物体に当たらなかったレイ -> 光源の放射輝度 (デルタ関数で表される光源はレイが当たらない)
+  fn radiance(&self, _ray: &Ray) -> Vector3 {
+    Vector3::zero()
+  }
+  // デルタ関数で表される光源か (BRDFサンプリングでは当たらない)
+  fn is_delta(&self) -> bool {
+    true
+  }
+}
+
+// 光源ごとに照らす物体を制限する (light linking)
+#[derive(Default)]
+pub struct LightLinks {
+  // シーン全体での物体の番号 -> シーンの記述での物体の番号
+  pub object: Vec<usize>,
+  // 物体として表されない光源の番号 -> 照らす物体 (制限がなければNone)
+  pub lights: Vec<Option<Vec<bool>>>,
+  // シーンの記述での物体の番号 -> その物体を光源としたときに照らす物体
+  pub emitters: Vec<Option<Vec<bool>>>,
+}
+
+impl LightLinks {
+  fn linked(&self, receivers: Option<&Option<Vec<bool>>>, receiver: usize) -> bool {
+    match receivers {
+      Some(&Some(ref receivers)) => self.object.get(receiver).map( |&o| receivers[o] ).unwrap_or(true),
+      _ => true,
+    }
+  }
+
+  // 物体として表されない光源がその物体を照らすか
+  pub fn light(&self, light: usize, receiver: usize) -> bool {
+    self.linked(self.lights.get(light), receiver)
+  }
+
+  // 光源の物体がその物体を照らすか
+  pub fn emitter(&self, emitter: usize, receiver: usize) -> bool {
+    match self.object.get(emitter) {
+      Some(&o) => self.linked(self.emitters.get(o), receiver),
+      None => true,
+    }
+  }
+}
+
+// 配光 -> 光源から照らす方向への放射強度の倍率 (配光の光度)
+fn profile_value(profile: &Option<Arc<IesProfile>>, direction: Vector3, axis: Vector3, reference: Vector3) -> f32 {
+  match *profile {
+    Some(ref profile) => profile.value_in(direction, axis, reference),
+    None => 1.0,
+  }
+}
+
+pub struct PointLight {
+  pub position: Vector3,
+  // 放射強度 (W/sr, 配光があれば光度 (cd) に掛ける倍率)
+  pub intensity: Vector3,
+  // 配光
+  pub profile: Option<Arc<IesProfile>>,
+  // 配光の照射軸
+  pub axis: Vector3,
+  // 配光の水平角0°の基準方向 (ゼロのときは任意)
+  pub reference: Vector3,
+}
+
+impl Light for PointLight {
+  fn sample(&self, position: Vector3) -> LightSample {
+    let path = self.position - position;
+    let sqr_distance = path.sqr_norm();
+    let direction = path / sqr_distance.sqrt();
+    LightSample {
+      direction: direction,
+      distance: sqr_distance.sqrt(),
+      irradiance: self.intensity * profile_value(&self.profile, -direction, self.axis, self.reference) / sqr_distance,
+    }
+  }
+}
+
+pub struct SpotLight {
+  pub position: Vector3,
+  // 照らす方向
+  pub direction: Vector3,
+  // 光軸上の放射強度 (W/sr, 配光があれば光度 (cd) に掛ける倍率)
+  pub intensity: Vector3,
+  // 照らす範囲の半頂角のcos
+  cos_total: f32,
+  // 減衰を始める角度のcos
+  cos_falloff_start: f32,
+  // 配光 (照射軸は照らす方向)
+  profile: Option<Arc<IesProfile>>,
+  // 配光の水平角0°の基準方向 (ゼロのときは任意)
+  reference: Vector3,
+}
+
+impl SpotLight {
+  // angle: 照らす範囲の半頂角, falloff: 縁で減衰させる幅 (ともに度)
+  pub fn new(position: Vector3, direction: Vector3, intensity: Vector3, angle: f32, falloff: f32, profile: Option<Arc<IesProfile>>, reference: Vector3) -> SpotLight {
+    let total = angle.max(0.0).min(180.0) * PI / 180.0;
+    let start = (angle - falloff).max(0.0).min(180.0) * PI / 180.0;
+    SpotLight {
+      position: position,
+      direction: direction.normalize(),
+      intensity: intensity,
+      cos_total: total.cos(),
+      cos_falloff_start: start.cos(),
+      profile: profile,
+      reference: reference,
+    }
+  }
+
+  fn falloff(&self, cos: f32) -> f32 {
+    if cos <= self.cos_total {
+      return 0.0
+    }
+    if cos >= self.cos_falloff_start {
+      return 1.0
+    }
+    // 範囲の縁ではsmoothstepで滑らかに減衰させる
+    let t = (cos - self.cos_total) / (self.cos_falloff_start - self.cos_total);
+    t * t * (3.0 - 2.0 * t)
+  }
+}
+
+impl Light for SpotLight {
+  fn sample(&self, position: Vector3) -> LightSample {
+    let path = self.position - position;
+    let sqr_distance = path.sqr_norm();
+    let direction = path / sqr_distance.sqrt();
+    LightSample {
+      direction: direction,
+      distance: sqr_distance.sqrt(),
+      irradiance: self.intensity * self.falloff((-direction).dot(self.direction))
+        * profile_value(&self.profile, -direction, self.direction, self.reference) / sqr_distance,
+    }
+  }
+}
+
+pub struct DirectionalLight {
+  // 光の進む方向
+  pub direction: Vector3,
+  // 光の方向に垂直な面での放射照度 (W/m^2)
+  pub irradiance: Vector3,
+}
+
+impl Light for DirectionalLight {
+  fn sample(&self, _position: Vector3) -> LightSample {
+    LightSample {
+      direction: -self.direction,
+      distance: INF,
+      irradiance: self.irradiance,
+    }
+  }
+}
+
+// 無限遠にある視半径をもつ円盤
+pub struct SunLight {
+  // 太陽の方向
+  pub direction: Vector3,
+  pub radiance: Vector3,
+  // 視半径のcos
+  pub cos_radius: f32,
+}
+
+impl Light for SunLight {
+  fn sample(&self, _position: Vector3) -> LightSample {
+    // 円盤の張る立体角内で一様にサンプリング
+    let cos_theta = 1.0 - rand::random::<f32>() * (1.0 - self.cos_radius);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * rand::random::<f32>();
+    let (u, v) = self.direction.orthonormal_basis();
+    let solid_angle = 2.0 * PI * (1.0 - self.cos_radius);
+    LightSample {
+      direction: u * (sin_theta * phi.cos()) + v * (sin_theta * phi.sin()) + self.direction * cos_theta,
+      distance: INF,
+      irradiance: self.radiance * solid_angle,
+    }
+  }
+
+  fn is_delta(&self) -> bool {
+    false
+  }
+
+  fn radiance(&self, ray: &Ray) -> Vector3 {
+    if ray.direction.dot(self.direction) >= self.cos_radius {
+      self.radiance
+    } else {
+      Vector3::zero()
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn point_light_test() {
+    // 距離の2乗に反比例する
+    let light = PointLight {
+      position: Vector3::new(0.0, 2.0, 0.0),
+      intensity: Vector3::new(4.0, 4.0, 4.0),
+      profile: None,
+      axis: Vector3::new(0.0, -1.0, 0.0),
+      reference: Vector3::zero(),
+    };
+    let sample = light.sample(Vector3::zero());
+    assert!((sample.direction - Vector3::new(0.0, 1.0, 0.0)).norm() < EPS);
+    assert!((sample.distance - 2.0).abs() < EPS);
+    assert!((sample.irradiance.x - 1.0).abs() < EPS);
+    assert!(light.is_delta());
+  }
+
+  #[test]
+  fn sun_light_test() {
+    // 円盤の内側に向かうレイは当たるのでデルタ関数ではない
+    let light = SunLight {
+      direction: Vector3::new(0.0, 1.0, 0.0),
+      radiance: Vector3::new(1.0, 1.0, 1.0),
+      cos_radius: 0.99,
+    };
+    assert!(!light.is_delta());
+    let sample = light.sample(Vector3::zero());
+    assert!(sample.direction.dot(light.direction) >= light.cos_radius - EPS);
+  }
+
+  #[test]
+  fn spot_light_test() {
+    let light = SpotLight::new(
+      Vector3::new(0.0, 1.0, 0.0),
+      Vector3::new(0.0, -1.0, 0.0),
+      Vector3::new(1.0, 1.0, 1.0),
+      45.0,
+      10.0,
+      None,
+      Vector3::zero(),
+    );
+    // 光軸上, 減衰の途中, 範囲外
+    let center = light.sample(Vector3::zero()).irradiance.x;
+    let edge = light.sample(Vector3::new(40f32.to_radians().tan() * 1.0, 0.0, 0.0)).irradiance.x;
+    let outside = light.sample(Vector3::new(2.0, 0.0, 0.0)).irradiance.x;
+    assert!((center - 1.0).abs() < EPS);
+    assert!(edge > 0.0 && edge < center, "{}", edge);
+    assert_eq!(outside, 0.0);
+  }
+
+  #[test]
+  fn light_links_test() {
+    // 物体0, 1 (物体1は2つのインスタンス) のうち光源0は物体1だけを照らす
+    let links = LightLinks {
+      object: vec![0, 1, 1],
+      lights: vec![Some(vec![false, true]), None],
+      emitters: vec![None, Some(vec![true, false])],
+    };
+    assert!(!links.light(0, 0));
+    assert!(links.light(0, 2));
+    assert!(links.light(1, 0));
+    // 空の光源などリストにない光源は制限されない
+    assert!(links.light(2, 0));
+    assert!(links.emitter(0, 1));
+    assert!(!links.emitter(1, 2));
+    assert!(links.emitter(2, 0));
+  }
+}
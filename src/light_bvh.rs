@@ -0,0 +1,131 @@
+extern crate ordered_float;
+
+use aabb::AABB;
+use math::vector::*;
+use sample::Sample;
+use self::ordered_float::OrderedFloat;
+
+// 光源の位置と放射の強さによる2分木 (照らされる点に近く明るい光源ほど選ばれやすい)
+pub struct LightBVH {
+  root: LightNode,
+}
+
+struct LightNode {
+  aabb: AABB,
+  // 部分木に含まれる光源の放射の強さの和
+  power: f32,
+  children: Option<Box<(LightNode, LightNode)>>,
+  // 葉の場合の光源の番号
+  index: usize,
+}
+
+impl LightNode {
+  // 照らされる点から見た部分木の重要度
+  fn importance(&self, position: Vector3) -> f32 {
+    // 部分木の内側では距離を半径で下から抑える
+    let radius = self.aabb.side().norm() * 0.5;
+    let sqr_distance = (self.aabb.center - position).sqr_norm().max(radius * radius).max(1e-6);
+    self.power / sqr_distance
+  }
+}
+
+impl LightBVH {
+  // lights: (AABB, 放射の強さ)
+  pub fn new(lights: Vec<(AABB, f32)>) -> LightBVH {
+    let mut leaf = lights.into_iter().enumerate().map( |(i, (aabb, power))| LightNode {
+      aabb: aabb,
+      power: power,
+      children: None,
+      index: i,
+    }).collect::<Vec<_>>();
+    LightBVH {
+      root: Self::construct(&mut leaf),
+    }
+  }
+
+  fn construct(list: &mut Vec<LightNode>) -> LightNode {
+    if list.len() == 1 {
+      return list.pop().unwrap()
+    }
+    // 中心の広がりが最も大きい軸の中央値で分割する
+    let mut center = AABB::empty();
+    for node in list.iter() {
+      center = center.merge_with(&AABB {
+        min: node.aabb.center,
+        max: node.aabb.center,
+        center: node.aabb.center,
+      });
+    }
+    let side = center.side();
+    let axis = if side.x >= side.y && side.x >= side.z { 0 } else if side.y >= side.z { 1 } else { 2 };
+    list.sort_unstable_by_key( |v| OrderedFloat(v.aabb.center[axis]) );
+    let mut right = list.split_off(list.len() / 2);
+    let left = Self::construct(list);
+    let right = Self::construct(&mut right);
+    LightNode {
+      aabb: left.aabb.merge_with(&right.aabb),
+      power: left.power + right.power,
+      children: Some(box (left, right)),
+      index: 0,
+    }
+  }
+
+  // 0 <= u < 1 の一様乱数と照らされる点から光源をひとつ選ぶ (確率密度は確率質量)
+  pub fn sample(&self, position: Vector3, u: f32) -> Sample<usize> {
+    let mut node = &self.root;
+    let mut u = u;
+    let mut pmf = 1.0;
+    while let Some(ref children) = node.children {
+      let (ref left, ref right) = **children;
+      let l = left.importance(position);
+      let r = right.importance(position);
+      let p = if l + r > 0.0 { l / (l + r) } else { 0.5 };
+      // 乱数を選んだ側の区間に引き伸ばして再利用する
+      if u < p {
+        u = u / p;
+        pmf *= p;
+        node = left;
+      } else {
+        u = ((u - p) / (1.0 - p)).min(1.0 - 1e-6);
+        pmf *= 1.0 - p;
+        node = right;
+      }
+    }
+    Sample {
+      value: node.index,
+      pdf: pmf,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use constant::*;
+
+  fn point(x: f32) -> AABB {
+    let p = Vector3::new(x, 0.0, 0.0);
+    AABB {
+      min: p,
+      max: p,
+      center: p,
+    }
+  }
+
+  #[test]
+  fn nearby_light_test() {
+    // 同じ強さの光源は近い方が選ばれやすく, 確率質量の和は1になる
+    let bvh = LightBVH::new(vec![(point(0.0), 1.0), (point(10.0), 1.0), (point(20.0), 1.0)]);
+    let position = Vector3::new(1.0, 0.0, 0.0);
+    let n = 1000;
+    let mut pmf = vec![0.0; 3];
+    let mut count = vec![0; 3];
+    for k in 0..n {
+      let s = bvh.sample(position, (k as f32 + 0.5) / n as f32);
+      pmf[s.value] = s.pdf;
+      count[s.value] += 1;
+    }
+    assert!(count[0] > count[1] && count[1] > count[2], "{:?}", count);
+    assert!((pmf.iter().sum::<f32>() - 1.0).abs() < EPS, "{:?}", pmf);
+  }
+}
@@ -15,19 +15,26 @@ mod img;
 mod math;
 mod ray;
 mod sample;
+mod medium;
+mod distribution;
 mod camera;
 mod intersection;
 mod material;
+mod texture;
 mod scene;
 mod sphere;
 mod triangle;
 mod objects;
 mod sky;
+mod light;
+mod portal;
+mod ies;
 mod description;
 mod util;
 mod shape;
 mod aabb;
 mod bvh;
+mod light_bvh;
 mod scene_loader;
 
 use scoped_threadpool::Pool;
@@ -92,7 +99,9 @@ fn main() {
             let estimated_sum = (0..spp).fold(Vector3::zero(), |sum, _| {
               // センサーの1画素に入射する放射輝度を立体角測度でモンテカルロ積分し放射照度を得る
               // カメラから出射されるレイをサンプリング
-              let (ray, g_term) = cam.sample(x, y);
+              let (mut ray, g_term) = cam.sample(x, y);
+              // 1画素を複数のサンプルで覆うので広がりはサンプル間隔まで縮める
+              ray.value.scale_differential(1.0 / (spp as f32).sqrt());
               // 開口部に入射する放射輝度 (W sr^-1 m^-2)
               let l_into_sensor = scene.radiance(&ray.value);
               // センサーに入射する放射照度
@@ -109,7 +118,9 @@ fn main() {
             let estimated_sum = (0..spp).fold(Vector3::zero(), |sum, _| {
               // センサーの1画素に入射する放射輝度を立体角測度でモンテカルロ積分し放射照度を得る
               // カメラから出射されるレイをサンプリング
-              let (ray, g_term) = cam.sample(x, y);
+              let (mut ray, g_term) = cam.sample(x, y);
+              // 1画素を複数のサンプルで覆うので広がりはサンプル間隔まで縮める
+              ray.value.scale_differential(1.0 / (spp as f32).sqrt());
               // 開口部に入射する放射輝度 (W sr^-1 m^-2)
               let l_into_sensor = scene.radiance_nee(&ray.value);
               // センサーに入射する放射照度
@@ -121,6 +132,22 @@ fn main() {
             tx.send((x, y, estimated_sum / spp as f32)).unwrap()
           });
         },
+        "normal" | "depth" => {
+          scope.execute(move || {
+            let estimated_sum = (0..spp).fold(Vector3::zero(), |sum, _| {
+              // 放射輝度の代わりに最初に交差した面の法線, 距離を出力する
+              let (ray, _) = cam.sample(x, y);
+              let aov = if integrator == "normal" {
+                scene.normal(&ray.value)
+              } else {
+                let depth = scene.depth(&ray.value);
+                Vector3::new(depth, depth, depth)
+              };
+              sum + aov
+            });
+            tx.send((x, y, estimated_sum / spp as f32)).unwrap()
+          });
+        },
         _ => panic!(format!("Unknown integrator type `{}`", integrator)),
       }
     });
@@ -133,7 +160,21 @@ fn main() {
 
   println!("");
   println!("saving...");
-  let gamma = description.config.film.gamma.unwrap_or(2.2);
+  // 法線, 距離は線形の値として保存する (PNGの距離は最大値で [0, 1] に詰める)
+  let is_aov = integrator == "normal" || integrator == "depth";
+  if integrator == "depth" && description.config.film.output == "png" {
+    let max = (0..height).flat_map( |y| (0..width).map( move |x| (x, y) ) )
+      .fold(0.0f32, |max, (x, y)| max.max(output.get(x, y).x) );
+    if max > 0.0 {
+      for y in 0..height {
+        for x in 0..width {
+          let pixel = output.get(x, y);
+          output.set(x, y, pixel / max);
+        }
+      }
+    }
+  }
+  let gamma = if is_aov { 1.0 } else { description.config.film.gamma.unwrap_or(2.2) };
   save(&output, &description.config.film.output, gamma, spp);
 
   let end_time = time::now();
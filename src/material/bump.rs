@@ -0,0 +1,154 @@
+use std::sync::Arc;
+use super::traits::Material;
+use math::vector::*;
+use intersection::SurfacePoint;
+use sample::Sample;
+use medium::Medium;
+use texture::texture::Texture;
+
+pub struct BumpMaterial {
+  // 法線を摂動するマテリアル
+  pub base: Arc<Material + Send + Sync>,
+  // 高さのテクスチャ (x成分をつかう)
+  pub map: Arc<Texture + Send + Sync>,
+  // 高さの倍率
+  pub scale: f32,
+}
+
+impl BumpMaterial {
+  fn height(&self, point: &SurfacePoint) -> f32 {
+    self.map.value(point).x * self.scale
+  }
+}
+
+impl Material for BumpMaterial {
+  fn normal(&self, point: &SurfacePoint) -> Vector3 {
+    let n = self.base.normal(point);
+    // 差分の幅はテクスチャのフィルタリングの範囲に合わせる
+    let delta = |a: f32, b: f32| {
+      let d = (a.abs() + b.abs()) * 0.5;
+      if d == 0.0 { 0.0005 } else { d }
+    };
+    let du = delta(point.duvdx.0, point.duvdy.0);
+    let dv = delta(point.duvdx.1, point.duvdy.1);
+    let h = self.height(point);
+    let hu = self.height(&SurfacePoint {
+      position: point.position + point.dpdu * du,
+      uv: (point.uv.0 + du, point.uv.1),
+      ..*point
+    });
+    let hv = self.height(&SurfacePoint {
+      position: point.position + point.dpdv * dv,
+      uv: (point.uv.0, point.uv.1 + dv),
+      ..*point
+    });
+    // 法線方向に変位した面の偏微分 (接平面に射影して陰影計算用の法線を基準にする)
+    let dpdu = point.dpdu - n * n.dot(point.dpdu) + n * ((hu - h) / du);
+    let dpdv = point.dpdv - n * n.dot(point.dpdv) + n * ((hv - h) / dv);
+    let perturbed = dpdu.cross(dpdv);
+    if perturbed.sqr_norm() == 0.0 {
+      return n
+    }
+    let perturbed = perturbed.normalize();
+    // 偏微分の向きによらず元の法線と同じ側にそろえる
+    if perturbed.dot(n) < 0.0 { -perturbed } else { perturbed }
+  }
+
+  fn opacity(&self, point: &SurfacePoint) -> f32 {
+    self.base.opacity(point)
+  }
+
+  fn has_opacity(&self) -> bool {
+    self.base.has_opacity()
+  }
+
+  fn orienting_normal(&self, out_: Vector3, normal: Vector3) -> Vector3 {
+    self.base.orienting_normal(out_, normal)
+  }
+
+  fn emission(&self, point: &SurfacePoint) -> Vector3 {
+    self.base.emission(point)
+  }
+
+  fn directional_emission(&self, point: &SurfacePoint, out_: Vector3) -> Vector3 {
+    self.base.directional_emission(point, out_)
+  }
+
+  fn max_emission(&self) -> Vector3 {
+    self.base.max_emission()
+  }
+
+  fn two_sided(&self) -> bool {
+    self.base.two_sided()
+  }
+
+  fn weight(&self) -> f32 {
+    self.base.weight()
+  }
+
+  fn brdf(&self, out_: Vector3, in_: Vector3, n: Vector3, point: &SurfacePoint) -> Vector3 {
+    self.base.brdf(out_, in_, n, point)
+  }
+
+  fn sample(&self, out_: Vector3, n: Vector3, point: &SurfacePoint) -> Sample<Vector3> {
+    self.base.sample(out_, n, point)
+  }
+
+  fn pdf(&self, out_: Vector3, in_: Vector3, n: Vector3, point: &SurfacePoint) -> f32 {
+    self.base.pdf(out_, in_, n, point)
+  }
+
+  fn coef(&self, out_: Vector3, n: Vector3, fly_distance: f32, point: &SurfacePoint) -> Vector3 {
+    self.base.coef(out_, n, fly_distance, point)
+  }
+
+  fn is_delta(&self, out_: Vector3, in_: Vector3, n: Vector3) -> bool {
+    self.base.is_delta(out_, in_, n)
+  }
+
+  fn transmittance(&self, direction: Vector3, n: Vector3, point: &SurfacePoint) -> Option<Vector3> {
+    self.base.transmittance(direction, n, point)
+  }
+
+  fn medium(&self, out_: Vector3, n: Vector3) -> Option<Medium> {
+    self.base.medium(out_, n)
+  }
+
+  fn select(&self, point: &SurfacePoint) -> Option<Arc<Material + Send + Sync>> {
+    self.base.select(point).map( |base| Arc::new(BumpMaterial {
+      base: base,
+      map: self.map.clone(),
+      scale: self.scale,
+    }) as Arc<Material + Send + Sync> )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use material::material::*;
+  use texture::texture::*;
+
+  #[test]
+  fn slope_test() {
+    // x方向に傾き1で高くなる面
+    let mat = BumpMaterial {
+      base: Arc::new(LambertianMaterial {
+        emission: Vector3::zero(),
+        albedo: Arc::new(ConstantTexture { value: Vector3::new(1.0, 1.0, 1.0) }),
+      }),
+      map: Arc::new(ProceduralTexture {
+        mapping: Mapping::World,
+        scale: 1.0,
+        pattern: box GradientPattern { axis: Vector3::new(1.0, 0.0, 0.0) },
+        colors: (Vector3::zero(), Vector3::new(1.0, 1.0, 1.0)),
+      }),
+      scale: 1.0,
+    };
+    let mut point = SurfacePoint::new(Vector3::new(0.5, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0), (0.5, 0.0));
+    point.dpdu = Vector3::new(1.0, 0.0, 0.0);
+    point.dpdv = Vector3::new(0.0, 1.0, 0.0);
+    let n = mat.normal(&point);
+    assert!((n - Vector3::new(-1.0, 0.0, 1.0).normalize()).norm() < 1e-2, "{}", n);
+  }
+}
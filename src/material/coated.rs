@@ -0,0 +1,244 @@
+extern crate rand;
+
+use std::sync::Arc;
+use super::traits::Material;
+use super::ggx::GGXMaterial;
+use math::vector::*;
+use intersection::SurfacePoint;
+use sample::Sample;
+use util::BoundaryResponse;
+use texture::texture::ConstantTexture;
+
+pub struct CoatedMaterial {
+  // 下地のマテリアル
+  pub base: Arc<Material + Send + Sync>,
+  // コーティングの屈折率
+  pub ior: f32,
+  // コーティングのラフネス (0のとき鏡面)
+  pub roughness: f32,
+  // 垂直入射で往復したときのコーティングの透過率 (吸収による色味)
+  pub tint: Vector3,
+  // ラフネスがあるときのコーティングでの反射
+  coat: GGXMaterial,
+}
+// ディラックのデルタ関数
+const DELTA_FUNC: f32 = 1.0;
+
+impl CoatedMaterial {
+  pub fn new(base: Arc<Material + Send + Sync>, ior: f32, roughness: f32, tint: Vector3) -> CoatedMaterial {
+    CoatedMaterial {
+      base: base,
+      ior: ior,
+      roughness: roughness,
+      tint: tint,
+      coat: GGXMaterial {
+        reflectance: Arc::new(ConstantTexture { value: Vector3::new(1.0, 1.0, 1.0) }),
+        roughness: Arc::new(ConstantTexture { value: Vector3::new(roughness, roughness, roughness) }),
+        ior: ior,
+      },
+    }
+  }
+
+  fn is_smooth(&self) -> bool {
+    self.roughness <= 0.0
+  }
+
+  fn fresnel(&self, cos: f32) -> f32 {
+    // Fresnelの式(Schlickの近似)
+    let f_0 = (1.0 - self.ior).powi(2) / (1.0 + self.ior).powi(2);
+    f_0 + (1.0 - f_0) * (1.0 - cos.max(0.0).min(1.0)).powi(5)
+  }
+
+  fn coat_transmittance(&self, out_: Vector3, in_: Vector3, on: Vector3) -> Vector3 {
+    // コーティング内で屈折した方向のcos
+    let refracted_cos = |cos: f32| {
+      (1.0 - (1.0 - cos * cos) / (self.ior * self.ior)).max(0.0).sqrt()
+    };
+    // 入射と出射でコーティング内を通過する光路長 (垂直入射の往復を1とする)
+    let distance = 0.5 / refracted_cos(out_.dot(on)) + 0.5 / refracted_cos(in_.dot(on));
+    Vector3::new(
+      self.tint.x.powf(distance),
+      self.tint.y.powf(distance),
+      self.tint.z.powf(distance),
+    )
+  }
+
+  fn is_mirror(&self, out_: Vector3, in_: Vector3, on: Vector3) -> bool {
+    (out_.reflect(on) - in_).sqr_norm() < 1e-6
+  }
+
+  fn base_brdf(&self, out_: Vector3, in_: Vector3, n: Vector3, point: &SurfacePoint) -> Vector3 {
+    let on = self.orienting_normal(out_, n);
+    if in_.dot(on) <= 0.0 { return Vector3::zero() }
+    // コーティングを透過して下地に入射し, 再びコーティングを透過して出射する
+    let t = (1.0 - self.fresnel(out_.dot(on))) * (1.0 - self.fresnel(in_.dot(on)));
+    self.base.brdf(out_, in_, n, point) * self.coat_transmittance(out_, in_, on) * t
+  }
+}
+
+impl Material for CoatedMaterial {
+  fn opacity(&self, point: &SurfacePoint) -> f32 {
+    self.base.opacity(point)
+  }
+
+  fn has_opacity(&self) -> bool {
+    self.base.has_opacity()
+  }
+
+  fn normal(&self, point: &SurfacePoint) -> Vector3 {
+    // 塗膜の下の法線マップ, バンプマップを反映する
+    self.base.normal(point)
+  }
+
+  fn orienting_normal(&self, out_: Vector3, normal: Vector3) -> Vector3 {
+    // 物体の内外を考慮した法線方向から拡散反射面としての法線方向を求める
+    if normal.dot(out_) < 0.0 {
+      normal * -1.0
+    } else {
+      normal
+    }
+  }
+
+  fn emission(&self, point: &SurfacePoint) -> Vector3 {
+    self.base.emission(point)
+  }
+
+  fn directional_emission(&self, point: &SurfacePoint, out_: Vector3) -> Vector3 {
+    self.base.directional_emission(point, out_)
+  }
+
+  fn max_emission(&self) -> Vector3 {
+    self.base.max_emission()
+  }
+
+  fn two_sided(&self) -> bool {
+    self.base.two_sided()
+  }
+
+  fn weight(&self) -> f32 {
+    // 下地とコーティングの反射率のうち大きいものをつかう
+    self.base.weight().max(self.fresnel(1.0))
+  }
+
+  fn brdf(&self, out_: Vector3, in_: Vector3, n: Vector3, point: &SurfacePoint) -> Vector3 {
+    let on = self.orienting_normal(out_, n);
+    if self.is_smooth() {
+      if self.is_mirror(out_, in_, on) {
+        // コーティングでの鏡面反射
+        Vector3::new(1.0, 1.0, 1.0) * DELTA_FUNC / in_.dot(on) * self.fresnel(out_.dot(on))
+      } else {
+        self.base_brdf(out_, in_, n, point)
+      }
+    } else {
+      self.coat.brdf(out_, in_, n, point) + self.base_brdf(out_, in_, n, point)
+    }
+  }
+
+  fn sample(&self, out_: Vector3, n: Vector3, point: &SurfacePoint) -> Sample<Vector3> {
+    let on = self.orienting_normal(out_, n);
+    // コーティングで反射する確率でどちらの層をサンプリングするか選ぶ
+    let coat_prob = self.fresnel(out_.dot(on));
+    if self.is_smooth() {
+      if rand::random::<f32>() < coat_prob {
+        Sample {
+          value: out_.reflect(on),
+          pdf: DELTA_FUNC * coat_prob,
+        }
+      } else {
+        let sample = self.base.sample(out_, n, point);
+        Sample {
+          value: sample.value,
+          pdf: sample.pdf * (1.0 - coat_prob),
+        }
+      }
+    } else {
+      let in_ = if rand::random::<f32>() < coat_prob {
+        self.coat.sample(out_, n, point).value
+      } else {
+        self.base.sample(out_, n, point).value
+      };
+      Sample {
+        value: in_,
+        pdf: self.pdf(out_, in_, n, point),
+      }
+    }
+  }
+
+  fn pdf(&self, out_: Vector3, in_: Vector3, n: Vector3, point: &SurfacePoint) -> f32 {
+    let on = self.orienting_normal(out_, n);
+    let coat_prob = self.fresnel(out_.dot(on));
+    if self.is_smooth() {
+      if self.is_mirror(out_, in_, on) {
+        DELTA_FUNC * coat_prob
+      } else {
+        self.base.pdf(out_, in_, n, point) * (1.0 - coat_prob)
+      }
+    } else {
+      // 両方の層のサンプリング方法を確率で混合した確率密度
+      self.coat.pdf(out_, in_, n, point) * coat_prob + self.base.pdf(out_, in_, n, point) * (1.0 - coat_prob)
+    }
+  }
+
+  fn is_delta(&self, out_: Vector3, in_: Vector3, n: Vector3) -> bool {
+    let on = self.orienting_normal(out_, n);
+    if self.is_smooth() && self.is_mirror(out_, in_, on) {
+      true
+    } else {
+      self.base.is_delta(out_, in_, n)
+    }
+  }
+
+  fn select(&self, point: &SurfacePoint) -> Option<Arc<Material + Send + Sync>> {
+    self.base.select(point).map( |base| {
+      Arc::new(CoatedMaterial::new(base, self.ior, self.roughness, self.tint)) as Arc<Material + Send + Sync>
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use material::material::*;
+  use constant::*;
+
+  fn coated(roughness: f32) -> CoatedMaterial {
+    let base = Arc::new(LambertianMaterial {
+      emission: Vector3::zero(),
+      albedo: Arc::new(ConstantTexture { value: Vector3::new(0.5, 0.5, 0.5) }),
+    });
+    CoatedMaterial::new(base, 1.5, roughness, Vector3::new(1.0, 1.0, 1.0))
+  }
+
+  #[test]
+  fn smooth_mirror_pdf_test() {
+    let point = SurfacePoint::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0), (0.0, 0.0));
+    let mat = coated(0.0);
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    let out_ = Vector3::new(1.0, 0.0, 1.0).normalize();
+    let in_ = out_.reflect(n);
+    let expect = mat.fresnel(out_.dot(n));
+    assert!((mat.pdf(out_, in_, n, &point) - expect).abs() < EPS);
+  }
+
+  #[test]
+  fn rough_sample_pdf_test() {
+    let point = SurfacePoint::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0), (0.0, 0.0));
+    let mat = coated(0.3);
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    let out_ = Vector3::new(1.0, 0.0, 1.0).normalize();
+    for _ in 0..100 {
+      let sample = mat.sample(out_, n, &point);
+      assert!((sample.pdf - mat.pdf(out_, sample.value, n, &point)).abs() < EPS);
+    }
+  }
+
+  #[test]
+  fn tint_absorption_test() {
+    let mut mat = coated(0.0);
+    mat.tint = Vector3::new(0.5, 0.5, 0.5);
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    // 垂直入射の往復で透過率がtintと等しくなる
+    let t = mat.coat_transmittance(n, n, n);
+    assert!((t - mat.tint).norm() < EPS);
+  }
+}
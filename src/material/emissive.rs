@@ -0,0 +1,162 @@
+use std::sync::Arc;
+use super::traits::Material;
+use math::vector::*;
+use intersection::SurfacePoint;
+use sample::Sample;
+use medium::Medium;
+use texture::texture::Texture;
+use ies::IesProfile;
+
+pub struct EmissiveMaterial {
+  // 放射以外の反射・透過を担うマテリアル
+  pub base: Arc<Material + Send + Sync>,
+  // 放射の色 (下地のマテリアルの放射を置き換える)
+  pub emission: Arc<Texture + Send + Sync>,
+  // 放射の倍率
+  pub scale: f32,
+  // 裏面からも放射するか
+  pub two_sided: bool,
+  // 配光と光源全体の面積 (法線方向を照射軸とする, 放射は光度 (cd) に掛ける倍率になる)
+  pub profile: Option<(Arc<IesProfile>, f32)>,
+}
+
+// 配光を放射輝度にするときの放射面のcosの下限
+const MIN_EMITTER_COS: f32 = 1e-2;
+
+impl EmissiveMaterial {
+  // 照射軸の方向の放射輝度の倍率 (配光がなければ1)
+  fn peak_factor(&self) -> f32 {
+    match self.profile {
+      Some((ref profile, area)) => profile.max() / area,
+      None => 1.0,
+    }
+  }
+}
+
+impl Material for EmissiveMaterial {
+  fn emission(&self, point: &SurfacePoint) -> Vector3 {
+    // 配光があれば照射軸の方向への放射
+    self.emission.value(point) * self.scale * self.peak_factor()
+  }
+
+  fn directional_emission(&self, point: &SurfacePoint, out_: Vector3) -> Vector3 {
+    let emission = self.emission.value(point) * self.scale;
+    match self.profile {
+      Some((ref profile, area)) => {
+        // 裏面から放射する場合は裏向きの法線を照射軸とする
+        let axis = if self.two_sided && out_.dot(point.normal) < 0.0 { -point.normal } else { point.normal };
+        // 光源全体の光度を見かけの面積で割って放射輝度にする
+        let cos = out_.normalize().dot(axis).max(MIN_EMITTER_COS);
+        emission * profile.value_in(out_, axis, point.tangent) / (area * cos)
+      },
+      None => emission,
+    }
+  }
+
+  fn max_emission(&self) -> Vector3 {
+    self.emission.max_value() * self.scale * self.peak_factor()
+  }
+
+  fn two_sided(&self) -> bool {
+    self.two_sided
+  }
+
+  fn opacity(&self, point: &SurfacePoint) -> f32 {
+    self.base.opacity(point)
+  }
+
+  fn has_opacity(&self) -> bool {
+    self.base.has_opacity()
+  }
+
+  fn normal(&self, point: &SurfacePoint) -> Vector3 {
+    self.base.normal(point)
+  }
+
+  fn orienting_normal(&self, out_: Vector3, normal: Vector3) -> Vector3 {
+    self.base.orienting_normal(out_, normal)
+  }
+
+  fn weight(&self) -> f32 {
+    self.base.weight()
+  }
+
+  fn brdf(&self, out_: Vector3, in_: Vector3, n: Vector3, point: &SurfacePoint) -> Vector3 {
+    self.base.brdf(out_, in_, n, point)
+  }
+
+  fn sample(&self, out_: Vector3, n: Vector3, point: &SurfacePoint) -> Sample<Vector3> {
+    self.base.sample(out_, n, point)
+  }
+
+  fn pdf(&self, out_: Vector3, in_: Vector3, n: Vector3, point: &SurfacePoint) -> f32 {
+    self.base.pdf(out_, in_, n, point)
+  }
+
+  fn coef(&self, out_: Vector3, n: Vector3, fly_distance: f32, point: &SurfacePoint) -> Vector3 {
+    self.base.coef(out_, n, fly_distance, point)
+  }
+
+  fn is_delta(&self, out_: Vector3, in_: Vector3, n: Vector3) -> bool {
+    self.base.is_delta(out_, in_, n)
+  }
+
+  fn transmittance(&self, direction: Vector3, n: Vector3, point: &SurfacePoint) -> Option<Vector3> {
+    self.base.transmittance(direction, n, point)
+  }
+
+  fn medium(&self, out_: Vector3, n: Vector3) -> Option<Medium> {
+    self.base.medium(out_, n)
+  }
+
+  fn select(&self, point: &SurfacePoint) -> Option<Arc<Material + Send + Sync>> {
+    self.base.select(point).map( |base| Arc::new(EmissiveMaterial {
+      base: base,
+      emission: self.emission.clone(),
+      scale: self.scale,
+      two_sided: self.two_sided,
+      profile: self.profile.clone(),
+    }) as Arc<Material + Send + Sync> )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use material::material::*;
+  use texture::texture::*;
+  use constant::*;
+
+  #[test]
+  fn profile_test() {
+    // 軸対称で鉛直角について 100cd から 0cd に線形に減る配光
+    let profile = IesProfile::parse("TILT=NONE\n1 1000 1 2 1 1 2 0 0 0\n1 1 50\n0 90\n0\n100 0\n").unwrap();
+    // 0.5m x 0.2m の面光源
+    let (width, height) = (0.5, 0.2);
+    let material = EmissiveMaterial {
+      base: Arc::new(LambertianMaterial {
+        emission: Vector3::zero(),
+        albedo: Arc::new(ConstantTexture { value: Vector3::zero() }),
+      }),
+      emission: Arc::new(ConstantTexture { value: Vector3::new(1.0, 1.0, 1.0) }),
+      scale: 2.0,
+      two_sided: false,
+      profile: Some((Arc::new(profile), width * height)),
+    };
+    // 面全体で放射輝度 x cos を積分した光度は倍率を掛けた配光の光度に一致する
+    let n = 16;
+    let intensity = |direction: Vector3| {
+      (0..n * n).map( |k| {
+        let position = Vector3::new(((k % n) as f32 + 0.5) / n as f32 * width, ((k / n) as f32 + 0.5) / n as f32 * height, 0.0);
+        let point = SurfacePoint::new(position, Vector3::new(0.0, 0.0, 1.0), (0.0, 0.0));
+        material.directional_emission(&point, direction).x * direction.z
+      }).sum::<f32>() * width * height / (n * n) as f32
+    };
+    let axis = intensity(Vector3::new(0.0, 0.0, 1.0));
+    assert!((axis - 200.0).abs() < 1e-2, "{}", axis);
+    let oblique = intensity(Vector3::new(3f32.sqrt(), 0.0, 1.0).normalize());
+    assert!((oblique - 2.0 * 100.0 / 3.0).abs() < 1e-2, "{}", oblique);
+    // 照射軸の方向の放射輝度
+    assert!((material.max_emission().x - 200.0 / (width * height)).abs() < 1e-2);
+  }
+}
@@ -1,38 +1,43 @@
 extern crate rand;
 
+use std::sync::Arc;
 use super::traits::Material;
 use math::vector::*;
+use intersection::SurfacePoint;
 use sample::Sample;
+use texture::texture::Texture;
 use constant::*;
 use util::OrthonormalBasis;
 
 pub struct GGXMaterial {
   // 反射率
-  pub reflectance: Vector3,
+  pub reflectance: Arc<Texture + Send + Sync>,
   // 屈折率
   pub ior: f32,
   // ラフネス
-  pub roughness: f32,
+  pub roughness: Arc<Texture + Send + Sync>,
 }
 
 impl GGXMaterial {
-  fn alpha(&self) -> f32 {
-    self.roughness * self.roughness
+  fn alpha(&self, point: &SurfacePoint) -> f32 {
+    // ラフネスはテクスチャの1チャンネル目をつかう
+    let roughness = self.roughness.value(point).x;
+    roughness * roughness
   }
 
-  fn gaf_smith(&self, out_: Vector3, in_: Vector3, n: Vector3) -> f32 {
-    self.g_ggx(in_, n) * self.g_ggx(out_, n)
+  fn gaf_smith(&self, out_: Vector3, in_: Vector3, n: Vector3, alpha: f32) -> f32 {
+    self.g_ggx(in_, n, alpha) * self.g_ggx(out_, n, alpha)
   }
 
-  fn g_ggx(&self, v: Vector3, n: Vector3) -> f32 {
-    let a2 = self.alpha() * self.alpha();
+  fn g_ggx(&self, v: Vector3, n: Vector3, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
     let cos = v.dot(n);
     let tan = 1.0 / (cos * cos) - 1.0;
     2.0 / (1.0 + (1.0 + a2 * tan * tan).sqrt())
   }
 
-  fn ndf(&self, m: Vector3, n: Vector3) -> f32 {
-    let a2 = self.alpha() * self.alpha();
+  fn ndf(&self, m: Vector3, n: Vector3, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
     let mdn = m.dot(n);
     let x = (a2 - 1.0) * mdn * mdn + 1.0;
     a2 / (PI * x * x)
@@ -57,34 +62,34 @@ impl Material for GGXMaterial {
     }
   }
 
-  fn emission(&self) -> Vector3 {
+  fn emission(&self, _point: &SurfacePoint) -> Vector3 {
     Vector3::zero()
   }
 
   fn weight(&self) -> f32 {
     // 反射率のうち最大のものをつかう
-    self.reflectance.x.max(self.reflectance.y).max(
-      self.reflectance.z,
-    )
+    let reflectance = self.reflectance.max_value();
+    reflectance.x.max(reflectance.y).max(reflectance.z)
   }
 
-  fn brdf(&self, out_: Vector3, in_: Vector3, n: Vector3, _pos: Vector3) -> Vector3 {
+  fn brdf(&self, out_: Vector3, in_: Vector3, n: Vector3, point: &SurfacePoint) -> Vector3 {
     let on = self.orienting_normal(out_, n);
     if in_.dot(on) <= 0.0 { return Vector3::zero() }
     debug_assert!(out_.dot(on) > 0.0, "o.n  = {}", out_.dot(on));
+    let alpha = self.alpha(point);
     // ハーフベクトル
     let h = (in_ + out_).normalize();
     // Torrance-Sparrow model
     let f = self.fresnel_schlick(in_, h);
     debug_assert!(f >= 0.0 && f <= 1.0 && f.is_finite(), "f: {}", f);
-    let g = self.gaf_smith(out_, in_, on);
+    let g = self.gaf_smith(out_, in_, on, alpha);
     debug_assert!(g >= 0.0 && g <= 1.0 && g.is_finite(), "g: {}", g);
-    let d = self.ndf(h, on);
+    let d = self.ndf(h, on, alpha);
     debug_assert!(d >= 0.0 && d.is_finite() , "d: {}", d);
-    self.reflectance * f * g * d / (4.0 * in_.dot(on) * out_.dot(on))
+    self.reflectance.value(point) * f * g * d / (4.0 * in_.dot(on) * out_.dot(on))
   }
 
-  fn sample(&self, out_: Vector3, n: Vector3) -> Sample<Vector3> {
+  fn sample(&self, out_: Vector3, n: Vector3, point: &SurfacePoint) -> Sample<Vector3> {
     let on = self.orienting_normal(out_, n);
     // 法線方向を基準にした正規直交基底を生成
     let w = on;
@@ -93,7 +98,8 @@ impl Material for GGXMaterial {
     // (brdfの分布にしたがって重点的にサンプル)
     let r1 = 2.0 * PI * rand::random::<f32>();
     let r2 = rand::random::<f32>();
-    let tan = self.alpha() * (r2 / (1.0 - r2)).sqrt();
+    let alpha = self.alpha(point);
+    let tan = alpha * (r2 / (1.0 - r2)).sqrt();
     let x = 1.0 + tan * tan;
     let cos = 1.0 / x.sqrt();
     let sin = tan / x.sqrt();
@@ -105,10 +111,20 @@ impl Material for GGXMaterial {
     // ヤコビアン
     let jacobian = 1.0 / (4.0 * o_h);
     // 確率密度関数
-    let pdf = self.ndf(h, on) * h.dot(on) * jacobian;
+    let pdf = self.ndf(h, on, alpha) * h.dot(on) * jacobian;
     Sample {
       value: in_,
       pdf: pdf,
     }
   }
+
+  fn pdf(&self, out_: Vector3, in_: Vector3, n: Vector3, point: &SurfacePoint) -> f32 {
+    let on = self.orienting_normal(out_, n);
+    if in_.dot(on) <= 0.0 { return 0.0 }
+    // ハーフベクトル
+    let h = (in_ + out_).normalize();
+    // ヤコビアン
+    let jacobian = 1.0 / (4.0 * out_.dot(h));
+    self.ndf(h, on, self.alpha(point)) * h.dot(on) * jacobian
+  }
 }
@@ -1,5 +1,6 @@
 use super::traits::Material;
 use math::vector::*;
+use intersection::SurfacePoint;
 use sample::Sample;
 use util::{BoundaryResponse};
 
@@ -23,7 +24,7 @@ impl Material for IdealRefractionMaterial {
     }
   }
 
-  fn emission(&self) -> Vector3 {
+  fn emission(&self, _point: &SurfacePoint) -> Vector3 {
     Vector3::zero()
   }
 
@@ -36,7 +37,7 @@ impl Material for IdealRefractionMaterial {
       .max(self.reflectance.z)
   }
 
-  fn brdf(&self, out_: Vector3, in_: Vector3, n: Vector3, _pos: Vector3) -> Vector3 {
+  fn brdf(&self, out_: Vector3, in_: Vector3, n: Vector3, _point: &SurfacePoint) -> Vector3 {
     // out_: 入射
     // in_: 出射(透過/反射)
     // n: surface normal
@@ -50,22 +51,22 @@ impl Material for IdealRefractionMaterial {
         let fr = Self::fresnel(from_ior, to_ior, out_, r, on);
         if in_.dot(on) > 0.0 {
           // 反射
-          self.reflectance * DELTA_FUNC / in_.dot(n) * fr
+          self.reflectance * DELTA_FUNC / in_.dot(n).abs() * fr
         } else {
           // 透過率
           let ft = (1.0 - fr) * (to_ior / from_ior).powi(2);
           // ロシアンルーレットで反射と屈折のどちらかの寄与を取る
-          self.reflectance * DELTA_FUNC / in_.dot(n) * ft
+          self.reflectance * DELTA_FUNC / in_.dot(n).abs() * ft
         }
       },
       None => {
         // 全反射
-        self.reflectance * DELTA_FUNC / in_.dot(n)
+        self.reflectance * DELTA_FUNC / in_.dot(n).abs()
       }
     }
   }
 
-  fn sample(&self, out_: Vector3, n: Vector3) -> Sample<Vector3> {
+  fn sample(&self, out_: Vector3, n: Vector3, _point: &SurfacePoint) -> Sample<Vector3> {
     // out_: 入射
     // n: surface normal
     let (from_ior, to_ior) = self.ior_pair(out_, n);
@@ -103,7 +104,29 @@ impl Material for IdealRefractionMaterial {
     }
   }
 
-  fn coef(&self, out_: Vector3, n: Vector3, fly_distance: f32) -> Vector3 {
+  fn pdf(&self, out_: Vector3, in_: Vector3, n: Vector3, _point: &SurfacePoint) -> f32 {
+    let (from_ior, to_ior) = self.ior_pair(out_, n);
+    let on = self.orienting_normal(out_, n);
+    match out_.refract(on, from_ior / to_ior) {
+      Some(r) => {
+        let fr = Self::fresnel(from_ior, to_ior, out_, r, on);
+        if in_.dot(on) > 0.0 {
+          // 反射
+          DELTA_FUNC * fr
+        } else {
+          // 透過
+          DELTA_FUNC * (1.0 - fr)
+        }
+      },
+      None => DELTA_FUNC,
+    }
+  }
+
+  fn is_delta(&self, _out_: Vector3, _in_: Vector3, _n: Vector3) -> bool {
+    true
+  }
+
+  fn coef(&self, out_: Vector3, n: Vector3, fly_distance: f32, _point: &SurfacePoint) -> Vector3 {
     if out_.dot(n) < 0.0 {
       let v = -(Vector3::new(1.0, 1.0, 1.0) - self.reflectance) * self.absorbtance * fly_distance;
       Vector3::new(v.x.exp(), v.y.exp(), v.z.exp())
@@ -135,7 +158,7 @@ impl IdealRefractionMaterial {
     (from_ior, to_ior)
   }
 
-  fn fresnel(from_ior: f32, to_ior: f32, out_: Vector3, in_: Vector3, on: Vector3) -> f32 {
+  pub fn fresnel(from_ior: f32, to_ior: f32, out_: Vector3, in_: Vector3, on: Vector3) -> f32 {
     // out_: 入射
     // in_: 透過
     // on: orienting normal
@@ -196,6 +219,7 @@ mod tests {
 
   #[test]
   fn brdf_reflecting_test() {
+    let point = SurfacePoint::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0), (0.0, 0.0));
     let mat = IdealRefractionMaterial {
       reflectance: Vector3::new(1.0, 1.0, 1.0),
       absorbtance: 0.0,
@@ -204,11 +228,11 @@ mod tests {
     let n = Vector3::new(0.0, 0.0, -1.0);
     let out_ = Vector3::new(1.0, 0.0, 1.0).normalize();
     let on = mat.orienting_normal(out_, n);
-    let in_ = mat.sample(out_, n).value;
+    let in_ = mat.sample(out_, n, &point).value;
     let expect = out_.reflect(on);
     assert!((expect - in_).norm() < EPS);
-    let brdf = mat.brdf(out_, in_, n, Vector3::zero());
-    let expect_brdf = Vector3::new(1.0, 1.0, 1.0) / in_.dot(n);
+    let brdf = mat.brdf(out_, in_, n, &point);
+    let expect_brdf = Vector3::new(1.0, 1.0, 1.0) / in_.dot(n).abs();
     assert!((expect_brdf - brdf).norm() < EPS);
   }
 
@@ -300,6 +324,7 @@ mod tests {
 
   #[test]
   fn sample_test() {
+    let point = SurfacePoint::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0), (0.0, 0.0));
     let mat = IdealRefractionMaterial {
       reflectance: Vector3::new(1.0, 1.0, 1.0),
       absorbtance: 0.0,
@@ -307,7 +332,7 @@ mod tests {
     };
     let n = Vector3::new(0.0, 0.0, -1.0);
     let out_ = Vector3::new(1.0, 0.0, 1.0).normalize();
-    let in_ = mat.sample(out_, n).value;
+    let in_ = mat.sample(out_, n, &point).value;
     assert!((in_.norm() - 1.0).abs() < EPS);
   }
 }
@@ -1,13 +1,16 @@
+use std::sync::Arc;
 use super::traits::Material;
 use math::vector::*;
+use intersection::SurfacePoint;
 use sample::Sample;
+use texture::texture::Texture;
 use constant::*;
 use util::{Sampler, OrthonormalBasis};
 
 pub struct LambertianMaterial {
   pub emission: Vector3,
   // 拡散反射率
-  pub albedo: Vector3,
+  pub albedo: Arc<Texture + Send + Sync>,
 }
 
 impl Material for LambertianMaterial {
@@ -20,21 +23,29 @@ impl Material for LambertianMaterial {
     }
   }
 
-  fn emission(&self) -> Vector3 {
+  fn emission(&self, _point: &SurfacePoint) -> Vector3 {
+    self.emission
+  }
+
+  fn max_emission(&self) -> Vector3 {
     self.emission
   }
 
   fn weight(&self) -> f32 {
     // 拡散反射の時は各色の反射率のうち最大のものを使う
-    self.albedo.x.max(self.albedo.y).max(self.albedo.z)
+    let albedo = self.albedo.max_value();
+    albedo.x.max(albedo.y).max(albedo.z)
   }
 
-  fn brdf(&self, _out_: Vector3, _in_: Vector3, _n_: Vector3, pos: Vector3) -> Vector3 {
+  fn brdf(&self, out_: Vector3, in_: Vector3, n: Vector3, point: &SurfacePoint) -> Vector3 {
+    let on = self.orienting_normal(out_, n);
+    if in_.dot(on) <= 0.0 { return Vector3::zero() }
     // BRDFは半球全体に一様に散乱するDiffuse面を考えると ρ / π
-    self.albedo * checker((pos.x, pos.z)) / PI
+    let pos = point.position;
+    self.albedo.value(point) * checker((pos.x, pos.z)) / PI
   }
 
-  fn sample(&self, out_: Vector3, n: Vector3) -> Sample<Vector3> {
+  fn sample(&self, out_: Vector3, n: Vector3, _point: &SurfacePoint) -> Sample<Vector3> {
     // 反射点での法線方向を基準にした正規直交基底を生成
     let on = self.orienting_normal(out_, n);
     let w = on;
@@ -44,7 +55,7 @@ impl Material for LambertianMaterial {
     let sample = Sampler::hemisphere_cos_importance();
     let in_ = u * sample.x + v * sample.y + w * sample.z;
     // cos項
-    let cos_term = in_.dot(n);
+    let cos_term = in_.dot(on);
     // 確率密度関数
     // (cosにしたがって重点的にサンプル) cosθ / π
     let pdf = cos_term / PI;
@@ -53,6 +64,12 @@ impl Material for LambertianMaterial {
       pdf: pdf,
     }
   }
+
+  fn pdf(&self, out_: Vector3, in_: Vector3, n: Vector3, _point: &SurfacePoint) -> f32 {
+    let on = self.orienting_normal(out_, n);
+    // (cosにしたがって重点的にサンプル) cosθ / π
+    in_.dot(on).max(0.0) / PI
+  }
 }
 
 fn signed_mod(base: f32, module: f32) -> f32 {
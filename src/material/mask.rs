@@ -0,0 +1,87 @@
+use std::sync::Arc;
+use super::traits::Material;
+use math::vector::*;
+use intersection::SurfacePoint;
+use sample::Sample;
+use medium::Medium;
+use texture::texture::Texture;
+
+pub struct MaskMaterial {
+  // 切り抜くマテリアル
+  pub base: Arc<Material + Send + Sync>,
+  // 不透明度のテクスチャ (x成分をつかう, 0のとき完全に透明)
+  pub opacity: Arc<Texture + Send + Sync>,
+}
+
+impl Material for MaskMaterial {
+  fn opacity(&self, point: &SurfacePoint) -> f32 {
+    self.opacity.value(point).x.max(0.0).min(1.0) * self.base.opacity(point)
+  }
+
+  fn has_opacity(&self) -> bool {
+    true
+  }
+
+  fn normal(&self, point: &SurfacePoint) -> Vector3 {
+    self.base.normal(point)
+  }
+
+  fn orienting_normal(&self, out_: Vector3, normal: Vector3) -> Vector3 {
+    self.base.orienting_normal(out_, normal)
+  }
+
+  fn emission(&self, point: &SurfacePoint) -> Vector3 {
+    self.base.emission(point)
+  }
+
+  fn directional_emission(&self, point: &SurfacePoint, out_: Vector3) -> Vector3 {
+    self.base.directional_emission(point, out_)
+  }
+
+  fn max_emission(&self) -> Vector3 {
+    self.base.max_emission()
+  }
+
+  fn two_sided(&self) -> bool {
+    self.base.two_sided()
+  }
+
+  fn weight(&self) -> f32 {
+    self.base.weight()
+  }
+
+  fn brdf(&self, out_: Vector3, in_: Vector3, n: Vector3, point: &SurfacePoint) -> Vector3 {
+    self.base.brdf(out_, in_, n, point)
+  }
+
+  fn sample(&self, out_: Vector3, n: Vector3, point: &SurfacePoint) -> Sample<Vector3> {
+    self.base.sample(out_, n, point)
+  }
+
+  fn pdf(&self, out_: Vector3, in_: Vector3, n: Vector3, point: &SurfacePoint) -> f32 {
+    self.base.pdf(out_, in_, n, point)
+  }
+
+  fn coef(&self, out_: Vector3, n: Vector3, fly_distance: f32, point: &SurfacePoint) -> Vector3 {
+    self.base.coef(out_, n, fly_distance, point)
+  }
+
+  fn is_delta(&self, out_: Vector3, in_: Vector3, n: Vector3) -> bool {
+    self.base.is_delta(out_, in_, n)
+  }
+
+  fn transmittance(&self, direction: Vector3, n: Vector3, point: &SurfacePoint) -> Option<Vector3> {
+    self.base.transmittance(direction, n, point)
+  }
+
+  fn medium(&self, out_: Vector3, n: Vector3) -> Option<Medium> {
+    self.base.medium(out_, n)
+  }
+
+  fn select(&self, point: &SurfacePoint) -> Option<Arc<Material + Send + Sync>> {
+    self.base.select(point).map( |base| Arc::new(MaskMaterial {
+      base: base,
+      opacity: self.opacity.clone(),
+    }) as Arc<Material + Send + Sync> )
+  }
+}
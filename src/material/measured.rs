@@ -0,0 +1,235 @@
+extern crate rand;
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+use super::traits::Material;
+use math::vector::*;
+use intersection::SurfacePoint;
+use sample::Sample;
+use constant::*;
+use util::{Sampler, OrthonormalBasis};
+use distribution::Distribution1D;
+
+// MERL BRDF データベースの分解能
+const THETA_H_RES: usize = 90;
+const THETA_D_RES: usize = 90;
+const PHI_D_RES: usize = 180;
+const TABLE_SIZE: usize = THETA_H_RES * THETA_D_RES * PHI_D_RES;
+// RGBそれぞれの値の倍率
+const SCALE: [f32; 3] = [1.0 / 1500.0, 1.15 / 1500.0, 1.66 / 1500.0];
+// ハーフベクトルの分布でサンプリングする確率
+const HALF_VECTOR_PROB: f32 = 0.5;
+// 反射率を求めるときの格子の分解能
+const ALBEDO_RES: usize = 32;
+
+pub struct MeasuredMaterial {
+  // RGBの順に並んだ測定値
+  table: Vec<f32>,
+  // ハーフベクトルの天頂角の分布
+  theta_half: Distribution1D,
+  // 垂直入射での反射率 (RGBのうち最大のもの)
+  albedo: f32,
+}
+
+impl MeasuredMaterial {
+  pub fn load(path: &str) -> MeasuredMaterial {
+    let file = File::open(path).expect(&format!("File `{}` is not found.", path));
+    let mut bytes = Vec::new();
+    BufReader::new(file).read_to_end(&mut bytes).unwrap();
+    let read = |offset: usize, size: usize| {
+      bytes[offset..offset + size].iter().rev().fold(0u64, |acc, &b| (acc << 8) | b as u64)
+    };
+    // ヘッダは各軸の分解能
+    let dims = (0..3).map( |i| read(i * 4, 4) as usize ).collect::<Vec<_>>();
+    if dims[0] * dims[1] * dims[2] != TABLE_SIZE || bytes.len() < 12 + TABLE_SIZE * 3 * 8 {
+      panic!(format!("File `{}` is not a MERL BRDF.", path))
+    }
+    let table = (0..TABLE_SIZE * 3).map( |i| {
+      let v = f64::from_bits(read(12 + i * 8, 8)) as f32 * SCALE[i / TABLE_SIZE];
+      // 負の値は測定できなかった点
+      v.max(0.0)
+    }).collect();
+    Self::new(table)
+  }
+
+  pub fn new(table: Vec<f32>) -> MeasuredMaterial {
+    assert!(table.len() == TABLE_SIZE * 3);
+    // ハーフベクトルの天頂角ごとに, 他の軸で平均した反射率の重みを求める
+    let func = (0..THETA_H_RES).map( |h| {
+      let x = (h as f32 + 0.5) / THETA_H_RES as f32;
+      let theta_h = x * x * PI / 2.0;
+      let sum = (0..THETA_D_RES * PHI_D_RES).map( |i| {
+        let index = h * THETA_D_RES * PHI_D_RES + i;
+        (table[index] + table[index + TABLE_SIZE] + table[index + TABLE_SIZE * 2]) / 3.0
+      }).sum::<f32>();
+      // 天頂角の立体角とインデックス空間への変換のヤコビアン
+      sum / (THETA_D_RES * PHI_D_RES) as f32 * theta_h.sin() * x * PI
+    }).collect();
+    let mut material = MeasuredMaterial {
+      table: table,
+      theta_half: Distribution1D::new(func),
+      albedo: 0.0,
+    };
+    material.albedo = material.normal_albedo();
+    material
+  }
+
+  // 垂直入射での半球の反射率を格子点で数値積分する
+  fn normal_albedo(&self) -> f32 {
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    let res = ALBEDO_RES;
+    let sum = (0..res * res).fold(Vector3::zero(), |sum, i| {
+      // cosにしたがう分布の格子点なので brdf * π の平均が反射率になる
+      let (x, y) = (((i % res) as f32 + 0.5) / res as f32, ((i / res) as f32 + 0.5) / res as f32);
+      let r = x.sqrt();
+      let phi = 2.0 * PI * y;
+      let in_ = Vector3::new(r * phi.cos(), r * phi.sin(), (1.0 - x).sqrt());
+      sum + self.lookup(n, in_, n) * PI
+    }) / (res * res) as f32;
+    sum.x.max(sum.y).max(sum.z).min(1.0)
+  }
+
+  fn lookup(&self, out_: Vector3, in_: Vector3, on: Vector3) -> Vector3 {
+    // 法線を基準とした局所座標系
+    let (u, v) = on.orthonormal_basis();
+    let local = |d: Vector3| Vector3::new(d.dot(u), d.dot(v), d.dot(on));
+    let wi = local(in_);
+    let wo = local(out_);
+    // ハーフベクトルと差分ベクトルの座標系に変換 [Rusinkiewicz 1998]
+    let h = (wi + wo).normalize();
+    let theta_h = h.z.max(-1.0).min(1.0).acos();
+    let phi_h = h.y.atan2(h.x);
+    let rotate_z = |d: Vector3, a: f32| Vector3::new(
+      d.x * a.cos() - d.y * a.sin(), d.x * a.sin() + d.y * a.cos(), d.z
+    );
+    let rotate_y = |d: Vector3, a: f32| Vector3::new(
+      d.x * a.cos() + d.z * a.sin(), d.y, -d.x * a.sin() + d.z * a.cos()
+    );
+    let diff = rotate_y(rotate_z(wi, -phi_h), -theta_h);
+    let theta_d = diff.z.max(-1.0).min(1.0).acos();
+    let mut phi_d = diff.y.atan2(diff.x);
+    // 相反性より phi_d と phi_d + π は等しい
+    if phi_d < 0.0 { phi_d += PI }
+    let clamp = |i: f32, res: usize| (i.max(0.0) as usize).min(res - 1);
+    let h_index = clamp((theta_h / (PI / 2.0)).sqrt() * THETA_H_RES as f32, THETA_H_RES);
+    let d_index = clamp(theta_d / (PI / 2.0) * THETA_D_RES as f32, THETA_D_RES);
+    let p_index = clamp(phi_d / PI * PHI_D_RES as f32, PHI_D_RES);
+    let index = p_index + d_index * PHI_D_RES + h_index * PHI_D_RES * THETA_D_RES;
+    Vector3::new(
+      self.table[index],
+      self.table[index + TABLE_SIZE],
+      self.table[index + TABLE_SIZE * 2],
+    )
+  }
+
+  fn half_vector_pdf(&self, h: Vector3, on: Vector3) -> f32 {
+    let theta_h = h.dot(on).max(-1.0).min(1.0).acos();
+    let x = (theta_h / (PI / 2.0)).sqrt();
+    if x <= 0.0 || x >= 1.0 { return 0.0 }
+    // インデックス空間から天頂角, 立体角への変換
+    self.theta_half.pdf_at(x) / (x * PI) / (2.0 * PI * theta_h.sin())
+  }
+}
+
+impl Material for MeasuredMaterial {
+  fn orienting_normal(&self, out_: Vector3, normal: Vector3) -> Vector3 {
+    // 物体の内外を考慮した法線方向から拡散反射面としての法線方向を求める
+    if normal.dot(out_) < 0.0 {
+      normal * -1.0
+    } else {
+      normal
+    }
+  }
+
+  fn emission(&self, _point: &SurfacePoint) -> Vector3 {
+    Vector3::zero()
+  }
+
+  fn weight(&self) -> f32 {
+    self.albedo
+  }
+
+  fn brdf(&self, out_: Vector3, in_: Vector3, n: Vector3, _point: &SurfacePoint) -> Vector3 {
+    let on = self.orienting_normal(out_, n);
+    if in_.dot(on) <= 0.0 { return Vector3::zero() }
+    self.lookup(out_, in_, on)
+  }
+
+  fn sample(&self, out_: Vector3, n: Vector3, point: &SurfacePoint) -> Sample<Vector3> {
+    let on = self.orienting_normal(out_, n);
+    let (u, v) = on.orthonormal_basis();
+    let in_ = if rand::random::<f32>() < HALF_VECTOR_PROB {
+      // 測定値から求めた分布にしたがってハーフベクトルをサンプリング
+      let x = self.theta_half.sample_continuous(rand::random::<f32>()).value;
+      let theta_h = x * x * PI / 2.0;
+      let phi_h = 2.0 * PI * rand::random::<f32>();
+      let h = u * theta_h.sin() * phi_h.cos() + v * theta_h.sin() * phi_h.sin() + on * theta_h.cos();
+      h * (2.0 * out_.dot(h)) - out_
+    } else {
+      // (cosにしたがって重点的にサンプル)
+      let sample = Sampler::hemisphere_cos_importance();
+      u * sample.x + v * sample.y + on * sample.z
+    };
+    Sample {
+      value: in_,
+      pdf: self.pdf(out_, in_, n, point),
+    }
+  }
+
+  fn pdf(&self, out_: Vector3, in_: Vector3, n: Vector3, _point: &SurfacePoint) -> f32 {
+    let on = self.orienting_normal(out_, n);
+    // 入射ベクトルが下側の半球の場合もサンプリングしたハーフベクトルは上側の半球にある
+    let h = (in_ + out_).normalize();
+    let h = if h.dot(on) < 0.0 { -h } else { h };
+    // ヤコビアン (ハーフベクトルが出射方向と直交する場合はその方向にサンプリングされない)
+    let cos_oh = out_.dot(h);
+    let jacobian = if cos_oh > EPS { 1.0 / (4.0 * cos_oh) } else { 0.0 };
+    let half_pdf = self.half_vector_pdf(h, on) * jacobian;
+    let cos_pdf = in_.dot(on).max(0.0) / PI;
+    half_pdf * HALF_VECTOR_PROB + cos_pdf * (1.0 - HALF_VECTOR_PROB)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn constant_table_test() {
+    let point = SurfacePoint::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0), (0.0, 0.0));
+    let mat = MeasuredMaterial::new(vec![1.0 / PI; TABLE_SIZE * 3]);
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    let out_ = Vector3::new(1.0, 0.0, 1.0).normalize();
+    for _ in 0..100 {
+      let sample = mat.sample(out_, n, &point);
+      assert!(sample.pdf >= 0.0 && sample.pdf.is_finite(), "{}", sample.pdf);
+      if sample.value.dot(n) > 0.0 {
+        assert!(sample.pdf > 0.0, "{}", sample.pdf);
+        let brdf = mat.brdf(out_, sample.value, n, &point);
+        assert!((brdf.x - 1.0 / PI).abs() < EPS);
+      }
+    }
+  }
+
+  #[test]
+  fn lookup_index_test() {
+    let point = SurfacePoint::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0), (0.0, 0.0));
+    // 垂直入射と垂直出射はすべての角度が0の要素を参照する
+    let mut table = vec![0.0; TABLE_SIZE * 3];
+    table[0] = 1.0;
+    let mat = MeasuredMaterial::new(table);
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    let brdf = mat.brdf(n, n, n, &point);
+    assert!((brdf.x - 1.0).abs() < EPS, "{}", brdf);
+  }
+
+  #[test]
+  fn albedo_test() {
+    // 完全拡散面の反射率は1, 半分の値なら0.5
+    let mat = MeasuredMaterial::new(vec![1.0 / PI; TABLE_SIZE * 3]);
+    assert!((mat.weight() - 1.0).abs() < 1e-3, "{}", mat.weight());
+    let mat = MeasuredMaterial::new(vec![0.5 / PI; TABLE_SIZE * 3]);
+    assert!((mat.weight() - 0.5).abs() < 1e-3, "{}", mat.weight());
+  }
+}
@@ -0,0 +1,280 @@
+extern crate rand;
+
+use std::sync::Arc;
+use super::traits::Material;
+use math::vector::*;
+use intersection::SurfacePoint;
+use sample::Sample;
+use medium::Medium;
+use texture::texture::Texture;
+
+pub struct MixMaterial {
+  // 混合するマテリアル
+  pub materials: (Arc<Material + Send + Sync>, Arc<Material + Send + Sync>),
+  // 2つめのマテリアルの重み (テクスチャでマスクできる)
+  pub weight: Arc<Texture + Send + Sync>,
+}
+
+impl MixMaterial {
+  fn weight_at(&self, point: &SurfacePoint) -> f32 {
+    self.weight.value(point).x.max(0.0).min(1.0)
+  }
+}
+
+impl Material for MixMaterial {
+  fn opacity(&self, point: &SurfacePoint) -> f32 {
+    let w = self.weight_at(point);
+    self.materials.0.opacity(point) * (1.0 - w) + self.materials.1.opacity(point) * w
+  }
+
+  fn has_opacity(&self) -> bool {
+    self.materials.0.has_opacity() || self.materials.1.has_opacity()
+  }
+
+  fn normal(&self, point: &SurfacePoint) -> Vector3 {
+    let w = self.weight_at(point);
+    (self.materials.0.normal(point) * (1.0 - w) + self.materials.1.normal(point) * w).normalize()
+  }
+
+  fn orienting_normal(&self, out_: Vector3, normal: Vector3) -> Vector3 {
+    // 物体の内外を考慮した法線方向から拡散反射面としての法線方向を求める
+    if normal.dot(out_) < 0.0 {
+      normal * -1.0
+    } else {
+      normal
+    }
+  }
+
+  fn emission(&self, point: &SurfacePoint) -> Vector3 {
+    let w = self.weight_at(point);
+    self.materials.0.emission(point) * (1.0 - w) + self.materials.1.emission(point) * w
+  }
+
+  fn directional_emission(&self, point: &SurfacePoint, out_: Vector3) -> Vector3 {
+    let w = self.weight_at(point);
+    self.materials.0.directional_emission(point, out_) * (1.0 - w) + self.materials.1.directional_emission(point, out_) * w
+  }
+
+  fn max_emission(&self) -> Vector3 {
+    let a = self.materials.0.max_emission();
+    let b = self.materials.1.max_emission();
+    Vector3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z))
+  }
+
+  fn two_sided(&self) -> bool {
+    self.materials.0.two_sided() || self.materials.1.two_sided()
+  }
+
+  fn weight(&self) -> f32 {
+    // 混合の重みは評価点によって変わるので大きい方をつかう
+    self.materials.0.weight().max(self.materials.1.weight())
+  }
+
+  fn brdf(&self, out_: Vector3, in_: Vector3, n: Vector3, point: &SurfacePoint) -> Vector3 {
+    // 両方のBRDFを重みで線形補間する
+    let w = self.weight_at(point);
+    self.materials.0.brdf(out_, in_, n, point) * (1.0 - w) +
+      self.materials.1.brdf(out_, in_, n, point) * w
+  }
+
+  fn sample(&self, out_: Vector3, n: Vector3, point: &SurfacePoint) -> Sample<Vector3> {
+    // 重みにしたがってどちらかのマテリアルを選んでサンプリング
+    let w = self.weight_at(point);
+    let (chosen, other, w_chosen) = if rand::random::<f32>() < w {
+      (&self.materials.1, &self.materials.0, w)
+    } else {
+      (&self.materials.0, &self.materials.1, 1.0 - w)
+    };
+    let sample = chosen.sample(out_, n, point);
+    // 選ばれなかった方のマテリアルでサンプリングされる可能性も考慮した確率密度
+    Sample {
+      value: sample.value,
+      pdf: sample.pdf * w_chosen + other.pdf(out_, sample.value, n, point) * (1.0 - w_chosen),
+    }
+  }
+
+  fn pdf(&self, out_: Vector3, in_: Vector3, n: Vector3, point: &SurfacePoint) -> f32 {
+    let w = self.weight_at(point);
+    self.materials.0.pdf(out_, in_, n, point) * (1.0 - w) +
+      self.materials.1.pdf(out_, in_, n, point) * w
+  }
+
+  fn is_delta(&self, out_: Vector3, in_: Vector3, n: Vector3) -> bool {
+    // デルタ関数の成分を含む混合はselectで成分を選んでから評価する
+    self.materials.0.is_delta(out_, in_, n) && self.materials.1.is_delta(out_, in_, n)
+  }
+
+  fn coef(&self, out_: Vector3, n: Vector3, fly_distance: f32, point: &SurfacePoint) -> Vector3 {
+    let w = self.weight_at(point);
+    self.materials.0.coef(out_, n, fly_distance, point) * (1.0 - w) +
+      self.materials.1.coef(out_, n, fly_distance, point) * w
+  }
+
+  fn transmittance(&self, direction: Vector3, n: Vector3, point: &SurfacePoint) -> Option<Vector3> {
+    // 不透明な成分は透過しないものとして重みで混ぜる
+    let w = self.weight_at(point);
+    match (self.materials.0.transmittance(direction, n, point), self.materials.1.transmittance(direction, n, point)) {
+      (None, None) => None,
+      (a, b) => Some(
+        a.unwrap_or(Vector3::zero()) * (1.0 - w) + b.unwrap_or(Vector3::zero()) * w
+      ),
+    }
+  }
+
+  fn medium(&self, out_: Vector3, n: Vector3) -> Option<Medium> {
+    self.materials.0.medium(out_, n).or_else( || self.materials.1.medium(out_, n) )
+  }
+
+  fn select(&self, point: &SurfacePoint) -> Option<Arc<Material + Send + Sync>> {
+    let chosen = if rand::random::<f32>() < self.weight_at(point) {
+      &self.materials.1
+    } else {
+      &self.materials.0
+    };
+    // 入れ子の混合はさらに選ぶ
+    Some(chosen.select(point).unwrap_or(chosen.clone()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use material::material::*;
+  use texture::texture::*;
+  use constant::*;
+
+  #[test]
+  fn brdf_weight_test() {
+    let point = SurfacePoint::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0), (0.0, 0.0));
+    let white = Arc::new(LambertianMaterial {
+      emission: Vector3::zero(),
+      albedo: Arc::new(ConstantTexture { value: Vector3::new(1.0, 1.0, 1.0) }),
+    });
+    let black = Arc::new(LambertianMaterial {
+      emission: Vector3::zero(),
+      albedo: Arc::new(ConstantTexture { value: Vector3::zero() }),
+    });
+    let mat = MixMaterial {
+      materials: (white, black),
+      weight: Arc::new(ConstantTexture { value: Vector3::new(0.25, 0.25, 0.25) }),
+    };
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    let out_ = Vector3::new(1.0, 0.0, 1.0).normalize();
+    let in_ = Vector3::new(-1.0, 0.0, 1.0).normalize();
+    let brdf = mat.brdf(out_, in_, n, &point);
+    assert!((brdf.x - 0.75 / PI).abs() < EPS, "{}", brdf);
+  }
+
+  #[test]
+  fn sample_pdf_test() {
+    let point = SurfacePoint::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0), (0.0, 0.0));
+    let mat = MixMaterial {
+      materials: (
+        Arc::new(LambertianMaterial {
+          emission: Vector3::zero(),
+          albedo: Arc::new(ConstantTexture { value: Vector3::new(0.5, 0.5, 0.5) }),
+        }),
+        Arc::new(GGXMaterial {
+          reflectance: Arc::new(ConstantTexture { value: Vector3::new(1.0, 1.0, 1.0) }),
+          roughness: Arc::new(ConstantTexture { value: Vector3::new(0.2, 0.2, 0.2) }),
+          ior: 100000.0,
+        }),
+      ),
+      weight: Arc::new(ConstantTexture { value: Vector3::new(0.3, 0.3, 0.3) }),
+    };
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    let out_ = Vector3::new(1.0, 0.0, 1.0).normalize();
+    for _ in 0..100 {
+      // サンプリングした方向の確率密度はpdfで評価した値と一致する
+      let sample = mat.sample(out_, n, &point);
+      if sample.value.dot(n) <= 0.0 {
+        // 面の裏側に外れたマイクロファセットの反射は寄与しない
+        continue
+      }
+      let pdf = mat.pdf(out_, sample.value, n, &point);
+      assert!((sample.pdf - pdf).abs() <= pdf * 1e-3, "{} {}", sample.pdf, pdf);
+    }
+  }
+
+  #[test]
+  fn select_test() {
+    let point = SurfacePoint::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0), (0.0, 0.0));
+    let mat = MixMaterial {
+      materials: (
+        Arc::new(LambertianMaterial {
+          emission: Vector3::zero(),
+          albedo: Arc::new(ConstantTexture { value: Vector3::new(0.5, 0.5, 0.5) }),
+        }),
+        Arc::new(IdealRefractionMaterial {
+          reflectance: Vector3::new(1.0, 1.0, 1.0),
+          absorbtance: 0.0,
+          ior: 1.5,
+        }),
+      ),
+      weight: Arc::new(ConstantTexture { value: Vector3::new(0.25, 0.25, 0.25) }),
+    };
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    let out_ = Vector3::new(1.0, 0.0, 1.0).normalize();
+    // 重みの割合で成分が選ばれ, 選ばれた成分のみがデルタ関数になる
+    let count = 10000;
+    let delta = (0..count).filter( |_| {
+      let lobe = mat.select(&point).unwrap();
+      let sample = lobe.sample(out_, n, &point);
+      lobe.is_delta(out_, sample.value, n)
+    }).count();
+    assert!((delta as f32 / count as f32 - 0.25).abs() < 0.02, "{}", delta);
+    assert!(!mat.is_delta(out_, Vector3::new(-1.0, 0.0, 1.0).normalize(), n));
+  }
+
+  #[test]
+  fn texture_mask_test() {
+    let white = Arc::new(LambertianMaterial {
+      emission: Vector3::zero(),
+      albedo: Arc::new(ConstantTexture { value: Vector3::new(1.0, 1.0, 1.0) }),
+    });
+    let black = Arc::new(LambertianMaterial {
+      emission: Vector3::zero(),
+      albedo: Arc::new(ConstantTexture { value: Vector3::zero() }),
+    });
+    // 左半分が白, 右半分が黒になるマスク
+    let mask = ImageTexture::new(2, 1, vec![Vector3::zero(), Vector3::new(1.0, 1.0, 1.0)]);
+    let mat = MixMaterial {
+      materials: (white, black),
+      weight: Arc::new(mask),
+    };
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    let out_ = Vector3::new(1.0, 0.0, 1.0).normalize();
+    let in_ = Vector3::new(-1.0, 0.0, 1.0).normalize();
+    let left = mat.brdf(out_, in_, n, &SurfacePoint::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0), (0.25, 0.5)));
+    let right = mat.brdf(out_, in_, n, &SurfacePoint::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0), (0.75, 0.5)));
+    assert!((left.x - 1.0 / PI).abs() < EPS, "{}", left);
+    assert!(right.x.abs() < EPS, "{}", right);
+  }
+
+  #[test]
+  fn texture_mask_transmittance_test() {
+    let glass = Arc::new(ThinDielectricMaterial {
+      reflectance: Vector3::new(1.0, 1.0, 1.0),
+      tint: Vector3::new(1.0, 1.0, 1.0),
+      ior: 1.5,
+    });
+    let black = Arc::new(LambertianMaterial {
+      emission: Vector3::zero(),
+      albedo: Arc::new(ConstantTexture { value: Vector3::zero() }),
+    });
+    // 左半分が不透明, 右半分が薄いガラスになるマスク
+    let mask = ImageTexture::new(2, 1, vec![Vector3::zero(), Vector3::new(1.0, 1.0, 1.0)]);
+    let mat = MixMaterial {
+      materials: (black, glass.clone()),
+      weight: Arc::new(mask),
+    };
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    let direction = Vector3::new(0.0, 0.0, -1.0);
+    let left = SurfacePoint::new(Vector3::zero(), n, (0.25, 0.5));
+    let right = SurfacePoint::new(Vector3::zero(), n, (0.75, 0.5));
+    // シャドウレイの透過率は交差した点のマスクにしたがう
+    let expected = glass.transmittance(direction, n, &right).unwrap();
+    assert!(mat.transmittance(direction, n, &left).unwrap().norm() < EPS);
+    assert!((mat.transmittance(direction, n, &right).unwrap() - expected).norm() < EPS);
+  }
+}
@@ -4,6 +4,16 @@ mod phong;
 mod blinn_phong;
 mod ggx;
 mod ideal_refraction;
+mod coated;
+mod mix;
+mod thin_dielectric;
+mod translucent;
+mod subsurface;
+mod measured;
+mod normal_map;
+mod bump;
+mod mask;
+mod emissive;
 
 pub mod material {
   pub use super::traits::*;
@@ -12,4 +22,14 @@ pub mod material {
   pub use super::blinn_phong::*;
   pub use super::ggx::*;
   pub use super::ideal_refraction::*;
+  pub use super::coated::*;
+  pub use super::mix::*;
+  pub use super::thin_dielectric::*;
+  pub use super::translucent::*;
+  pub use super::subsurface::*;
+  pub use super::measured::*;
+  pub use super::normal_map::*;
+  pub use super::bump::*;
+  pub use super::mask::*;
+  pub use super::emissive::*;
 }
@@ -0,0 +1,140 @@
+use std::sync::Arc;
+use super::traits::Material;
+use math::vector::*;
+use intersection::SurfacePoint;
+use sample::Sample;
+use medium::Medium;
+use texture::texture::Texture;
+
+pub struct NormalMapMaterial {
+  // 法線を摂動するマテリアル
+  pub base: Arc<Material + Send + Sync>,
+  // 接空間の法線を [0, 1] に詰めたテクスチャ
+  pub map: Arc<Texture + Send + Sync>,
+  // 凹凸の強さ (接線方向の成分に乗算する)
+  pub strength: f32,
+}
+
+impl Material for NormalMapMaterial {
+  fn normal(&self, point: &SurfacePoint) -> Vector3 {
+    let n = self.base.normal(point);
+    let (t, b) = SurfacePoint { normal: n, ..*point }.tangent_frame();
+    let c = self.map.value(point) * 2.0 - Vector3::new(1.0, 1.0, 1.0);
+    let perturbed = t * (c.x * self.strength) + b * (c.y * self.strength) + n * c.z;
+    if perturbed.sqr_norm() == 0.0 || perturbed.dot(n) <= 0.0 {
+      // 面の裏側を向く法線は使えない
+      return n
+    }
+    perturbed.normalize()
+  }
+
+  fn opacity(&self, point: &SurfacePoint) -> f32 {
+    self.base.opacity(point)
+  }
+
+  fn has_opacity(&self) -> bool {
+    self.base.has_opacity()
+  }
+
+  fn orienting_normal(&self, out_: Vector3, normal: Vector3) -> Vector3 {
+    self.base.orienting_normal(out_, normal)
+  }
+
+  fn emission(&self, point: &SurfacePoint) -> Vector3 {
+    self.base.emission(point)
+  }
+
+  fn directional_emission(&self, point: &SurfacePoint, out_: Vector3) -> Vector3 {
+    self.base.directional_emission(point, out_)
+  }
+
+  fn max_emission(&self) -> Vector3 {
+    self.base.max_emission()
+  }
+
+  fn two_sided(&self) -> bool {
+    self.base.two_sided()
+  }
+
+  fn weight(&self) -> f32 {
+    self.base.weight()
+  }
+
+  fn brdf(&self, out_: Vector3, in_: Vector3, n: Vector3, point: &SurfacePoint) -> Vector3 {
+    self.base.brdf(out_, in_, n, point)
+  }
+
+  fn sample(&self, out_: Vector3, n: Vector3, point: &SurfacePoint) -> Sample<Vector3> {
+    self.base.sample(out_, n, point)
+  }
+
+  fn pdf(&self, out_: Vector3, in_: Vector3, n: Vector3, point: &SurfacePoint) -> f32 {
+    self.base.pdf(out_, in_, n, point)
+  }
+
+  fn coef(&self, out_: Vector3, n: Vector3, fly_distance: f32, point: &SurfacePoint) -> Vector3 {
+    self.base.coef(out_, n, fly_distance, point)
+  }
+
+  fn is_delta(&self, out_: Vector3, in_: Vector3, n: Vector3) -> bool {
+    self.base.is_delta(out_, in_, n)
+  }
+
+  fn transmittance(&self, direction: Vector3, n: Vector3, point: &SurfacePoint) -> Option<Vector3> {
+    self.base.transmittance(direction, n, point)
+  }
+
+  fn medium(&self, out_: Vector3, n: Vector3) -> Option<Medium> {
+    self.base.medium(out_, n)
+  }
+
+  fn select(&self, point: &SurfacePoint) -> Option<Arc<Material + Send + Sync>> {
+    self.base.select(point).map( |base| Arc::new(NormalMapMaterial {
+      base: base,
+      map: self.map.clone(),
+      strength: self.strength,
+    }) as Arc<Material + Send + Sync> )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use material::material::*;
+  use texture::texture::*;
+
+  fn base() -> Arc<Material + Send + Sync> {
+    Arc::new(LambertianMaterial {
+      emission: Vector3::zero(),
+      albedo: Arc::new(ConstantTexture { value: Vector3::new(1.0, 1.0, 1.0) }),
+    })
+  }
+
+  #[test]
+  fn flat_map_test() {
+    // (0.5, 0.5, 1.0) は法線を変えない
+    let mat = NormalMapMaterial {
+      base: base(),
+      map: Arc::new(ConstantTexture { value: Vector3::new(0.5, 0.5, 1.0) }),
+      strength: 1.0,
+    };
+    let point = SurfacePoint::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0), (0.0, 0.0));
+    let n = mat.normal(&point);
+    assert!((n - point.normal).norm() < 1e-4, "{}", n);
+  }
+
+  #[test]
+  fn tangent_space_test() {
+    // 接線方向に45度傾ける
+    let mat = NormalMapMaterial {
+      base: base(),
+      map: Arc::new(ConstantTexture { value: Vector3::new(1.0, 0.5, 1.0) }),
+      strength: 1.0,
+    };
+    let mut point = SurfacePoint::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0), (0.0, 0.0));
+    point.tangent = Vector3::new(1.0, 0.0, 0.0);
+    point.dpdv = Vector3::new(0.0, 1.0, 0.0);
+    let n = mat.normal(&point);
+    assert!((n - Vector3::new(1.0, 0.0, 1.0).normalize()).norm() < 1e-4, "{}", n);
+  }
+}
@@ -1,14 +1,17 @@
 extern crate rand;
 
+use std::sync::Arc;
 use super::traits::Material;
 use math::vector::*;
+use intersection::SurfacePoint;
 use sample::Sample;
+use texture::texture::Texture;
 use constant::*;
 use util::{BoundaryResponse, OrthonormalBasis};
 
 pub struct PhongMaterial {
   // 反射率
-  pub reflectance: Vector3,
+  pub reflectance: Arc<Texture + Send + Sync>,
   // ラフネス
   pub roughness: f32,
 }
@@ -23,28 +26,27 @@ impl Material for PhongMaterial {
     }
   }
 
-  fn emission(&self) -> Vector3 {
+  fn emission(&self, _point: &SurfacePoint) -> Vector3 {
     Vector3::zero()
   }
 
   fn weight(&self) -> f32 {
     // 反射率のうち最大のものをつかう
-    self.reflectance.x.max(self.reflectance.y).max(
-      self.reflectance.z,
-    )
+    let reflectance = self.reflectance.max_value();
+    reflectance.x.max(reflectance.y).max(reflectance.z)
   }
 
-  fn brdf(&self, out_: Vector3, in_: Vector3, n: Vector3, _pos: Vector3) -> Vector3 {
+  fn brdf(&self, out_: Vector3, in_: Vector3, n: Vector3, point: &SurfacePoint) -> Vector3 {
     let on = self.orienting_normal(out_, n);
     if in_.dot(on) <= 0.0 { return Vector3::zero() }
     let r = out_.reflect(on);
     let cos = r.dot(in_);
     let a = self.roughness;
     // modified phong
-    self.reflectance * ((a + 2.0) / (2.0 * PI) * cos.powf(a))
+    self.reflectance.value(point) * ((a + 2.0) / (2.0 * PI) * cos.powf(a))
   }
 
-  fn sample(&self, out_: Vector3, n: Vector3) -> Sample<Vector3> {
+  fn sample(&self, out_: Vector3, n: Vector3, _point: &SurfacePoint) -> Sample<Vector3> {
     let on = self.orienting_normal(out_, n);
     let a = self.roughness;
     let r = out_.reflect(on);
@@ -66,4 +68,12 @@ impl Material for PhongMaterial {
       pdf: pdf,
     }
   }
+
+  fn pdf(&self, out_: Vector3, in_: Vector3, n: Vector3, _point: &SurfacePoint) -> f32 {
+    let on = self.orienting_normal(out_, n);
+    let a = self.roughness;
+    let r = out_.reflect(on);
+    let cos = r.dot(in_).max(0.0);
+    (a + 2.0) / (2.0 * PI) * cos.powf(a)
+  }
 }
@@ -0,0 +1,66 @@
+use super::traits::Material;
+use super::ideal_refraction::IdealRefractionMaterial;
+use math::vector::*;
+use intersection::SurfacePoint;
+use sample::Sample;
+use medium::Medium;
+
+pub struct SubsurfaceMaterial {
+  // 物体表面の屈折
+  pub boundary: IdealRefractionMaterial,
+  // 物体内部の散乱媒質
+  pub medium: Medium,
+}
+
+impl SubsurfaceMaterial {
+  pub fn new(albedo: Vector3, mean_free_path: Vector3, ior: f32) -> SubsurfaceMaterial {
+    SubsurfaceMaterial {
+      boundary: IdealRefractionMaterial {
+        reflectance: Vector3::new(1.0, 1.0, 1.0),
+        absorbtance: 0.0,
+        ior: ior,
+      },
+      medium: Medium::new(albedo, mean_free_path),
+    }
+  }
+}
+
+impl Material for SubsurfaceMaterial {
+  fn orienting_normal(&self, out_: Vector3, normal: Vector3) -> Vector3 {
+    self.boundary.orienting_normal(out_, normal)
+  }
+
+  fn emission(&self, _point: &SurfacePoint) -> Vector3 {
+    Vector3::zero()
+  }
+
+  fn weight(&self) -> f32 {
+    // 境界面では吸収しない
+    1.0
+  }
+
+  fn brdf(&self, out_: Vector3, in_: Vector3, n: Vector3, point: &SurfacePoint) -> Vector3 {
+    self.boundary.brdf(out_, in_, n, point)
+  }
+
+  fn sample(&self, out_: Vector3, n: Vector3, point: &SurfacePoint) -> Sample<Vector3> {
+    self.boundary.sample(out_, n, point)
+  }
+
+  fn pdf(&self, out_: Vector3, in_: Vector3, n: Vector3, point: &SurfacePoint) -> f32 {
+    self.boundary.pdf(out_, in_, n, point)
+  }
+
+  fn is_delta(&self, _out_: Vector3, _in_: Vector3, _n: Vector3) -> bool {
+    true
+  }
+
+  fn medium(&self, out_: Vector3, n: Vector3) -> Option<Medium> {
+    // 物体内部から交差した場合は媒質中を通過してきた
+    if out_.dot(n) < 0.0 {
+      Some(self.medium)
+    } else {
+      None
+    }
+  }
+}
@@ -0,0 +1,141 @@
+extern crate rand;
+
+use super::traits::Material;
+use super::ideal_refraction::IdealRefractionMaterial;
+use math::vector::*;
+use intersection::SurfacePoint;
+use sample::Sample;
+use util::BoundaryResponse;
+
+pub struct ThinDielectricMaterial {
+  // スペキュラー反射率
+  pub reflectance: Vector3,
+  // 透過光の色
+  pub tint: Vector3,
+  // 屈折率
+  pub ior: f32,
+}
+// ディラックのデルタ関数
+const DELTA_FUNC: f32 = 1.0;
+
+impl ThinDielectricMaterial {
+  fn fresnel(&self, out_: Vector3, on: Vector3) -> f32 {
+    // 真空から物体への1回の境界での反射率
+    let fr = match out_.refract(on, 1.0 / self.ior) {
+      Some(r) => IdealRefractionMaterial::fresnel(1.0, self.ior, out_, r, on),
+      None => 1.0,
+    };
+    // 薄い板の内部での多重反射を考慮した反射率
+    2.0 * fr / (1.0 + fr)
+  }
+}
+
+impl Material for ThinDielectricMaterial {
+  fn orienting_normal(&self, out_: Vector3, normal: Vector3) -> Vector3 {
+    // 物体の内外を考慮した法線方向から拡散反射面としての法線方向を求める
+    if normal.dot(out_) < 0.0 {
+      normal * -1.0
+    } else {
+      normal
+    }
+  }
+
+  fn emission(&self, _point: &SurfacePoint) -> Vector3 {
+    Vector3::zero()
+  }
+
+  fn weight(&self) -> f32 {
+    // 反射率と透過率のうち最大のものをつかう
+    self.reflectance.x.max(self.reflectance.y).max(self.reflectance.z)
+      .max(self.tint.x).max(self.tint.y).max(self.tint.z)
+  }
+
+  fn brdf(&self, out_: Vector3, in_: Vector3, n: Vector3, _point: &SurfacePoint) -> Vector3 {
+    let on = self.orienting_normal(out_, n);
+    let fr = self.fresnel(out_, on);
+    if in_.dot(on) > 0.0 {
+      // 反射
+      self.reflectance * DELTA_FUNC / in_.dot(n).abs() * fr
+    } else {
+      // 屈折せずに直進して透過
+      self.tint * DELTA_FUNC / in_.dot(n).abs() * (1.0 - fr)
+    }
+  }
+
+  fn sample(&self, out_: Vector3, n: Vector3, _point: &SurfacePoint) -> Sample<Vector3> {
+    let on = self.orienting_normal(out_, n);
+    let fr = self.fresnel(out_, on);
+    // ロシアンルーレットで反射と透過のどちらかの寄与を取る
+    if rand::random::<f32>() < fr {
+      Sample {
+        value: out_.reflect(on),
+        pdf: DELTA_FUNC * fr,
+      }
+    } else {
+      Sample {
+        value: -out_,
+        pdf: DELTA_FUNC * (1.0 - fr),
+      }
+    }
+  }
+
+  fn pdf(&self, out_: Vector3, in_: Vector3, n: Vector3, _point: &SurfacePoint) -> f32 {
+    let on = self.orienting_normal(out_, n);
+    let fr = self.fresnel(out_, on);
+    if in_.dot(on) > 0.0 {
+      DELTA_FUNC * fr
+    } else {
+      DELTA_FUNC * (1.0 - fr)
+    }
+  }
+
+  fn is_delta(&self, _out_: Vector3, _in_: Vector3, _n: Vector3) -> bool {
+    true
+  }
+
+  fn transmittance(&self, direction: Vector3, n: Vector3, _point: &SurfacePoint) -> Option<Vector3> {
+    let out_ = -direction;
+    let on = self.orienting_normal(out_, n);
+    Some(self.tint * (1.0 - self.fresnel(out_, on)))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use constant::*;
+
+  #[test]
+  fn energy_conservation_test() {
+    let mat = ThinDielectricMaterial {
+      reflectance: Vector3::new(1.0, 1.0, 1.0),
+      tint: Vector3::new(1.0, 1.0, 1.0),
+      ior: 1.5,
+    };
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    let point = SurfacePoint::new(Vector3::zero(), n, (0.0, 0.0));
+    for i in 0..90 {
+      let t = i as f32 / 90.0 * PI / 2.0;
+      let out_ = Vector3::new(t.sin(), 0.0, t.cos());
+      let fr = mat.fresnel(out_, n);
+      let ft = mat.transmittance(-out_, n, &point).unwrap().x;
+      assert!(fr >= 0.0 && ft >= 0.0 && (fr + ft - 1.0).abs() < EPS, "{} {}", fr, ft);
+    }
+  }
+
+  #[test]
+  fn straight_transmission_test() {
+    let point = SurfacePoint::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0), (0.0, 0.0));
+    let mat = ThinDielectricMaterial {
+      reflectance: Vector3::zero(),
+      tint: Vector3::new(1.0, 1.0, 1.0),
+      ior: 1.5,
+    };
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    let out_ = Vector3::new(1.0, 0.0, 1.0).normalize();
+    for _ in 0..10 {
+      let in_ = mat.sample(out_, n, &point).value;
+      assert!(in_.dot(n) > 0.0 || (in_ + out_).norm() < EPS);
+    }
+  }
+}
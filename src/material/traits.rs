@@ -1,23 +1,69 @@
-use math::vector::Vector3;
+use std::sync::Arc;
+use math::vector::*;
 use sample::Sample;
+use medium::Medium;
+use intersection::SurfacePoint;
 
 // (ω_o) 出射ベクトル(視線ベクトル)
 // (ω_i) 入射ベクトル(光源ベクトル)
 
 pub trait Material {
-  // 物体自体の放射成分
-  fn emission(&self) -> Vector3;
+  // 表面上の点 -> 物体自体の放射成分
+  fn emission(&self, &SurfacePoint) -> Vector3;
+  // 表面上の点, 出射ベクトル -> 放射成分 (配光による方向の依存を含む)
+  fn directional_emission(&self, point: &SurfacePoint, _out_: Vector3) -> Vector3 {
+    self.emission(point)
+  }
+  // 放射成分の最大値 (光源として扱うかの判定につかう)
+  fn max_emission(&self) -> Vector3 {
+    Vector3::zero()
+  }
+  // 裏面からも放射するか
+  fn two_sided(&self) -> bool {
+    false
+  }
+  // 表面上の点 -> 陰影計算につかう法線ベクトル (法線マップなどで摂動する)
+  fn normal(&self, point: &SurfacePoint) -> Vector3 {
+    point.normal
+  }
+  // 表面上の点 -> 不透明度 (確率的に面を通り抜ける)
+  fn opacity(&self, _point: &SurfacePoint) -> f32 {
+    1.0
+  }
+  // 不透明度が1未満になり得るか (交差判定で不透明度を評価するかどうか)
+  fn has_opacity(&self) -> bool {
+    false
+  }
   // 出射ベクトル, 物体法線ベクトル -> 法線ベクトル
   fn orienting_normal(&self, Vector3, Vector3) -> Vector3;
-  // 出射ベクトル, 入射ベクトル, 法線ベクトル, 座標 -> BRDF
-  fn brdf(&self, Vector3, Vector3, Vector3, Vector3) -> Vector3;
-  // 出射ベクトル, 法線ベクトル -> 入射ベクトル, 確率密度
-  fn sample(&self, Vector3, Vector3) -> Sample<Vector3>;
+  // 出射ベクトル, 入射ベクトル, 法線ベクトル, 表面上の点 -> BRDF
+  fn brdf(&self, Vector3, Vector3, Vector3, &SurfacePoint) -> Vector3;
+  // 出射ベクトル, 法線ベクトル, 表面上の点 -> 入射ベクトル, 確率密度
+  fn sample(&self, Vector3, Vector3, &SurfacePoint) -> Sample<Vector3>;
+  // 出射ベクトル, 入射ベクトル, 法線ベクトル, 表面上の点 -> sampleで入射ベクトルが得られる確率密度
+  fn pdf(&self, Vector3, Vector3, Vector3, &SurfacePoint) -> f32;
   // 再帰継続用ロシアンルーレットの重み
   fn weight(&self) -> f32;
   // 輝度に乗算する係数
-  // 出射ベクトル, 法線ベクトル, 飛行距離
-  fn coef(&self, Vector3, Vector3, f32) -> Vector3 {
+  // 出射ベクトル, 法線ベクトル, 飛行距離, 表面上の点
+  fn coef(&self, Vector3, Vector3, f32, &SurfacePoint) -> Vector3 {
     Vector3::new(1.0, 1.0, 1.0)
   }
+  // 出射ベクトル, 入射ベクトル, 法線ベクトル -> デルタ関数で表される成分か (NEEでは扱えない)
+  fn is_delta(&self, Vector3, Vector3, Vector3) -> bool {
+    false
+  }
+  // シャドウレイの方向, 法線ベクトル, 表面上の点 -> 面を直進して透過する割合 (不透明な場合はNone)
+  fn transmittance(&self, Vector3, Vector3, &SurfacePoint) -> Option<Vector3> {
+    None
+  }
+  // 出射ベクトル, 法線ベクトル -> 交差までに通過してきた物体内部の媒質
+  fn medium(&self, Vector3, Vector3) -> Option<Medium> {
+    None
+  }
+  // 表面上の点 -> 確率的に選んだ成分のマテリアル (混合でない場合はNone)
+  // 交差ごとに1つの成分を選ぶとデルタ関数の成分も含めてBRDFと確率密度が一致する
+  fn select(&self, _point: &SurfacePoint) -> Option<Arc<Material + Send + Sync>> {
+    None
+  }
 }
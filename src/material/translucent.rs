@@ -0,0 +1,105 @@
+extern crate rand;
+
+use super::traits::Material;
+use math::vector::*;
+use intersection::SurfacePoint;
+use sample::Sample;
+use constant::*;
+use util::{Sampler, OrthonormalBasis};
+
+pub struct TranslucentMaterial {
+  // 拡散反射率
+  pub albedo: Vector3,
+  // 拡散透過率
+  pub transmission: Vector3,
+}
+
+impl TranslucentMaterial {
+  fn reflection_prob(&self) -> f32 {
+    // 反射と透過の重みの比でどちらの半球をサンプリングするか選ぶ
+    let r = self.albedo.x.max(self.albedo.y).max(self.albedo.z);
+    let t = self.transmission.x.max(self.transmission.y).max(self.transmission.z);
+    if r + t > 0.0 { r / (r + t) } else { 0.5 }
+  }
+}
+
+impl Material for TranslucentMaterial {
+  fn orienting_normal(&self, out_: Vector3, normal: Vector3) -> Vector3 {
+    // 物体の内外を考慮した法線方向から拡散反射面としての法線方向を求める
+    if normal.dot(out_) < 0.0 {
+      normal * -1.0
+    } else {
+      normal
+    }
+  }
+
+  fn emission(&self, _point: &SurfacePoint) -> Vector3 {
+    Vector3::zero()
+  }
+
+  fn weight(&self) -> f32 {
+    let v = self.albedo + self.transmission;
+    v.x.max(v.y).max(v.z)
+  }
+
+  fn brdf(&self, out_: Vector3, in_: Vector3, n: Vector3, _point: &SurfacePoint) -> Vector3 {
+    let on = self.orienting_normal(out_, n);
+    // 表側には拡散反射, 裏側には拡散透過
+    if in_.dot(on) > 0.0 {
+      self.albedo / PI
+    } else {
+      self.transmission / PI
+    }
+  }
+
+  fn sample(&self, out_: Vector3, n: Vector3, _point: &SurfacePoint) -> Sample<Vector3> {
+    let on = self.orienting_normal(out_, n);
+    let prob = self.reflection_prob();
+    // 反射か透過の半球を選び, その法線方向を基準にした正規直交基底を生成
+    let (w, side_prob) = if rand::random::<f32>() < prob {
+      (on, prob)
+    } else {
+      (-on, 1.0 - prob)
+    };
+    let (u, v) = w.orthonormal_basis();
+    // (cosにしたがって重点的にサンプル)
+    let sample = Sampler::hemisphere_cos_importance();
+    let in_ = u * sample.x + v * sample.y + w * sample.z;
+    Sample {
+      value: in_,
+      pdf: side_prob * in_.dot(w) / PI,
+    }
+  }
+
+  fn pdf(&self, out_: Vector3, in_: Vector3, n: Vector3, _point: &SurfacePoint) -> f32 {
+    let on = self.orienting_normal(out_, n);
+    let prob = self.reflection_prob();
+    let cos = in_.dot(on);
+    if cos > 0.0 {
+      prob * cos / PI
+    } else {
+      (1.0 - prob) * -cos / PI
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn sample_pdf_test() {
+    let point = SurfacePoint::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0), (0.0, 0.0));
+    let mat = TranslucentMaterial {
+      albedo: Vector3::new(0.2, 0.2, 0.2),
+      transmission: Vector3::new(0.6, 0.6, 0.6),
+    };
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    let out_ = Vector3::new(1.0, 0.0, 1.0).normalize();
+    for _ in 0..100 {
+      let sample = mat.sample(out_, n, &point);
+      assert!(sample.pdf > 0.0);
+      assert!((sample.pdf - mat.pdf(out_, sample.value, n, &point)).abs() < EPS);
+    }
+  }
+}
@@ -67,6 +67,37 @@ impl Matrix4 {
     }
   }
 
+  pub fn inverse(&self) -> Matrix4 {
+    // ガウス・ジョルダンの消去法 (部分ピボット選択)
+    let mut a = self.v.clone();
+    let mut inv = Matrix4::unit().v;
+    for c in 0..4 {
+      let pivot = (c..4).max_by(|&i, &j|
+        a[i * 4 + c].abs().partial_cmp(&a[j * 4 + c].abs()).unwrap()
+      ).unwrap();
+      if a[pivot * 4 + c].abs() < 1e-12 { panic!("Matrix is not invertible.") }
+      for k in 0..4 {
+        a.swap(c * 4 + k, pivot * 4 + k);
+        inv.swap(c * 4 + k, pivot * 4 + k);
+      }
+      let d = a[c * 4 + c];
+      for k in 0..4 {
+        a[c * 4 + k] /= d;
+        inv[c * 4 + k] /= d;
+      }
+      for r in (0..4).filter( |&r| r != c ) {
+        let f = a[r * 4 + c];
+        for k in 0..4 {
+          a[r * 4 + k] -= f * a[c * 4 + k];
+          inv[r * 4 + k] -= f * inv[c * 4 + k];
+        }
+      }
+    }
+    Matrix4 {
+      v: inv,
+    }
+  }
+
   pub fn col(&self, x: usize) -> Vector4 {
     (0..4).map( |i| self.v[x + i * 4]).collect::<Vec<_>>().into()
   }
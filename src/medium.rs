@@ -0,0 +1,85 @@
+extern crate rand;
+
+use math::vector::*;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Medium {
+  // 散乱係数
+  pub scattering: Vector3,
+  // 吸収係数
+  pub absorption: Vector3,
+}
+
+impl Medium {
+  pub fn new(albedo: Vector3, mean_free_path: Vector3) -> Medium {
+    // 多重散乱後の見た目の色から単一散乱アルベドを求める [Chiang et al. 2016]
+    let single_albedo = |a: f32| {
+      let a = a.max(0.0).min(1.0);
+      1.0 - (4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt()).powi(2)
+    };
+    let albedo = Vector3::new(
+      single_albedo(albedo.x),
+      single_albedo(albedo.y),
+      single_albedo(albedo.z),
+    );
+    // 減衰係数は平均自由行程の逆数
+    let extinction = 1.0 / mean_free_path;
+    Medium {
+      scattering: extinction * albedo,
+      absorption: extinction * (Vector3::new(1.0, 1.0, 1.0) - albedo),
+    }
+  }
+
+  pub fn extinction(&self) -> Vector3 {
+    self.scattering + self.absorption
+  }
+
+  pub fn transmittance(&self, distance: f32) -> Vector3 {
+    let v = -self.extinction() * distance;
+    Vector3::new(v.x.exp(), v.y.exp(), v.z.exp())
+  }
+
+  pub fn sample_distance(&self) -> f32 {
+    // 波長ごとに減衰係数が異なるので, 一様に選んだチャンネルの減衰係数で自由行程をサンプリング
+    let extinction = self.extinction()[(rand::random::<f32>() * 3.0) as usize % 3];
+    -(1.0 - rand::random::<f32>()).ln() / extinction
+  }
+
+  pub fn distance_pdf(&self, distance: f32) -> f32 {
+    // 各チャンネルでの確率密度の平均
+    let extinction = self.extinction();
+    let transmittance = self.transmittance(distance);
+    (extinction * transmittance).dot(Vector3::new(1.0, 1.0, 1.0)) / 3.0
+  }
+
+  pub fn pass_through_prob(&self, distance: f32) -> f32 {
+    // 散乱せずに距離distanceを通過する確率
+    self.transmittance(distance).dot(Vector3::new(1.0, 1.0, 1.0)) / 3.0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use constant::*;
+
+  #[test]
+  fn albedo_bounds_test() {
+    let white = Medium::new(Vector3::new(1.0, 1.0, 1.0), Vector3::new(1.0, 1.0, 1.0));
+    assert!(white.absorption.norm() < EPS, "{}", white.absorption);
+    let black = Medium::new(Vector3::zero(), Vector3::new(1.0, 1.0, 1.0));
+    assert!(black.scattering.norm() < EPS, "{}", black.scattering);
+  }
+
+  #[test]
+  fn distance_pdf_test() {
+    let medium = Medium::new(Vector3::new(0.8, 0.5, 0.2), Vector3::new(1.0, 0.5, 0.25));
+    // 確率密度を積分すると1になる
+    let dt = 0.001;
+    let sum = (0..20000).map( |i| medium.distance_pdf((i as f32 + 0.5) * dt) * dt ).sum::<f32>();
+    assert!((sum - 1.0).abs() < EPS, "{}", sum);
+    // 通過確率は確率密度の残りの積分と一致する
+    let rest = (0..20000).map( |i| medium.distance_pdf(1.0 + (i as f32 + 0.5) * dt) * dt ).sum::<f32>();
+    assert!((rest - medium.pass_through_prob(1.0)).abs() < EPS, "{}", rest);
+  }
+}
@@ -1,67 +1,170 @@
 extern crate rand;
 
-use ray::Ray;
-use intersection::Intersection;
+use ray::{Ray, RayKind};
+use intersection::{Intersection, SurfacePoint};
 use shape::*;
 use bvh::BVH;
 use math::vector::*;
 use sample::Sample;
 use aabb::AABB;
+use distribution::AliasTable;
+use light_bvh::LightBVH;
+
+// NEEで光源を選ぶ方法
+pub enum LightSelection {
+  // 放射の強さに比例 (エイリアス法)
+  Power,
+  // 照らされる点からの距離も考慮 (光源のBVH)
+  Bvh,
+}
+
+// 物体がどの種類のレイと交差するか
+#[derive(Debug, Clone, Copy)]
+pub struct Visibility {
+  pub camera: bool,
+  pub reflection: bool,
+  pub shadow: bool,
+}
+
+impl Default for Visibility {
+  fn default() -> Visibility {
+    Visibility {
+      camera: true,
+      reflection: true,
+      shadow: true,
+    }
+  }
+}
+
+impl Visibility {
+  fn accepts(&self, kind: RayKind) -> bool {
+    match kind {
+      RayKind::Camera => self.camera,
+      RayKind::Reflection => self.reflection,
+      RayKind::Shadow => self.shadow,
+    }
+  }
+}
+
+enum EmissionSelector {
+  Power(AliasTable),
+  Bvh(LightBVH),
+}
 
 pub struct Objects<'a> {
   bvh: BVH<'a>,
   emission: Vec<&'a Box<dyn SurfaceShape + Send + Sync>>,
-  emission_area: f32,
+  emission_selector: EmissionSelector,
+  // 物体ごとの可視性
+  visibility: Vec<Visibility>,
+}
+
+// 光源の放射の強さを見積もるサンプル数
+const EMISSION_ESTIMATE_SAMPLES: usize = 16;
+// 光源上の点を選ぶときの候補の数
+const EMISSION_CANDIDATES: usize = 8;
+
+fn luminance(e: Vector3) -> f32 {
+  e.x.max(e.y).max(e.z)
 }
 
 impl<'a> Objects<'a> {
-  pub fn new(objects: &'a Vec<Box<SurfaceShape + Send + Sync>>) -> Objects<'a> {
+  pub fn new(objects: &'a Vec<Box<SurfaceShape + Send + Sync>>, visibility: Vec<Visibility>, selection: LightSelection) -> Objects<'a> {
+    assert!(visibility.len() == objects.len());
     let emission = objects
       .iter()
-      .filter( |v| v.material().emission().sqr_norm() > 0.0 )
+      .filter( |v| v.material().max_emission().sqr_norm() > 0.0 )
       .collect::<Vec<_>>();
-    let emission_area = emission.iter().map( |v| v.area() ).sum();
+    let power = emission.iter().map( |v| Self::estimate_power(v) ).collect::<Vec<_>>();
+    let emission_selector = match selection {
+      LightSelection::Power => EmissionSelector::Power(AliasTable::new(power)),
+      LightSelection::Bvh if emission.is_empty() => EmissionSelector::Power(AliasTable::new(power)),
+      LightSelection::Bvh => EmissionSelector::Bvh(LightBVH::new(
+        emission.iter().map( |v| v.aabb().clone() ).zip(power).collect()
+      )),
+    };
+    // 光源上の点との接続を判定できるように, 光源は常に可視判定のレイと交差させる
+    let visibility = visibility.into_iter().zip(objects).map( |(v, o)| Visibility {
+      shadow: v.shadow || o.material().max_emission().sqr_norm() > 0.0,
+      .. v
+    }).collect();
     Objects {
       bvh: BVH::new(objects),
       emission: emission,
-      emission_area: emission_area,
+      emission_selector: emission_selector,
+      visibility: visibility,
     }
   }
 
+  fn estimate_power(shape: &Box<SurfaceShape + Send + Sync>) -> f32 {
+    // 表面上の放射の平均 x 面積
+    let material = shape.material();
+    let mean = (0..EMISSION_ESTIMATE_SAMPLES)
+      .map( |_| luminance(material.emission(&shape.sample().value)) )
+      .sum::<f32>() / EMISSION_ESTIMATE_SAMPLES as f32;
+    // テクスチャの暗い部分しかサンプルされなかった光源も選ばれるように下限を設ける
+    mean.max(luminance(material.max_emission()) * 0.01) * shape.area()
+  }
+
   // pub fn new(objects: Vec<Box<Shape + Send + Sync>>) -> Objects {
   //   Objects {
   //     objects: objects,
   //   }
   // }
 
-  pub fn sample_emission(&self) -> Sample<Vector3> {
-    let roulette = self.emission_area * rand::random::<f32>();
-    let mut area = 0.0;
-    for obj in &self.emission {
-      area += obj.area();
-      if roulette <= area {
-        let sample = obj.sample();
-        return Sample {
-          value: sample.value,
-          pdf: sample.pdf * obj.area() / self.emission_area,
-        };
+  // 照らされる点 -> 光源上の点
+  pub fn sample_emission(&self, position: Vector3) -> Sample<SurfacePoint> {
+    let choice = match self.emission_selector {
+      EmissionSelector::Power(ref table) => table.sample(rand::random::<f32>()),
+      EmissionSelector::Bvh(ref bvh) => bvh.sample(position, rand::random::<f32>()),
+    };
+    let shape = self.emission[choice.value];
+    let material = shape.material();
+    // 照らされる点から見た立体角に対して一様に選んだ候補から放射の強さに比例して1点を選ぶ (resampled importance sampling)
+    // 確率密度の代わりに, 推定量が不偏になる重みの逆数を返す
+    let candidates = (0..EMISSION_CANDIDATES).map( |_| {
+      let sample = shape.sample_solid_angle(position);
+      let w = luminance(material.emission(&sample.value));
+      (sample, w)
+    }).collect::<Vec<_>>();
+    let sum = candidates.iter().map( |&(_, w)| w ).sum::<f32>();
+    if sum == 0.0 {
+      let (sample, _) = candidates.into_iter().next().unwrap();
+      return Sample {
+        value: sample.value,
+        pdf: sample.pdf * choice.pdf,
+      }
+    }
+    let roulette = sum * rand::random::<f32>();
+    let mut acc = 0.0;
+    let mut selected = candidates.iter().rposition( |&(_, w)| w > 0.0 ).unwrap();
+    for (k, &(_, w)) in candidates.iter().enumerate() {
+      acc += w;
+      if roulette < acc {
+        selected = k;
+        break;
       }
     }
-    unreachable!();
+    let (sample, w) = candidates.into_iter().nth(selected).unwrap();
+    Sample {
+      value: sample.value,
+      pdf: sample.pdf * choice.pdf * w * EMISSION_CANDIDATES as f32 / sum,
+    }
   }
 
   pub fn has_emission(&self) -> bool {
-    self.emission_area > 0.0
+    !self.emission.is_empty()
   }
 }
 
-impl<'a> Shape for Objects<'a> {
-  fn aabb(&self) -> &AABB {
+impl<'a> Objects<'a> {
+  pub fn aabb(&self) -> &AABB {
     self.bvh.aabb()
   }
 
-  fn intersect(&self, ray: &Ray) -> Option<Intersection> {
-    self.bvh.intersect(&ray)
+  // レイの種類に対して見えない物体は通り抜ける
+  pub fn intersect(&self, ray: &Ray, kind: RayKind) -> Option<Intersection> {
+    self.bvh.intersect_filter(&ray, |i| self.visibility[i].accepts(kind))
   }
 
   // pub fn intersect(&self, ray: &Ray) -> Option<Intersection> {
@@ -72,3 +175,86 @@ impl<'a> Shape for Objects<'a> {
   //   )
   // }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::Arc;
+  use triangle::Triangle;
+  use material::material::*;
+  use texture::texture::*;
+
+  fn emitter(offset: f32, emission: Arc<Texture + Send + Sync>) -> Box<SurfaceShape + Send + Sync> {
+    box Triangle::new(
+      Vector3::new(0.0, 0.0, offset),
+      Vector3::new(1.0, 0.0, offset),
+      Vector3::new(0.0, 0.0, offset + 1.0),
+      Arc::new(EmissiveMaterial {
+        base: Arc::new(LambertianMaterial {
+          emission: Vector3::zero(),
+          albedo: Arc::new(ConstantTexture { value: Vector3::zero() }),
+        }),
+        emission: emission,
+        scale: 1.0,
+        two_sided: false,
+        profile: None,
+      }),
+    )
+  }
+
+  #[test]
+  fn sample_emission_test() {
+    // 一様な光源と, xに比例して明るくなる光源
+    let shapes = vec![
+      emitter(0.0, Arc::new(ConstantTexture { value: Vector3::new(1.0, 1.0, 1.0) })),
+      emitter(2.0, Arc::new(ProceduralTexture {
+        mapping: Mapping::World,
+        scale: 1.0,
+        pattern: box GradientPattern { axis: Vector3::new(1.0, 0.0, 0.0) },
+        colors: (Vector3::zero(), Vector3::new(1.0, 1.0, 1.0)),
+      })),
+    ];
+    for selection in vec![LightSelection::Power, LightSelection::Bvh] {
+      let objects = Objects::new(&shapes, vec![Visibility::default(); shapes.len()], selection);
+      assert!(objects.has_emission());
+      // 放射 / 確率密度 の期待値は放射の面積分 (0.5 + 1/6) になる
+      let n = 100000;
+      let sum = (0..n).map( |_| {
+        let sample = objects.sample_emission(Vector3::new(0.0, 1.0, 0.0));
+        let x = sample.value.position.x;
+        let emission = if sample.value.position.z < 1.5 { 1.0 } else { x };
+        emission / sample.pdf
+      }).sum::<f32>();
+      let estimate = sum / n as f32;
+      assert!((estimate - 2.0 / 3.0).abs() < 0.02, "{}", estimate);
+    }
+  }
+
+  #[test]
+  fn visibility_test() {
+    let plane = |y: f32| box Triangle::new(
+      Vector3::new(-1.0, y, -1.0),
+      Vector3::new(1.0, y, -1.0),
+      Vector3::new(0.0, y, 1.0),
+      Arc::new(LambertianMaterial {
+        emission: Vector3::zero(),
+        albedo: Arc::new(ConstantTexture { value: Vector3::zero() }),
+      }),
+    ) as Box<SurfaceShape + Send + Sync>;
+    let shapes = vec![plane(1.0), plane(0.0)];
+    // 上の面はカメラからは見えず影を落とさない
+    let visibility = vec![
+      Visibility { camera: false, reflection: true, shadow: false },
+      Visibility::default(),
+    ];
+    let objects = Objects::new(&shapes, visibility, LightSelection::Power);
+    let ray = Ray {
+      origin: Vector3::new(0.0, 2.0, 0.0),
+      direction: Vector3::new(0.0, -1.0, 0.0),
+      differential: None,
+    };
+    assert_eq!(objects.intersect(&ray, RayKind::Camera).unwrap().object, 1);
+    assert_eq!(objects.intersect(&ray, RayKind::Reflection).unwrap().object, 0);
+    assert_eq!(objects.intersect(&ray, RayKind::Shadow).unwrap().object, 1);
+  }
+}
@@ -0,0 +1,158 @@
+extern crate rand;
+
+use math::vector::*;
+use sample::Sample;
+use triangle::Triangle;
+
+// 窓などの開口部 (空の光はここを通ってのみ室内に届く)
+pub struct Portal {
+  triangles: Vec<[Vector3; 3]>,
+  // 空の重点サンプリングと混ぜるときの開口部を選ぶ確率
+  pub fraction: f32,
+}
+
+// 照らされる点から見てこれより小さい三角形はサンプリングしない
+const MIN_SOLID_ANGLE: f32 = 1e-6;
+
+impl Portal {
+  pub fn new(triangles: Vec<[Vector3; 3]>, fraction: f32) -> Portal {
+    Portal {
+      triangles: triangles,
+      fraction: fraction,
+    }
+  }
+
+  // 照らされる点から見た各三角形の立体角
+  fn solid_angles(&self, position: Vector3) -> Vec<f32> {
+    self.triangles.iter().map( |t| {
+      let a = (t[0] - position).normalize();
+      let b = (t[1] - position).normalize();
+      let c = (t[2] - position).normalize();
+      let solid_angle = Triangle::solid_angle(a, b, c);
+      if solid_angle > MIN_SOLID_ANGLE { solid_angle } else { 0.0 }
+    }).collect()
+  }
+
+  // 照らされる点 -> 開口部の立体角の中で一様にサンプリングした方向
+  pub fn sample(&self, position: Vector3) -> Option<Sample<Vector3>> {
+    let solid_angles = self.solid_angles(position);
+    let sum = solid_angles.iter().sum::<f32>();
+    if sum == 0.0 {
+      return None
+    }
+    // 立体角に比例して三角形を選ぶ
+    let roulette = sum * rand::random::<f32>();
+    let mut acc = 0.0;
+    let mut selected = solid_angles.iter().rposition( |&s| s > 0.0 ).unwrap();
+    for (k, &s) in solid_angles.iter().enumerate() {
+      acc += s;
+      if roulette < acc && s > 0.0 {
+        selected = k;
+        break;
+      }
+    }
+    let t = &self.triangles[selected];
+    let direction = Triangle::sample_spherical_triangle(
+      (t[0] - position).normalize(),
+      (t[1] - position).normalize(),
+      (t[2] - position).normalize(),
+      solid_angles[selected],
+      rand::random::<f32>(),
+      rand::random::<f32>(),
+    );
+    // 三角形の縁で数値誤差により交差しなかった方向はpdfと矛盾するので捨てる
+    let pdf = self.pdf_with(position, direction, &solid_angles, sum);
+    if pdf == 0.0 {
+      return None
+    }
+    Some(Sample {
+      value: direction,
+      pdf: pdf,
+    })
+  }
+
+  // 照らされる点, 方向 -> sampleでその方向が得られる確率密度 (立体角測度)
+  pub fn pdf(&self, position: Vector3, direction: Vector3) -> f32 {
+    let solid_angles = self.solid_angles(position);
+    let sum = solid_angles.iter().sum::<f32>();
+    if sum == 0.0 {
+      return 0.0
+    }
+    self.pdf_with(position, direction, &solid_angles, sum)
+  }
+
+  fn pdf_with(&self, position: Vector3, direction: Vector3, solid_angles: &Vec<f32>, sum: f32) -> f32 {
+    // 重なって見える開口部はそれぞれの確率密度を足し合わせる
+    let count = self.triangles.iter().zip(solid_angles).filter( |&(t, &s)| {
+      s > 0.0 && Self::is_intersect(t, position, direction)
+    }).count();
+    count as f32 / sum
+  }
+
+  // 半直線と三角形の交差判定 (Möller–Trumbore)
+  fn is_intersect(t: &[Vector3; 3], origin: Vector3, direction: Vector3) -> bool {
+    let e1 = t[1] - t[0];
+    let e2 = t[2] - t[0];
+    let pv = direction.cross(e2);
+    let det = e1.dot(pv);
+    if det == 0.0 {
+      return false
+    }
+    let invdet = 1.0 / det;
+    let tv = origin - t[0];
+    let u = tv.dot(pv) * invdet;
+    if u < 0.0 || u > 1.0 {
+      return false
+    }
+    let qv = tv.cross(e1);
+    let v = direction.dot(qv) * invdet;
+    if v < 0.0 || u + v > 1.0 {
+      return false
+    }
+    e2.dot(qv) * invdet > 0.0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use constant::*;
+
+  fn window() -> Portal {
+    // y = 1 の面にある 1x1 の窓
+    let p = [
+      Vector3::new(0.0, 1.0, 0.0),
+      Vector3::new(1.0, 1.0, 0.0),
+      Vector3::new(1.0, 1.0, 1.0),
+      Vector3::new(0.0, 1.0, 1.0),
+    ];
+    Portal::new(vec![[p[0], p[1], p[2]], [p[0], p[2], p[3]]], 1.0)
+  }
+
+  #[test]
+  fn sample_test() {
+    let portal = window();
+    let position = Vector3::new(0.3, 0.0, 0.4);
+    // サンプルした方向は窓を通り, 各三角形を通る割合はその立体角に比例する
+    let n = 10000;
+    let mut first = 0;
+    for _ in 0..n {
+      let s = match portal.sample(position) {
+        Some(s) => s,
+        None => continue,
+      };
+      let t = (1.0 - position.y) / s.value.y;
+      let p = position + s.value * t;
+      assert!(p.x > -EPS && p.x < 1.0 + EPS && p.z > -EPS && p.z < 1.0 + EPS, "{}", p);
+      assert!((portal.pdf(position, s.value) - s.pdf).abs() < EPS);
+      if p.x > p.z {
+        first += 1;
+      }
+    }
+    let solid_angles = portal.solid_angles(position);
+    let expected = solid_angles[0] / (solid_angles[0] + solid_angles[1]);
+    assert!((first as f32 / n as f32 - expected).abs() < 0.02, "{} {}", first, expected);
+    // 窓を通らない方向
+    assert_eq!(portal.pdf(position, Vector3::new(0.0, -1.0, 0.0)), 0.0);
+  }
+}
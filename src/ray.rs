@@ -1,6 +1,133 @@
-use math::vector::Vector3;
+use math::vector::*;
 
 pub struct Ray {
   pub origin: Vector3,
   pub direction: Vector3,
+  // 隣接する画素を通るレイとの差分 (追跡できない場合はNone)
+  pub differential: Option<RayDifferential>,
+}
+
+// レイの種類 (物体ごとの可視性の判定につかう)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RayKind {
+  // カメラから直接出たレイ
+  Camera,
+  // 反射・透過・散乱したレイ
+  Reflection,
+  // 光源への可視判定のレイ
+  Shadow,
+}
+
+impl Ray {
+  pub fn scale_differential(&mut self, s: f32) {
+    self.differential = self.differential.map( |d| RayDifferential {
+      origin: (d.origin.0 * s, d.origin.1 * s),
+      direction: (d.direction.0 * s, d.direction.1 * s),
+    });
+  }
+}
+
+// 画面のx方向, y方向についてのレイの偏微分 [Igehy 1999]
+#[derive(Debug, Clone, Copy)]
+pub struct RayDifferential {
+  pub origin: (Vector3, Vector3),
+  pub direction: (Vector3, Vector3),
+}
+
+impl RayDifferential {
+  // 正規化前の方向ベクトルの偏微分から正規化後の方向ベクトルの偏微分を求める
+  pub fn normalized_direction(d: Vector3, dd: Vector3) -> Vector3 {
+    let norm = d.norm();
+    (dd * d.sqr_norm() - d * d.dot(dd)) / (norm * norm * norm)
+  }
+
+  // 平面との交差位置の偏微分 (dp/dx, dp/dy)
+  pub fn footprint(&self, ray: &Ray, distance: f32, normal: Vector3) -> (Vector3, Vector3) {
+    let dn = ray.direction.dot(normal);
+    if dn.abs() < 1e-6 {
+      return (Vector3::zero(), Vector3::zero())
+    }
+    // 交差距離の変化を含めた位置の変化
+    let dp = |dox: Vector3, ddx: Vector3| {
+      let dt = -(dox + ddx * distance).dot(normal) / dn;
+      dox + ddx * distance + ray.direction * dt
+    };
+    (
+      dp(self.origin.0, self.direction.0),
+      dp(self.origin.1, self.direction.1),
+    )
+  }
+
+  // 方向が out_ -> in_ に変わる散乱のあとの差分
+  // 反射はハーフベクトルを法線とする鏡面反射, 透過は屈折として広がりを伝播する
+  pub fn scatter(dpdx: Vector3, dpdy: Vector3, direction: (Vector3, Vector3), out_: Vector3, in_: Vector3, normal: Vector3) -> RayDifferential {
+    let on = if out_.dot(normal) < 0.0 { -normal } else { normal };
+    let is_reflection = in_.dot(on) > 0.0;
+    let scatter = |dd: Vector3| {
+      // 出射ベクトルの偏微分
+      let dwo = -dd;
+      if is_reflection {
+        let h = (in_ + out_).normalize();
+        h * (2.0 * dwo.dot(h)) - dwo
+      } else {
+        // 屈折率の比は幾何的に求める (sinθ_t / sinθ_o)
+        let sin_o = out_.cross(on).norm();
+        let sin_t = in_.cross(on).norm();
+        if sin_o < 1e-4 {
+          // 垂直入射や直進透過は方向の広がりを変えない
+          return dd
+        }
+        let eta = sin_t / sin_o;
+        let cos_o = out_.dot(on);
+        let cos_t = in_.dot(on).abs();
+        let dmu = (eta - eta * eta * cos_o / cos_t) * dwo.dot(on);
+        -dwo * eta + on * dmu
+      }
+    };
+    RayDifferential {
+      origin: (dpdx, dpdy),
+      direction: (scatter(direction.0), scatter(direction.1)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use constant::*;
+
+  #[test]
+  fn footprint_test() {
+    // 平面に垂直に入射する平行なレイの広がりはそのまま
+    let ray = Ray {
+      origin: Vector3::new(0.0, 0.0, 5.0),
+      direction: Vector3::new(0.0, 0.0, -1.0),
+      differential: None,
+    };
+    let d = RayDifferential {
+      origin: (Vector3::new(0.1, 0.0, 0.0), Vector3::new(0.0, 0.1, 0.0)),
+      direction: (Vector3::zero(), Vector3::zero()),
+    };
+    let (dpdx, dpdy) = d.footprint(&ray, 5.0, Vector3::new(0.0, 0.0, 1.0));
+    assert!((dpdx - Vector3::new(0.1, 0.0, 0.0)).norm() < EPS, "{}", dpdx);
+    assert!((dpdy - Vector3::new(0.0, 0.1, 0.0)).norm() < EPS, "{}", dpdy);
+    // 広がる方向の差分は距離に比例する
+    let d = RayDifferential {
+      origin: (Vector3::zero(), Vector3::zero()),
+      direction: (Vector3::new(0.01, 0.0, 0.0), Vector3::zero()),
+    };
+    let (dpdx, _) = d.footprint(&ray, 5.0, Vector3::new(0.0, 0.0, 1.0));
+    assert!((dpdx - Vector3::new(0.05, 0.0, 0.0)).norm() < EPS, "{}", dpdx);
+  }
+
+  #[test]
+  fn mirror_scatter_test() {
+    // 鏡面反射では法線に垂直な広がりの成分はそのまま残る
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    let out_ = Vector3::new(0.0, 0.0, 1.0);
+    let in_ = Vector3::new(0.0, 0.0, 1.0);
+    let dd = Vector3::new(0.01, 0.0, 0.0);
+    let d = RayDifferential::scatter(Vector3::zero(), Vector3::zero(), (dd, dd), out_, in_, n);
+    assert!((d.direction.0 - dd).norm() < EPS, "{}", d.direction.0);
+  }
 }
@@ -2,51 +2,111 @@ extern crate rand;
 
 use math::vector::*;
 use sky::Sky;
-use ray::Ray;
+use light::{Light, LightLinks};
+use ray::{Ray, RayDifferential, RayKind};
 use objects::Objects;
-use shape::Shape;
-use intersection::Intersection;
+use portal::Portal;
+use intersection::{Intersection, SurfacePoint};
 use constant::*;
+use util::Sampler;
+use sample::Sample;
+
+enum MediumEvent {
+  // 媒質中で散乱した (散乱後のレイ, 重み)
+  Scatter(Ray, Vector3),
+  // 散乱せずに交差した面に到達した (重み)
+  Surface(Vector3),
+}
 
 pub struct Scene<'a> {
   pub objects: Objects<'a>,
   pub depth: usize,
   pub depth_limit: usize,
   pub sky: Box<dyn Sky + Send + Sync>,
+  // レイが交差しない点光源などの光源
+  pub lights: Vec<Box<dyn Light + Send + Sync>>,
+  // 光源ごとに照らす物体の制限
+  pub light_links: LightLinks,
+  // 空の光が通る開口部
+  pub portal: Option<Portal>,
   pub no_direct_emitter: bool,
 }
 
 impl<'a> Scene<'a> {
   pub fn radiance(&self, ray: &Ray) -> Vector3 {
-    self.radiance_recursive(ray, 0)
+    self.radiance_recursive(ray, 0, None)
   }
 
-  fn radiance_recursive(&self, ray: &Ray, depth: usize) -> Vector3 {
+  // receiver: レイを反射・透過した直前の物体 (光源ごとの照らす物体の制限につかう)
+  fn radiance_recursive(&self, ray: &Ray, depth: usize, receiver: Option<usize>) -> Vector3 {
     // すべてのオブジェクトと当たり判定を行う
-    let maybe_intersect = self.objects.intersect(&ray);
+    let maybe_intersect = self.objects.intersect(&ray, Self::ray_kind(depth)).map( |i| self.select_material(i, &ray) );
     // 当たらなかった場合は背景色を返す
     match maybe_intersect {
-      None => self.sky.radiance(&ray),
-      Some(i) => self.intersect_radiance(&i, &ray, depth),
+      None => self.sky.radiance(&ray) + self.light_radiance(&ray, receiver),
+      Some(i) => match self.medium_event(&i, &ray) {
+        MediumEvent::Surface(weight) => weight * self.intersect_radiance(&i, &ray, depth, receiver),
+        MediumEvent::Scatter(new_ray, weight) => self.scattering_radiance(new_ray, weight, depth, |new_ray| {
+          self.radiance_recursive(&new_ray, depth + 1, None)
+        }),
+      },
     }
   }
 
   pub fn radiance_nee(&self, ray: &Ray) -> Vector3 {
-    self.radiance_nee_recursive(ray, 0, false)
+    self.radiance_nee_recursive(ray, 0, false, None, None)
   }
 
-  fn radiance_nee_recursive(&self, ray: &Ray, depth: usize, no_emission: bool) -> Vector3 {
+  // bsdf_pdf: NEEと重ねて評価するBRDFサンプリングで得られたレイのときの確率密度 (空のMISにつかう)
+  fn radiance_nee_recursive(&self, ray: &Ray, depth: usize, no_emission: bool, bsdf_pdf: Option<f32>, receiver: Option<usize>) -> Vector3 {
     // すべてのオブジェクトと当たり判定を行う
-    let maybe_intersect = self.objects.intersect(&ray);
+    let maybe_intersect = self.objects.intersect(&ray, Self::ray_kind(depth)).map( |i| self.select_material(i, &ray) );
     // 当たらなかった場合は背景色を返す
     match maybe_intersect {
-      None => self.sky.radiance(&ray),
-      Some(i) => self.intersect_radiance_nee(&i, &ray, depth, no_emission),
+      None => {
+        let sky = match bsdf_pdf {
+          Some(pdf) => self.sky.radiance(&ray) * Self::mis_weight(pdf, self.sky_pdf(ray.origin, ray.direction)),
+          None => self.sky.radiance(&ray),
+        };
+        // 太陽などの光源はNEEで考慮済みでなければ加える
+        if no_emission { sky } else { sky + self.light_radiance(&ray, receiver) }
+      },
+      Some(i) => match self.medium_event(&i, &ray) {
+        MediumEvent::Surface(weight) => weight * self.intersect_radiance_nee(&i, &ray, depth, no_emission, bsdf_pdf, receiver),
+        // 媒質中ではNEEを行わない
+        MediumEvent::Scatter(new_ray, weight) => self.scattering_radiance(new_ray, weight, depth, |new_ray| {
+          self.radiance_nee_recursive(&new_ray, depth + 1, false, None, None)
+        }),
+      },
     }
   }
 
+  // 物体の可視性の判定につかうレイの種類
+  fn ray_kind(depth: usize) -> RayKind {
+    if depth == 0 { RayKind::Camera } else { RayKind::Reflection }
+  }
+
+  // パワーヒューリスティックによるMISの重み
+  fn mis_weight(pdf: f32, other_pdf: f32) -> f32 {
+    if other_pdf <= 0.0 {
+      return 1.0
+    }
+    pdf * pdf / (pdf * pdf + other_pdf * other_pdf)
+  }
+
+  fn light_radiance(&self, ray: &Ray, receiver: Option<usize>) -> Vector3 {
+    self.lights.iter().enumerate().filter( |&(k, _)| {
+      receiver.map( |r| self.light_links.light(k, r) ).unwrap_or(true)
+    }).fold(Vector3::zero(), |sum, (_, light)| sum + light.radiance(ray))
+  }
+
+  // 交差した光源の物体が直前の物体を照らすか
+  fn emitter_linked(&self, i: &Intersection, receiver: Option<usize>) -> bool {
+    receiver.map( |r| self.light_links.emitter(i.object, r) ).unwrap_or(true)
+  }
+
   pub fn normal(&self, ray: &Ray) -> Vector3 {
-    let maybe_intersect = self.objects.intersect(&ray);
+    let maybe_intersect = self.objects.intersect(&ray, RayKind::Camera);
     match maybe_intersect {
       None => Vector3::zero(),
       Some(i) => i.normal / 2.0 + Vector3::new(0.5, 0.5, 0.5),
@@ -54,7 +114,7 @@ impl<'a> Scene<'a> {
   }
 
   pub fn depth(&self, ray: &Ray) -> f32 {
-    let maybe_intersect = self.objects.intersect(&ray);
+    let maybe_intersect = self.objects.intersect(&ray, RayKind::Camera);
     match maybe_intersect {
       None => 0.0,
       Some(i) => i.distance,
@@ -62,8 +122,8 @@ impl<'a> Scene<'a> {
   }
 
   fn russian_roulette(&self, init: f32, depth: usize) -> f32 {
-    // 再帰抑制用のロシアンルーレットの確率を決定する
-    let mut continue_rr_prob = init;
+    // 再帰抑制用のロシアンルーレットの確率を決定する (反射率が1を超える成分でも確率は1まで)
+    let mut continue_rr_prob = init.min(1.0);
     // スタックオーバーフロー対策のために反射回数の限界値を超えたら極端に確率を下げる
     if depth > self.depth_limit {
       continue_rr_prob *= (0.5f32).powi((depth - self.depth_limit) as i32);
@@ -75,25 +135,83 @@ impl<'a> Scene<'a> {
     continue_rr_prob
   }
 
-  fn material_interaction_radiance<F>(&self, i: &Intersection, ray: &Ray, f: F) -> Vector3
+  // 混合マテリアルは交差ごとに1つの成分を選んで置き換える
+  fn select_material(&self, i: Intersection, ray: &Ray) -> Intersection {
+    match i.material.select(&i.point(ray)) {
+      Some(material) => Intersection { material: material, ..i },
+      None => i,
+    }
+  }
+
+  fn medium_event(&self, i: &Intersection, ray: &Ray) -> MediumEvent {
+    let medium = match i.material.medium(-ray.direction, i.normal) {
+      Some(medium) => medium,
+      None => return MediumEvent::Surface(Vector3::new(1.0, 1.0, 1.0)),
+    };
+    // 媒質中の自由行程をサンプリング
+    let distance = medium.sample_distance();
+    if distance < i.distance {
+      // 交差した面に到達するまでに散乱
+      let weight = medium.scattering * medium.transmittance(distance) / medium.distance_pdf(distance);
+      // 等方散乱 (位相関数と確率密度は打ち消し合う)
+      let new_ray = Ray {
+        origin: ray.origin + ray.direction * distance,
+        direction: Sampler::sphere_uniform(),
+        differential: None,
+      };
+      MediumEvent::Scatter(new_ray, weight)
+    } else {
+      // 散乱せずに交差した面に到達
+      let weight = medium.transmittance(i.distance) / medium.pass_through_prob(i.distance);
+      MediumEvent::Surface(weight)
+    }
+  }
+
+  fn scattering_radiance<F>(&self, new_ray: Ray, weight: Vector3, depth: usize, f: F) -> Vector3
+    where F: Fn(Ray) -> Vector3
+  {
+    // ロシアンルーレットで再帰を抑制
+    let continue_rr_prob = self.russian_roulette(weight.x.max(weight.y).max(weight.z).min(1.0), depth);
+    if continue_rr_prob != 1.0 && rand::random::<f32>() >= continue_rr_prob {
+      return Vector3::zero();
+    }
+    weight * f(new_ray) / continue_rr_prob
+  }
+
+  fn material_interaction_radiance<F>(&self, i: &Intersection, ray: &Ray, point: &SurfacePoint, normal: Vector3, f: F) -> Vector3
     where F: Fn(Ray) -> Vector3
   {
     let out_ = -ray.direction;
     // BRDFに応じたサンプリング
-    let sample = i.material.sample(out_, i.normal);
+    let sample = i.material.sample(out_, normal, point);
     let in_ = sample.value;
     let pdf = sample.pdf;
+    if pdf <= 0.0 {
+      // サンプリングされ得ない方向は寄与なし
+      return Vector3::zero()
+    }
     // BRDF
-    let brdf = i.material.brdf(out_, in_, i.normal, i.position);
+    let brdf = i.material.brdf(out_, in_, normal, point);
     // 係数
-    let coef = i.material.coef(out_, i.normal, i.distance);
-    // コサイン項
-    let cos = in_.dot(i.normal);
+    let coef = i.material.coef(out_, i.normal, i.distance, point);
+    // コサイン項 (透過の場合は裏側の半球なので絶対値をとる)
+    let cos = in_.dot(normal).abs();
     // assert!(brdf.x * cos < 1.0 && brdf.x * cos > 0.0, "{} {} {}", brdf.x * cos, brdf.x, cos);
     // 放射輝度の圧縮で透過の場合は1を超えてもおかしくない
+    // 交差点での広がりを散乱方向に伝播する
+    // 拡散や光沢の反射は広がりが大きく鏡面として追跡できないので以降はもたない
+    let differential = if i.material.is_delta(out_, in_, normal) {
+      ray.differential.map( |d| {
+        let (dpdx, dpdy) = d.footprint(ray, i.distance, i.normal);
+        RayDifferential::scatter(dpdx, dpdy, d.direction, out_, in_, normal)
+      })
+    } else {
+      None
+    };
     let new_ray = Ray {
       direction: in_,
       origin: i.position,
+      differential: differential,
     };
     // 再帰的にレイを追跡
     let l_i = f(new_ray);
@@ -101,59 +219,219 @@ impl<'a> Scene<'a> {
     brdf * coef * l_i * cos / pdf
   }
 
-  fn direct_light_radiance(&self, i: &Intersection, ray: &Ray) -> Vector3 {
-    if i.material.emission().sqr_norm() > 0.0 || !self.objects.has_emission() {
-      // 交差したマテリアルが放射を持っているとき、NEE対象の光源が存在しないとき
+  // only_delta: 太陽のように広がりをもつ光源はBRDFサンプリングに任せて接続しない
+  fn delta_lights_radiance(&self, i: &Intersection, ray: &Ray, point: &SurfacePoint, normal: Vector3, only_delta: bool) -> Vector3 {
+    // 点光源などはこの物体を照らすものをすべて接続する
+    self.lights.iter().enumerate().filter( |&(k, light)| {
+      self.light_links.light(k, i.object) && (!only_delta || light.is_delta())
+    }).fold(Vector3::zero(), |sum, (_, light)| {
+      sum + self.delta_light_radiance(&**light, i, ray, point, normal)
+    })
+  }
+
+  fn direct_light_radiance(&self, i: &Intersection, ray: &Ray, point: &SurfacePoint, normal: Vector3) -> Vector3 {
+    let delta_light_radiance = self.delta_lights_radiance(i, ray, point, normal, false);
+    // 空はBRDFサンプリングとMISで重み付けする
+    let sky_radiance = self.sky_light_radiance(i, ray, point, normal);
+    if !self.objects.has_emission() {
+      // NEE対象の光源が存在しないとき
+      // (放射を持つマテリアルでもBRDFサンプリングは放射を数えないのでNEEを省略しない)
+      return delta_light_radiance + sky_radiance
+    }
+    delta_light_radiance + sky_radiance + self.area_light_radiance(i, ray, point, normal)
+  }
+
+  // 照らされる点 -> 空の方向 (開口部があれば開口部のサンプリングと空の重点サンプリングを混ぜる)
+  fn sky_sample(&self, position: Vector3) -> Option<Sample<Vector3>> {
+    let portal = match self.portal {
+      Some(ref portal) => portal,
+      None => return self.sky.sample(),
+    };
+    let sample = if rand::random::<f32>() < portal.fraction {
+      portal.sample(position)
+    } else {
+      self.sky.sample()
+    };
+    sample.map( |s| Sample {
+      value: s.value,
+      pdf: self.sky_pdf(position, s.value),
+    }).filter( |s| s.pdf > 0.0 )
+  }
+
+  // 照らされる点, 方向 -> sky_sampleでその方向が得られる確率密度
+  fn sky_pdf(&self, position: Vector3, direction: Vector3) -> f32 {
+    match self.portal {
+      Some(ref portal) => {
+        portal.pdf(position, direction) * portal.fraction + self.sky.pdf(direction) * (1.0 - portal.fraction)
+      },
+      None => self.sky.pdf(direction),
+    }
+  }
+
+  fn sky_light_radiance(&self, i: &Intersection, ray: &Ray, point: &SurfacePoint, normal: Vector3) -> Vector3 {
+    let sky_sample = match self.sky_sample(i.position) {
+      Some(sample) => sample,
+      None => return Vector3::zero(),
+    };
+    let point_in = sky_sample.value;
+    let point_out = -ray.direction;
+    if i.material.is_delta(point_out, point_in, normal) {
+      return Vector3::zero()
+    }
+    let point_normal = i.material.orienting_normal(point_out, normal);
+    let brdf = i.material.brdf(point_out, point_in, point_normal, point);
+    if brdf.sqr_norm() == 0.0 {
+      return Vector3::zero()
+    }
+    let sky_ray = Ray {
+      origin: i.position,
+      direction: point_in,
+      differential: None,
+    };
+    match self.shadow_intersect(&sky_ray) {
+      // 空が遮られている
+      (Some(_), _) => Vector3::zero(),
+      (None, transmittance) => {
+        let point_cos = point_in.dot(point_normal).abs();
+        let bsdf_pdf = i.material.pdf(point_out, point_in, normal, point);
+        let weight = Self::mis_weight(sky_sample.pdf, bsdf_pdf);
+        brdf * self.sky.radiance(&sky_ray) * transmittance * point_cos * weight / sky_sample.pdf
+      },
+    }
+  }
+
+  fn delta_light_radiance(&self, light: &Light, i: &Intersection, ray: &Ray, point: &SurfacePoint, normal: Vector3) -> Vector3 {
+    let light_sample = light.sample(i.position);
+    if light_sample.irradiance.sqr_norm() == 0.0 {
+      return Vector3::zero()
+    }
+    let point_in = light_sample.direction;
+    let point_out = -ray.direction;
+    if i.material.is_delta(point_out, point_in, normal) {
+      // デルタ関数で表される成分は光源と接続できない
+      return Vector3::zero()
+    }
+    let point_normal = i.material.orienting_normal(point_out, normal);
+    let brdf = i.material.brdf(point_out, point_in, point_normal, point);
+    if brdf.sqr_norm() == 0.0 {
       return Vector3::zero()
     }
+    let shadow_ray = Ray {
+      origin: i.position,
+      direction: point_in,
+      differential: None,
+    };
+    match self.shadow_intersect(&shadow_ray) {
+      // 光源より手前で遮られている
+      (Some(shadow_i), _) if shadow_i.distance < light_sample.distance - EPS => Vector3::zero(),
+      (_, transmittance) => {
+        // 光源は確率1で選ばれるので確率密度で割らない
+        let point_cos = point_in.dot(point_normal).abs();
+        brdf * light_sample.irradiance * transmittance * point_cos
+      },
+    }
+  }
+
+  fn area_light_radiance(&self, i: &Intersection, ray: &Ray, point: &SurfacePoint, normal: Vector3) -> Vector3 {
     // 光源上から1点をサンプリング (確率密度は面積測度)
-    let direct_sample = self.objects.sample_emission();
+    let direct_sample = self.objects.sample_emission(i.position);
+    if direct_sample.pdf == 0.0 {
+      // 照らされる点から見て光源の縁に接する点は寄与なし
+      return Vector3::zero()
+    }
     // 交差した座標と光源上の1点のパスを接続
-    let direct_path = direct_sample.value - i.position;
+    let direct_path = direct_sample.value.position - i.position;
     // 可視関数のテストレイを生成
     let direct_ray = Ray {
       origin: i.position,
       direction: direct_path.normalize(),
+      differential: None,
     };
-    // 面に対して可視であるかテスト
     let point_in = direct_ray.direction;
     let point_out = -ray.direction;
-    let point_normal = i.material.orienting_normal(point_out, i.normal);
-    if point_in.dot(point_normal) <= 0.0 {
-      // レイの入射方向とは逆の方向にレイを接続した場合は遮蔽
+    if i.material.is_delta(point_out, point_in, normal) {
+      // デルタ関数で表される成分は光源上の点と接続できない
+      return Vector3::zero()
+    }
+    let point_normal = i.material.orienting_normal(point_out, normal);
+    // BRDF
+    let brdf = i.material.brdf(point_out, point_in, point_normal, point);
+    if brdf.sqr_norm() == 0.0 {
+      // 光源の方向に反射・透過しない場合は寄与なし
       return Vector3::zero()
     }
     // 直接光のみのサンプリングなので可視の場合のみ寄与
-    match self.objects.intersect(&direct_ray) {
-      Some(direct_i) => {
+    match self.shadow_intersect(&direct_ray) {
+      (Some(direct_i), transmittance) => {
         if (direct_i.distance - direct_path.norm()).abs() > EPS {
           // 接続したパスとテストレイの距離が違う場合は遮蔽
           return Vector3::zero()
         }
+        if !self.light_links.emitter(direct_i.object, i.object) {
+          // この物体を照らさない光源
+          return Vector3::zero()
+        }
         let light_out = -direct_ray.direction;
         let light_normal = direct_i.normal;
-        let light_cos = light_out.dot(light_normal);
+        let light_cos = if direct_i.material.two_sided() {
+          light_out.dot(light_normal).abs()
+        } else {
+          light_out.dot(light_normal)
+        };
         if light_cos <= 0.0 {
           // 光源の裏面は寄与なし
           return Vector3::zero()
         }
         // ジオメトリターム (測度の変換)
-        let point_cos = point_in.dot(point_normal);
+        let point_cos = point_in.dot(point_normal).abs();
         let g_term = point_cos * light_cos / direct_path.sqr_norm();
-        // BRDF
-        let brdf = i.material.brdf(point_out, point_in, point_normal, i.position);
-        let l_i = direct_i.material.emission();
+        let l_i = direct_i.material.directional_emission(&direct_i.point(&direct_ray), light_out);
         let pdf = direct_sample.pdf;
-        brdf * l_i * g_term / pdf
+        brdf * l_i * transmittance * g_term / pdf
       },
-      None => Vector3::zero(),
+      (None, _) => Vector3::zero(),
+    }
+  }
+
+  fn shadow_intersect(&self, ray: &Ray) -> (Option<Intersection>, Vector3) {
+    // 直進して透過する面を通り抜けながら, 不透明な面との交差と透過率を求める
+    let mut transmittance = Vector3::new(1.0, 1.0, 1.0);
+    let mut distance = 0.0;
+    let mut shadow_ray = Ray {
+      origin: ray.origin,
+      direction: ray.direction,
+      differential: None,
+    };
+    loop {
+      match self.objects.intersect(&shadow_ray, RayKind::Shadow) {
+        Some(mut i) => {
+          match i.material.transmittance(shadow_ray.direction, i.normal, &i.point(&shadow_ray)) {
+            Some(t) => {
+              transmittance = transmittance * t;
+              distance += i.distance;
+              shadow_ray.origin = i.position;
+            },
+            None => {
+              i.distance += distance;
+              return (Some(i), transmittance)
+            },
+          }
+        },
+        None => return (None, transmittance),
+      }
     }
   }
 
-  fn intersect_radiance(&self, i: &Intersection, ray: &Ray, depth: usize) -> Vector3 {
+  fn is_emitting_side(&self, i: &Intersection, ray: &Ray) -> bool {
+    // 片面の光源は表側からのみ見える
+    i.material.two_sided() || (-ray.direction).dot(i.normal) > 0.0
+  }
+
+  fn intersect_radiance(&self, i: &Intersection, ray: &Ray, depth: usize, receiver: Option<usize>) -> Vector3 {
+    let point = i.point(ray);
     // 放射
-    let l_e = if !(self.no_direct_emitter && depth == 0) && (-ray.direction).dot(i.normal) > 0.0 {
-      i.material.emission()
+    let l_e = if !(self.no_direct_emitter && depth == 0) && self.is_emitting_side(i, ray) && self.emitter_linked(i, receiver) {
+      i.material.directional_emission(&point, -ray.direction)
     } else {
       Vector3::zero()
     };
@@ -162,18 +440,23 @@ impl<'a> Scene<'a> {
     if continue_rr_prob != 1.0 && rand::random::<f32>() >= continue_rr_prob {
       return l_e;
     }
+    // 陰影計算用の法線 (法線マップなどによる摂動を含む)
+    let normal = i.material.normal(&point);
+    // 点光源などはBRDFサンプリングで当たらないので直接接続する (太陽はレイが当たったときに数える)
+    let delta_light_radiance = self.delta_lights_radiance(&i, &ray, &point, normal, true);
     // マテリアルに応じたサンプリングによる寄与
-    let material_radiance = self.material_interaction_radiance(&i, &ray, |new_ray| {
-      self.radiance_recursive(&new_ray, depth + 1)
+    let material_radiance = self.material_interaction_radiance(&i, &ray, &point, normal, |new_ray| {
+      self.radiance_recursive(&new_ray, depth + 1, Some(i.object))
     });
     // ロシアンルーレットを用いた評価で期待値を満たすために確率で割る (再帰抑制用)
-    return l_e + material_radiance / continue_rr_prob;
+    return l_e + (delta_light_radiance + material_radiance) / continue_rr_prob;
   }
 
-  fn intersect_radiance_nee(&self, i: &Intersection, ray: &Ray, depth: usize, no_emission: bool) -> Vector3 {
+  fn intersect_radiance_nee(&self, i: &Intersection, ray: &Ray, depth: usize, no_emission: bool, bsdf_pdf: Option<f32>, receiver: Option<usize>) -> Vector3 {
+    let point = i.point(ray);
     // 放射
-    let l_e = if !(self.no_direct_emitter && depth == 0) && !no_emission && (-ray.direction).dot(i.normal) > 0.0 {
-      i.material.emission()
+    let l_e = if !(self.no_direct_emitter && depth == 0) && !no_emission && self.is_emitting_side(i, ray) && self.emitter_linked(i, receiver) {
+      i.material.directional_emission(&point, -ray.direction)
     } else {
       Vector3::zero()
     };
@@ -182,11 +465,24 @@ impl<'a> Scene<'a> {
     if continue_rr_prob != 1.0 && rand::random::<f32>() >= continue_rr_prob {
       return l_e;
     }
+    // 陰影計算用の法線 (法線マップなどによる摂動を含む)
+    let normal = i.material.normal(&point);
     // 直接光のサンプリングによる寄与
-    let direct_light_radiance = self.direct_light_radiance(&i, &ray);
+    let direct_light_radiance = self.direct_light_radiance(&i, &ray, &point, normal);
     // マテリアルに応じたサンプリングによる寄与
-    let material_radiance = self.material_interaction_radiance(&i, &ray, |new_ray| {
-      self.radiance_nee_recursive(&new_ray, depth + 1, true)
+    let material_radiance = self.material_interaction_radiance(&i, &ray, &point, normal, |new_ray| {
+      let out_ = -ray.direction;
+      let (next_no_emission, next_bsdf_pdf, next_receiver) = if !i.material.is_delta(out_, new_ray.direction, normal) {
+        // NEEで光源を考慮済み
+        (true, Some(i.material.pdf(out_, new_ray.direction, normal, &point)), Some(i.object))
+      } else if (new_ray.direction + out_).sqr_norm() < EPS {
+        // 直進透過はシャドウレイも通り抜けるので, NEEの考慮状況を引き継ぐ
+        (no_emission, bsdf_pdf, receiver)
+      } else {
+        // デルタ関数の反射・屈折はNEEで扱えないので放射を加える
+        (false, None, Some(i.object))
+      };
+      self.radiance_nee_recursive(&new_ray, depth + 1, next_no_emission, next_bsdf_pdf, next_receiver)
     });
     // ロシアンルーレットを用いた評価で期待値を満たすために確率で割る (再帰抑制用)
     return l_e + (direct_light_radiance + material_radiance) / continue_rr_prob;
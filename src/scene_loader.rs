@@ -3,7 +3,7 @@ use math::vector::*;
 use constant::PI;
 
 type Name = String;
-type Vec3 = (f32, f32, f32);
+pub type Vec3 = (f32, f32, f32);
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -14,6 +14,8 @@ pub struct Renderer {
   pub no_direct_emitter: Option<bool>,
   pub threads: Option<usize>,
   pub integrator: Option<String>,
+  // NEEで光源を選ぶ方法 (power, bvh)
+  pub light_selection: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -35,18 +37,141 @@ pub enum Sky {
   Ibl {
     path: String,
     #[serde(default)]
-    longitude_offset: f32
+    longitude_offset: f32,
+    #[serde(default)]
+    transform: Vec<Transform>,
+    #[serde(default)]
+    filter: SkyFilter,
+    #[serde(default = "default_scale")]
+    intensity: f32,
+    // 指定しない場合は縦横比で判別する
+    layout: Option<SkyLayout>,
+  },
+  Simple {
+    meridian: Vec3,
+    horizon: Vec3,
+  },
+  // 昼光モデル
+  #[serde(rename_all = "kebab-case")]
+  Preetham {
+    // 太陽の方向
+    sun_direction: Vec3,
+    #[serde(default = "default_turbidity")]
+    turbidity: f32,
+    #[serde(default = "default_ground_albedo")]
+    ground_albedo: Vec3,
+    #[serde(default = "default_scale")]
+    intensity: f32,
+    // 太陽の円盤を光源として加えるか
+    #[serde(default = "default_sun")]
+    sun: bool,
   },
 }
 
+// 環境マップの画素の補間
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkyFilter {
+  Nearest,
+  Bilinear,
+  Bicubic,
+}
+
+impl Default for SkyFilter {
+  fn default() -> SkyFilter {
+    SkyFilter::Bilinear
+  }
+}
+
+// 環境マップの画像の配置
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkyLayout {
+  Equirectangular,
+  Cube,
+}
+
+fn default_turbidity() -> f32 {
+  3.0
+}
+
+fn default_ground_albedo() -> Vec3 {
+  (0.2, 0.2, 0.2)
+}
+
+fn default_sun() -> bool {
+  true
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type", rename_all = "kebab-case")]
-enum Light {
+pub enum Light {
+  #[serde(rename_all = "kebab-case")]
   Area {
     object: Name,
     emission: Vec3,
     intensity: Option<f32>,
+    // 裏面からも放射するか
+    #[serde(default)]
+    two_sided: bool,
+    // 配光 (IESファイル, 法線方向を照射軸とする)
+    ies: Option<String>,
+    // 照らす物体, 照らさない物体
+    include: Option<Vec<Name>>,
+    exclude: Option<Vec<Name>>,
+  },
+  Point {
+    position: Vec3,
+    intensity: Vec3,
+    // 配光 (IESファイル)
+    ies: Option<String>,
+    // 配光の照射軸 (既定は真下)
+    direction: Option<Vec3>,
+    // 配光の水平角0°の基準方向
+    tangent: Option<Vec3>,
+    include: Option<Vec<Name>>,
+    exclude: Option<Vec<Name>>,
+  },
+  Spot {
+    position: Vec3,
+    direction: Vec3,
+    intensity: Vec3,
+    // 照らす範囲の半頂角 (度)
+    angle: f32,
+    // 範囲の縁で減衰させる幅 (度)
+    #[serde(default)]
+    falloff: f32,
+    // 配光 (IESファイル)
+    ies: Option<String>,
+    // 配光の水平角0°の基準方向
+    tangent: Option<Vec3>,
+    include: Option<Vec<Name>>,
+    exclude: Option<Vec<Name>>,
+  },
+  Directional {
+    // 光の進む方向
+    direction: Vec3,
+    irradiance: Vec3,
+    include: Option<Vec<Name>>,
+    exclude: Option<Vec<Name>>,
   },
+  // 空の光が通る開口部 (物体はレンダリングされない)
+  Portal {
+    object: Name,
+  },
+}
+
+impl Light {
+  // 照らす物体, 照らさない物体の名前
+  pub fn link(&self) -> (Option<&Vec<Name>>, Option<&Vec<Name>>) {
+    match *self {
+      Light::Area { ref include, ref exclude, .. } => (include.as_ref(), exclude.as_ref()),
+      Light::Point { ref include, ref exclude, .. } => (include.as_ref(), exclude.as_ref()),
+      Light::Spot { ref include, ref exclude, .. } => (include.as_ref(), exclude.as_ref()),
+      Light::Directional { ref include, ref exclude, .. } => (include.as_ref(), exclude.as_ref()),
+      Light::Portal { .. } => (None, None),
+    }
+  }
 }
 
 #[derive(Debug, Deserialize)]
@@ -55,8 +180,30 @@ struct Object {
   name: Option<Name>,
   mesh: Name,
   material: Option<Name>,
+  // 切り抜き用の不透明度テクスチャ
+  opacity: Option<Name>,
   #[serde(default)]
   transform: Vec<Transform>,
+  // カメラから直接見えるか
+  #[serde(default = "default_visible")]
+  visible_to_camera: bool,
+  // 影を落とすか
+  #[serde(default = "default_visible")]
+  casts_shadow: bool,
+  // 反射・屈折を通して見えるか
+  #[serde(default = "default_visible")]
+  visible_in_reflections: bool,
+}
+
+fn default_visible() -> bool {
+  true
+}
+
+// 座標変換のみの指定 (空など)
+impl HasTransform for Vec<Transform> {
+  fn transform(&self) -> &Vec<Transform> {
+    self
+  }
 }
 
 impl HasTransform for Object {
@@ -120,16 +267,66 @@ pub enum Camera {
     transform: Vec<Transform>,
   },
   Omnidirectional {
+    #[serde(default)]
+    layout: PanoramaLayout,
+    // 瞳孔間距離 (シーンと同じ長さの単位, 指定すると上下に左右の目を並べたステレオ画像になる)
+    ipd: Option<f32>,
+    #[serde(default)]
+    transform: Vec<Transform>,
+  },
+  Fisheye {
+    // 対角ではなくイメージサークル全体の画角 (度, 360まで, 立体射影は350まで)
+    fov: f32,
+    #[serde(default)]
+    projection: FisheyeProjection,
+    #[serde(default)]
+    transform: Vec<Transform>,
+  },
+  Orthographic {
+    // 写る範囲の横幅 (m)
+    width: f32,
+    #[serde(default)]
     transform: Vec<Transform>,
   },
 }
 
+// 全方位カメラの画像の配置
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum PanoramaLayout {
+  Equirectangular,
+  Cube,
+}
+
+impl Default for PanoramaLayout {
+  fn default() -> PanoramaLayout {
+    PanoramaLayout::Equirectangular
+  }
+}
+
+// 魚眼レンズの射影方式
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum FisheyeProjection {
+  Equidistant,
+  Equisolid,
+  Stereographic,
+}
+
+impl Default for FisheyeProjection {
+  fn default() -> FisheyeProjection {
+    FisheyeProjection::Equidistant
+  }
+}
+
 impl HasTransform for Camera {
   fn transform(&self) -> &Vec<Transform> {
     match *self {
       Camera::IdealPinhole { ref transform, .. } => transform,
       Camera::ThinLens { ref transform, .. } => transform,
       Camera::Omnidirectional { ref transform, .. } => transform,
+      Camera::Fisheye { ref transform, .. } => transform,
+      Camera::Orthographic { ref transform, .. } => transform,
     }
   }
 }
@@ -138,27 +335,156 @@ pub trait HasName {
   fn name(&self) -> Name;
 }
 
+// 定数またはテクスチャの名前
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Color {
+  Value(Vec3),
+  Texture(Name),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Scalar {
+  Value(f32),
+  Texture(Name),
+}
+
+// 手続き的テクスチャを評価する座標
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mapping {
+  Uv,
+  World,
+  Object,
+  Normal,
+}
+
+impl Default for Mapping {
+  fn default() -> Mapping {
+    Mapping::Uv
+  }
+}
+
+fn default_scale() -> f32 {
+  1.0
+}
+
+fn default_distortion() -> f32 {
+  1.0
+}
+
+fn default_strength() -> f32 {
+  1.0
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Texture {
+  Image {
+    name: Name,
+    path: String,
+    gamma: Option<f32>,
+  },
+  Checker {
+    name: Name,
+    #[serde(default)]
+    mapping: Mapping,
+    #[serde(default = "default_scale")]
+    scale: f32,
+    colors: (Vec3, Vec3),
+  },
+  Noise {
+    name: Name,
+    #[serde(default)]
+    mapping: Mapping,
+    #[serde(default = "default_scale")]
+    scale: f32,
+    colors: (Vec3, Vec3),
+    octaves: Option<usize>,
+    #[serde(default)]
+    seed: u32,
+  },
+  Voronoi {
+    name: Name,
+    #[serde(default)]
+    mapping: Mapping,
+    #[serde(default = "default_scale")]
+    scale: f32,
+    colors: (Vec3, Vec3),
+    #[serde(default)]
+    seed: u32,
+  },
+  Gradient {
+    name: Name,
+    #[serde(default)]
+    mapping: Mapping,
+    #[serde(default = "default_scale")]
+    scale: f32,
+    colors: (Vec3, Vec3),
+    axis: Option<Vec3>,
+  },
+  Wood {
+    name: Name,
+    #[serde(default)]
+    mapping: Mapping,
+    #[serde(default = "default_scale")]
+    scale: f32,
+    colors: (Vec3, Vec3),
+    #[serde(default = "default_distortion")]
+    distortion: f32,
+    #[serde(default)]
+    seed: u32,
+  },
+  Marble {
+    name: Name,
+    #[serde(default)]
+    mapping: Mapping,
+    #[serde(default = "default_scale")]
+    scale: f32,
+    colors: (Vec3, Vec3),
+    #[serde(default = "default_distortion")]
+    distortion: f32,
+    #[serde(default)]
+    seed: u32,
+  },
+}
+
+impl HasName for Texture {
+  fn name(&self) -> Name {
+    match *self {
+      Texture::Image { ref name, .. } => name.clone(),
+      Texture::Checker { ref name, .. } => name.clone(),
+      Texture::Noise { ref name, .. } => name.clone(),
+      Texture::Voronoi { ref name, .. } => name.clone(),
+      Texture::Gradient { ref name, .. } => name.clone(),
+      Texture::Wood { ref name, .. } => name.clone(),
+      Texture::Marble { ref name, .. } => name.clone(),
+    }
+  }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type", rename_all = "kebab-case")]
 pub enum Material {
   Lambert {
     name: Name,
-    albedo: Vec3,
+    albedo: Color,
   },
   Phong {
     name: Name,
-    reflectance: Vec3,
+    reflectance: Color,
     alpha: f32,
   },
   BlinnPhong {
     name: Name,
-    reflectance: Vec3,
+    reflectance: Color,
     alpha: f32,
   },
   Ggx {
     name: Name,
-    reflectance: Vec3,
-    roughness: f32,
+    reflectance: Color,
+    roughness: Scalar,
     ior: f32,
   },
   IdealRefraction {
@@ -167,7 +493,71 @@ pub enum Material {
     #[serde(default)]
     absorbtance: f32,
     ior: f32,
-  }
+  },
+  Coated {
+    name: Name,
+    base: Name,
+    ior: f32,
+    #[serde(default)]
+    roughness: f32,
+    tint: Option<Vec3>,
+  },
+  Mix {
+    name: Name,
+    materials: (Name, Name),
+    weight: Scalar,
+  },
+  ThinDielectric {
+    name: Name,
+    reflectance: Vec3,
+    tint: Option<Vec3>,
+    ior: f32,
+  },
+  Translucent {
+    name: Name,
+    albedo: Vec3,
+    transmission: Vec3,
+  },
+  #[serde(rename_all = "kebab-case")]
+  Subsurface {
+    name: Name,
+    albedo: Vec3,
+    mean_free_path: Vec3,
+    ior: f32,
+  },
+  Measured {
+    name: Name,
+    path: String,
+  },
+  NormalMap {
+    name: Name,
+    base: Name,
+    texture: Name,
+    #[serde(default = "default_strength")]
+    strength: f32,
+  },
+  Bump {
+    name: Name,
+    base: Name,
+    texture: Name,
+    #[serde(default = "default_scale")]
+    scale: f32,
+  },
+  Mask {
+    name: Name,
+    base: Name,
+    texture: Name,
+  },
+  #[serde(rename_all = "kebab-case")]
+  Emissive {
+    name: Name,
+    base: Name,
+    emission: Color,
+    #[serde(default = "default_scale")]
+    scale: f32,
+    #[serde(default)]
+    two_sided: bool,
+  },
 }
 
 impl HasName for Material {
@@ -178,6 +568,16 @@ impl HasName for Material {
       Material::BlinnPhong { ref name, ..} => name.clone(),
       Material::Ggx { ref name, ..} => name.clone(),
       Material::IdealRefraction { ref name, ..} => name.clone(),
+      Material::Coated { ref name, ..} => name.clone(),
+      Material::Mix { ref name, ..} => name.clone(),
+      Material::ThinDielectric { ref name, ..} => name.clone(),
+      Material::Translucent { ref name, ..} => name.clone(),
+      Material::Subsurface { ref name, ..} => name.clone(),
+      Material::Measured { ref name, ..} => name.clone(),
+      Material::NormalMap { ref name, ..} => name.clone(),
+      Material::Bump { ref name, ..} => name.clone(),
+      Material::Mask { ref name, ..} => name.clone(),
+      Material::Emissive { ref name, ..} => name.clone(),
     }
   }
 }
@@ -219,13 +619,23 @@ pub struct Config {
   material: Vec<Material>,
   #[serde(default)]
   mesh: Vec<Mesh>,
+  #[serde(default)]
+  texture: Vec<Texture>,
 }
 
 pub struct ObjectDescriptor<'a> {
+  pub name: Option<&'a Name>,
   pub mesh: &'a Mesh,
   pub material: Option<&'a Material>,
   pub transform: &'a Vec<Transform>,
   pub emission: Option<Vector3>,
+  pub two_sided: bool,
+  pub ies: Option<&'a String>,
+  pub opacity: Option<&'a Name>,
+  pub visible_to_camera: bool,
+  pub casts_shadow: bool,
+  pub visible_in_reflections: bool,
+  pub portal: bool,
 }
 
 impl<'a> HasTransform for ObjectDescriptor<'a> {
@@ -240,31 +650,62 @@ impl Config {
     mesh.ok_or(format!("Mesh named `{}` is not found.", name))
   }
 
-  fn find_material_by_name(&self, name: &str) -> Result<&Material, String> {
+  pub fn find_material_by_name(&self, name: &str) -> Result<&Material, String> {
     let material = self.material.iter().find( |m| m.name() == name );
     material.ok_or(format!("Material named `{}` is not found.", name))
   }
 
+  pub fn light(&self) -> &Vec<Light> {
+    &self.light
+  }
+
+  pub fn texture(&self) -> &Vec<Texture> {
+    &self.texture
+  }
+
   pub fn object(&self) -> Vec<ObjectDescriptor> {
     self.object.iter().map( |v| {
       let mesh = self.find_mesh_by_name(&v.mesh).unwrap();
       let material = v.material.as_ref().map( |name|
         self.find_material_by_name(name).unwrap()
       );
-      let emission = self.light.iter().find( |l| match **l {
+      let light = self.light.iter().find( |l| match **l {
         Light::Area { ref object, .. } => {
           v.name.as_ref().map( |name| name.as_str() == object ).unwrap_or(false)
         },
-      } ).map( |l| match *l {
+        _ => false,
+      } );
+      let emission = light.and_then( |l| match *l {
         Light::Area { ref emission, ref intensity, .. } => {
-          Vector3::from(*emission) * (*intensity).unwrap_or(1.0)
-        }
+          Some(Vector3::from(*emission) * (*intensity).unwrap_or(1.0))
+        },
+        _ => None,
+      });
+      let two_sided = light.map( |l| match *l {
+        Light::Area { two_sided, .. } => two_sided,
+        _ => false,
+      }).unwrap_or(false);
+      let portal = self.light.iter().any( |l| match *l {
+        Light::Portal { ref object } => v.name.as_ref().map( |name| name == object ).unwrap_or(false),
+        _ => false,
+      });
+      let ies = light.and_then( |l| match *l {
+        Light::Area { ref ies, .. } => ies.as_ref(),
+        _ => None,
       });
       ObjectDescriptor {
+        name: v.name.as_ref(),
         mesh: &mesh,
         material: material,
         transform: &v.transform,
         emission: emission,
+        two_sided: two_sided,
+        ies: ies,
+        opacity: v.opacity.as_ref(),
+        visible_to_camera: v.visible_to_camera,
+        casts_shadow: v.casts_shadow,
+        visible_in_reflections: v.visible_in_reflections,
+        portal: portal,
       }
     }).collect()
   }
@@ -1,15 +1,20 @@
 use std::sync::Arc;
-use intersection::Intersection;
+use intersection::{Intersection, SurfacePoint};
 use ray::Ray;
 use aabb::AABB;
 use material::material::Material;
-use math::vector::Vector3;
 use sample::Sample;
+use math::vector::*;
 
 pub trait SurfaceShape: Shape {
   fn material(&self) -> Arc<dyn Material>;
   fn area(&self) -> f32;
-  fn sample(&self) -> Sample<Vector3>;
+  // 表面上の点を面積に対して一様にサンプリング
+  fn sample(&self) -> Sample<SurfacePoint>;
+  // 照らされる点から見た立体角に対して一様にサンプリング (確率密度は面積測度に変換して返す)
+  fn sample_solid_angle(&self, _position: Vector3) -> Sample<SurfacePoint> {
+    self.sample()
+  }
 }
 
 pub trait Shape {
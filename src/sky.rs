@@ -1,13 +1,30 @@
 extern crate image;
+extern crate rand;
 
 use ray::Ray;
 use math::vector::*;
+use math::matrix::*;
 use constant::*;
+use texture::texture::{MipMap, Address};
+use distribution::Distribution2D;
+use sample::Sample;
 use std::fs::File;
 use std::io::BufReader;
 
 pub trait Sky {
   fn radiance(&self, &Ray) -> Vector3;
+  // NEEで空の方向をサンプリングする (確率密度は立体角測度, できない場合はNone)
+  fn sample(&self) -> Option<Sample<Vector3>> {
+    None
+  }
+  // 方向 -> sampleでその方向が得られる確率密度
+  fn pdf(&self, _direction: Vector3) -> f32 {
+    0.0
+  }
+  // sampleで方向をサンプリングできるか
+  fn can_sample(&self) -> bool {
+    false
+  }
 }
 
 pub struct UniformSky {
@@ -32,48 +49,505 @@ impl Sky for SimpleSky {
   }
 }
 
+// 環境マップの画素の補間
+#[derive(Clone, Copy)]
+pub enum SkyFilter {
+  Nearest,
+  Bilinear,
+  Bicubic,
+}
+
+// 環境マップの画像の配置
+#[derive(Clone, Copy)]
+pub enum SkyLayout {
+  // 緯度経度 (縦横比は問わない)
+  Equirectangular,
+  // キューブマップ (十字形または1列の並びを縦横比で判別する)
+  Cube,
+}
+
 pub struct IBLSky {
-  hdr_image: Vec<image::Rgb<f32>>,
-  height: usize,
+  mipmap: MipMap,
   longitude_offset: f32,
+  // 空の座標変換 (ワールド座標 -> 画像の座標系)
+  inverse: Matrix4,
+  transform: Matrix4,
+  filter: SkyFilter,
+  intensity: f32,
+  // 画素の明るさ x sin(theta) に比例する分布
+  distribution: Distribution2D,
 }
 
 impl IBLSky {
-  pub fn new(path: &str, longitude_offset: f32) -> IBLSky {
+  pub fn new(path: &str, longitude_offset: f32, transform: Matrix4, filter: SkyFilter, intensity: f32, layout: Option<SkyLayout>) -> IBLSky {
     println!("loading hdr image...");
     let image_file = File::open(path).unwrap();
     let decoder = image::hdr::HDRDecoder::new(BufReader::new(image_file)).unwrap();
     println!("{:?}", decoder.metadata());
+    let width = decoder.metadata().width as usize;
     let height = decoder.metadata().height as usize;
     let image = decoder.read_image_hdr().unwrap();
+    let data = image.iter().take(width * height).map( |c|
+      Vector3::new(c.data[0], c.data[1], c.data[2])
+    ).collect::<Vec<_>>();
+    let layout = layout.unwrap_or(
+      if width != height * 2 && CubeFaces::arrangement(width, height).is_some() {
+        SkyLayout::Cube
+      } else {
+        SkyLayout::Equirectangular
+      }
+    );
+    // 経度方向は一周してつながるが, 緯度方向は極で折り返さない
+    let mipmap = match layout {
+      SkyLayout::Equirectangular => MipMap::new(width, height, data),
+      SkyLayout::Cube => {
+        // 緯度経度の画像に変換して以降の処理を共通にする
+        let faces = CubeFaces::new(width, height, data).unwrap_or_else( ||
+          panic!(format!("Unsupported cube map layout `{}x{}` in `{}`.", width, height, path))
+        );
+        let (w, h) = (faces.size * 4, faces.size * 2);
+        let data = (0..w * h).map( |i| {
+          let u = ((i % w) as f32 + 0.5) / w as f32;
+          let v = ((i / w) as f32 + 0.5) / h as f32;
+          faces.value(Self::direction(u, v))
+        }).collect();
+        MipMap::new(w, h, data)
+      },
+    }.with_address(Address::Repeat, Address::Clamp);
+    let distribution = Self::distribution(&mipmap);
     IBLSky {
-      hdr_image: image,
-      height: height,
+      mipmap: mipmap,
       longitude_offset: longitude_offset,
+      inverse: transform.inverse(),
+      transform: transform,
+      filter: filter,
+      intensity: intensity,
+      distribution: distribution,
     }
   }
-}
 
-impl Sky for IBLSky {
-  fn radiance(&self, ray: &Ray) -> Vector3 {
+  fn distribution(mipmap: &MipMap) -> Distribution2D {
+    let width = mipmap.width();
+    let height = mipmap.height();
+    let mut func = Vec::with_capacity(width * height);
+    for y in 0..height {
+      let v = (y as f32 + 0.5) / height as f32;
+      // 極に近い画素ほど張る立体角が小さい
+      let sin_theta = (v * PI).sin();
+      for x in 0..width {
+        let c = mipmap.nearest(0, (x as f32 + 0.5) / width as f32, v);
+        func.push(c.x.max(c.y).max(c.z) * sin_theta);
+      }
+    }
+    Distribution2D::new(func, width, height)
+  }
+
+  // 画像上の座標 -> 画像の座標系での方向 (経度のずれを含まない)
+  fn direction(u: f32, v: f32) -> Vector3 {
+    let theta = v * PI;
+    let phi = u * 2.0 * PI - PI;
+    Vector3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin())
+  }
+
+  // ワールド座標とのあいだの方向の変換
+  fn rotate(matrix: &Matrix4, direction: Vector3) -> Vector3 {
+    Vector3::from(matrix * Vector4::new(direction.x, direction.y, direction.z, 0.0)).normalize()
+  }
+
+  // 方向 -> 画像上の座標
+  fn uv(&self, direction: Vector3) -> (f32, f32) {
+    let direction = Self::rotate(&self.inverse, direction);
     // 0 <= theta <= pi
-    let theta = (ray.direction.y).acos();
+    let theta = direction.y.max(-1.0).min(1.0).acos();
     // -pi < phi <= pi
-    let phi = ray.direction.z.atan2(ray.direction.x);
-    // 0 <= (u, v) < 1
+    let phi = direction.z.atan2(direction.x);
+    // 0 <= u < 1, 0 <= v <= 1
     let u = ((phi + PI + self.longitude_offset) / (2.0 * PI)) % 1.0;
-    let v = (theta / PI) % 1.0;
-    let height = self.height;
-    let width = self.height * 2;
-    let all = width * height;
-    let x = (width as f32 * u).floor() as usize;
-    let y = (height as f32 * v).floor() as usize;
-    let index = y * width + x;
-    let color = self.hdr_image[index % all];
-    return Vector3::new(
-      color.data[0] as f32,
-      color.data[1] as f32,
-      color.data[2] as f32,
+    let v = theta / PI;
+    (u, v)
+  }
+
+  fn lookup(&self, level: usize, u: f32, v: f32) -> Vector3 {
+    match self.filter {
+      SkyFilter::Nearest => self.mipmap.nearest(level, u, v),
+      SkyFilter::Bilinear => self.mipmap.bilinear(level, u, v),
+      SkyFilter::Bicubic => self.mipmap.bicubic(level, u, v),
+    }
+  }
+}
+
+impl Sky for IBLSky {
+  fn radiance(&self, ray: &Ray) -> Vector3 {
+    let (u, v) = self.uv(ray.direction);
+    // 方向の広がりに対応する画素数 (緯度方向はpiで高さ分)
+    let width = ray.differential.map( |d|
+      d.direction.0.norm().max(d.direction.1.norm()) / PI * self.mipmap.height() as f32
+    ).unwrap_or(0.0);
+    let level = self.mipmap.level(width);
+    let l0 = level.floor();
+    let t = level - l0;
+    let c0 = self.lookup(l0 as usize, u, v);
+    if t == 0.0 {
+      return c0 * self.intensity
+    }
+    (c0 * (1.0 - t) + self.lookup(l0 as usize + 1, u, v) * t) * self.intensity
+  }
+
+  fn can_sample(&self) -> bool {
+    true
+  }
+
+  fn sample(&self) -> Option<Sample<Vector3>> {
+    let sample = self.distribution.sample_continuous(rand::random::<f32>(), rand::random::<f32>());
+    let (u, v) = sample.value;
+    let sin_theta = (v * PI).sin();
+    if sin_theta <= 0.0 {
+      return None
+    }
+    let direction = Self::direction(u - self.longitude_offset / (2.0 * PI), v);
+    // 画像上の確率密度 -> 立体角測度 (d omega = 2 pi^2 sin(theta) du dv)
+    Some(Sample {
+      value: Self::rotate(&self.transform, direction),
+      pdf: sample.pdf / (2.0 * PI * PI * sin_theta),
+    })
+  }
+
+  fn pdf(&self, direction: Vector3) -> f32 {
+    let (u, v) = self.uv(direction);
+    let sin_theta = (v * PI).sin();
+    if sin_theta <= 0.0 {
+      return 0.0
+    }
+    self.distribution.pdf_at(u, v) / (2.0 * PI * PI * sin_theta)
+  }
+}
+
+// キューブマップの6面 (+X, -X, +Y, -Y, +Z, -Z の順)
+pub struct CubeFaces {
+  size: usize,
+  width: usize,
+  data: Vec<Vector3>,
+  // 面ごとの画像上の位置 (面の単位) と180度回転しているか
+  placement: [(usize, usize, bool); 6],
+}
+
+impl CubeFaces {
+  // 縦横比から面の並びを判別する
+  pub fn arrangement(width: usize, height: usize) -> Option<(usize, [(usize, usize, bool); 6])> {
+    if width * 3 == height * 4 {
+      // 横長の十字形
+      Some((width / 4, [(2, 1, false), (0, 1, false), (1, 0, false), (1, 2, false), (1, 1, false), (3, 1, false)]))
+    } else if width * 4 == height * 3 {
+      // 縦長の十字形 (-Zは上下左右が反転している)
+      Some((width / 3, [(2, 1, false), (0, 1, false), (1, 0, false), (1, 2, false), (1, 1, false), (1, 3, true)]))
+    } else if width == height * 6 {
+      Some((height, [(0, 0, false), (1, 0, false), (2, 0, false), (3, 0, false), (4, 0, false), (5, 0, false)]))
+    } else if height == width * 6 {
+      Some((width, [(0, 0, false), (0, 1, false), (0, 2, false), (0, 3, false), (0, 4, false), (0, 5, false)]))
+    } else {
+      None
+    }
+  }
+
+  fn new(width: usize, height: usize, data: Vec<Vector3>) -> Option<CubeFaces> {
+    Self::arrangement(width, height).map( |(size, placement)| CubeFaces {
+      size: size,
+      width: width,
+      data: data,
+      placement: placement,
+    })
+  }
+
+  fn texel(&self, face: usize, x: isize, y: isize) -> Vector3 {
+    let (col, row, flip) = self.placement[face];
+    // 面の外には出ないようにする
+    let last = self.size as isize - 1;
+    let (x, y) = (x.max(0).min(last) as usize, y.max(0).min(last) as usize);
+    let (x, y) = if flip { (self.size - 1 - x, self.size - 1 - y) } else { (x, y) };
+    self.data[(row * self.size + y) * self.width + col * self.size + x]
+  }
+
+  // 方向 -> 主軸の面と面上の座標 (OpenGLの規約, -1~1)
+  pub fn project(d: Vector3) -> (usize, f32, f32) {
+    let (ax, ay, az) = (d.x.abs(), d.y.abs(), d.z.abs());
+    let (face, sc, tc, ma) = if ax >= ay && ax >= az {
+      if d.x > 0.0 { (0, -d.z, -d.y, ax) } else { (1, d.z, -d.y, ax) }
+    } else if ay >= az {
+      if d.y > 0.0 { (2, d.x, d.z, ay) } else { (3, d.x, -d.z, ay) }
+    } else {
+      if d.z > 0.0 { (4, d.x, -d.y, az) } else { (5, -d.x, -d.y, az) }
+    };
+    (face, sc / ma, tc / ma)
+  }
+
+  // 面と面上の座標 -> 方向 (projectの逆, 正規化はしない)
+  pub fn unproject(face: usize, s: f32, t: f32) -> Vector3 {
+    match face {
+      0 => Vector3::new(1.0, -t, -s),
+      1 => Vector3::new(-1.0, -t, s),
+      2 => Vector3::new(s, 1.0, t),
+      3 => Vector3::new(s, -1.0, -t),
+      4 => Vector3::new(s, -t, 1.0),
+      _ => Vector3::new(-s, -t, -1.0),
+    }
+  }
+
+  // 方向 -> 面の上でバイリニア補間した値
+  fn value(&self, d: Vector3) -> Vector3 {
+    let (face, s, t) = Self::project(d);
+    let x = (s + 1.0) * 0.5 * self.size as f32 - 0.5;
+    let y = (t + 1.0) * 0.5 * self.size as f32 - 0.5;
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let (fx, fy) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as isize, y0 as isize);
+    self.texel(face, x0, y0) * (1.0 - fx) * (1.0 - fy) +
+      self.texel(face, x0 + 1, y0) * fx * (1.0 - fy) +
+      self.texel(face, x0, y0 + 1) * (1.0 - fx) * fy +
+      self.texel(face, x0 + 1, y0 + 1) * fx * fy
+  }
+}
+
+// 放射輝度の単位 (cd/m^2): 1.0 が 10^4 cd/m^2 になるように正規化する
+const DAYLIGHT_UNIT: f32 = 1e4;
+// 太陽の視半径 (rad)
+pub const SUN_ANGULAR_RADIUS: f32 = 0.00465;
+// 大気圏外での太陽の輝度 (cd/m^2)
+const SUN_LUMINANCE: f32 = 2.0e9;
+
+// Perezの輝度分布関数の係数
+struct Perez {
+  a: f32,
+  b: f32,
+  c: f32,
+  d: f32,
+  e: f32,
+}
+
+impl Perez {
+  // theta: 天頂角, gamma: 太陽との角度
+  fn value(&self, cos_theta: f32, gamma: f32) -> f32 {
+    let cos_gamma = gamma.cos();
+    (1.0 + self.a * (self.b / cos_theta.max(0.01)).exp()) *
+      (1.0 + self.c * (self.d * gamma).exp() + self.e * cos_gamma * cos_gamma)
+  }
+}
+
+// Preethamの昼光モデル [Preetham et al. 1999] (太陽の円盤は含まない)
+pub struct PreethamSky {
+  // 太陽の方向
+  sun_direction: Vector3,
+  // 大気の濁り具合
+  turbidity: f32,
+  // Y, x, y それぞれの分布と天頂の値
+  perez: [Perez; 3],
+  zenith: [f32; 3],
+  // 天頂の値を得るための正規化 (F(0, theta_s))
+  normalize: [f32; 3],
+  // 地面の放射輝度 (倍率を含む)
+  ground: Vector3,
+  intensity: f32,
+}
+
+impl PreethamSky {
+  pub fn new(sun_direction: Vector3, turbidity: f32, ground_albedo: Vector3, intensity: f32) -> PreethamSky {
+    let sun_direction = sun_direction.normalize();
+    let t = turbidity;
+    // 太陽が地平線より下の場合は地平線上にあるものとして扱う
+    let theta_s = sun_direction.y.max(0.0).min(1.0).acos();
+    let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+    let zenith_y = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192) * 1000.0;
+    let (t2, s, s2, s3) = (t * t, theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+    let zenith_x =
+      t2 * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s) +
+      t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s + 0.00394) +
+      (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s + 0.25886);
+    let zenith_yy =
+      t2 * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s) +
+      t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s + 0.00516) +
+      (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s + 0.26688);
+    let perez = [
+      Perez { a: 0.1787 * t - 1.4630, b: -0.3554 * t + 0.4275, c: -0.0227 * t + 5.3251, d: 0.1206 * t - 2.5771, e: -0.0670 * t + 0.3703 },
+      Perez { a: -0.0193 * t - 0.2592, b: -0.0665 * t + 0.0008, c: -0.0004 * t + 0.2125, d: -0.0641 * t - 0.8989, e: -0.0033 * t + 0.0452 },
+      Perez { a: -0.0167 * t - 0.2608, b: -0.0950 * t + 0.0092, c: -0.0079 * t + 0.2102, d: -0.0441 * t - 1.6537, e: -0.0109 * t + 0.0529 },
+    ];
+    let normalize = [
+      perez[0].value(1.0, theta_s),
+      perez[1].value(1.0, theta_s),
+      perez[2].value(1.0, theta_s),
+    ];
+    let mut sky = PreethamSky {
+      sun_direction: sun_direction,
+      turbidity: turbidity,
+      perez: perez,
+      zenith: [zenith_y.max(0.0), zenith_x, zenith_yy],
+      normalize: normalize,
+      ground: Vector3::zero(),
+      intensity: intensity,
+    };
+    // 地面は空と太陽に照らされた完全拡散面とする
+    let irradiance = sky.sky_irradiance() * intensity + sky.sun_radiance() * sky.sun_solid_angle() * sun_direction.y.max(0.0);
+    sky.ground = ground_albedo * irradiance / PI;
+    sky
+  }
+
+  fn sky_radiance(&self, direction: Vector3) -> Vector3 {
+    let cos_theta = direction.y;
+    let gamma = direction.dot(self.sun_direction).max(-1.0).min(1.0).acos();
+    let v = |i: usize| self.zenith[i] * self.perez[i].value(cos_theta, gamma) / self.normalize[i];
+    let (luminance, x, y) = (v(0), v(1), v(2));
+    // Yxy -> XYZ -> 線形sRGB
+    let cx = x / y * luminance;
+    let cz = (1.0 - x - y) / y * luminance;
+    let rgb = Vector3::new(
+      3.2406 * cx - 1.5372 * luminance - 0.4986 * cz,
+      -0.9689 * cx + 1.8758 * luminance + 0.0415 * cz,
+      0.0557 * cx - 0.2040 * luminance + 1.0570 * cz,
     );
+    Vector3::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0)) / DAYLIGHT_UNIT
+  }
+
+  // 天空からの水平面の放射照度 (数値積分)
+  fn sky_irradiance(&self) -> Vector3 {
+    let (n_theta, n_phi) = (32, 64);
+    let mut sum = Vector3::zero();
+    for i in 0..n_theta {
+      let theta = (i as f32 + 0.5) / n_theta as f32 * PI / 2.0;
+      for j in 0..n_phi {
+        let phi = (j as f32 + 0.5) / n_phi as f32 * 2.0 * PI;
+        let direction = Vector3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+        // L cos(theta) sin(theta) dtheta dphi
+        sum = sum + self.sky_radiance(direction) * (theta.cos() * theta.sin());
+      }
+    }
+    sum * (PI / 2.0 / n_theta as f32) * (2.0 * PI / n_phi as f32)
+  }
+
+  fn sun_solid_angle(&self) -> f32 {
+    2.0 * PI * (1.0 - SUN_ANGULAR_RADIUS.cos())
+  }
+
+  // 大気で減衰した太陽の放射輝度 (レイリー散乱とエアロゾルによる)
+  pub fn sun_radiance(&self) -> Vector3 {
+    if self.sun_direction.y <= 0.0 {
+      return Vector3::zero()
+    }
+    let theta_deg = self.sun_direction.y.min(1.0).acos() * 180.0 / PI;
+    // 相対的な大気の厚さ [Kasten and Young 1989]
+    let air_mass = 1.0 / (self.sun_direction.y + 0.50572 * (96.07995 - theta_deg).powf(-1.6364));
+    let beta = 0.04608 * self.turbidity - 0.04586;
+    let transmittance = |lambda: f32| {
+      // 波長はum
+      let rayleigh = 0.008735 * lambda.powf(-4.08);
+      let aerosol = beta * lambda.powf(-1.3);
+      (-air_mass * (rayleigh + aerosol)).exp()
+    };
+    Vector3::new(transmittance(0.680), transmittance(0.550), transmittance(0.440)) * (SUN_LUMINANCE / DAYLIGHT_UNIT * self.intensity)
+  }
+
+  pub fn sun_direction(&self) -> Vector3 {
+    self.sun_direction
+  }
+}
+
+impl Sky for PreethamSky {
+  fn radiance(&self, ray: &Ray) -> Vector3 {
+    if ray.direction.y < 0.0 {
+      return self.ground
+    }
+    self.sky_radiance(ray.direction) * self.intensity
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn ray(direction: Vector3) -> Ray {
+    Ray {
+      origin: Vector3::zero(),
+      direction: direction.normalize(),
+      differential: None,
+    }
+  }
+
+  #[test]
+  fn cube_layout_test() {
+    // 面ごとに番号の値をもつ画像
+    let size = 4;
+    for &(width, height) in &[(size * 4, size * 3), (size * 3, size * 4), (size * 6, size), (size, size * 6)] {
+      let (_, placement) = CubeFaces::arrangement(width, height).unwrap();
+      let mut data = vec![Vector3::new(-1.0, 0.0, 0.0); width * height];
+      for (face, &(col, row, _)) in placement.iter().enumerate() {
+        for y in 0..size {
+          for x in 0..size {
+            // 画像上の画素の座標も埋め込んで面の向きを確かめる
+            let (px, py) = (col * size + x, row * size + y);
+            data[py * width + px] = Vector3::new(face as f32, px as f32, py as f32);
+          }
+        }
+      }
+      let faces = CubeFaces::new(width, height, data).unwrap();
+      let directions = [
+        Vector3::new(1.0, 0.1, 0.2), Vector3::new(-1.0, 0.1, 0.2),
+        Vector3::new(0.1, 1.0, 0.2), Vector3::new(0.1, -1.0, 0.2),
+        Vector3::new(0.1, 0.2, 1.0), Vector3::new(0.1, 0.2, -1.0),
+      ];
+      for (face, &d) in directions.iter().enumerate() {
+        assert!((faces.value(d.normalize()).x - face as f32).abs() < EPS);
+      }
+      // 十字形では隣り合う面の境界をまたぐ方向が画像上でも隣り合う画素になる
+      if width * 3 == height * 4 || width * 4 == height * 3 {
+        let mut edges = vec![
+          // +Zと左右上下の面
+          (Vector3::new(1.0, 0.3, 1.01), Vector3::new(1.01, 0.3, 1.0)),
+          (Vector3::new(-1.0, 0.3, 1.01), Vector3::new(-1.01, 0.3, 1.0)),
+          (Vector3::new(0.3, 1.0, 1.01), Vector3::new(0.3, 1.01, 1.0)),
+          (Vector3::new(0.3, -1.0, 1.01), Vector3::new(0.3, -1.01, 1.0)),
+        ];
+        if width * 3 == height * 4 {
+          // 横長では+Xと-Zも隣り合う
+          edges.push((Vector3::new(1.01, 0.3, -1.0), Vector3::new(1.0, 0.3, -1.01)));
+        } else {
+          // 縦長では-Yと-Zも隣り合う
+          edges.push((Vector3::new(0.3, -1.01, -1.0), Vector3::new(0.3, -1.0, -1.01)));
+        }
+        for &(a, b) in &edges {
+          let (va, vb) = (faces.value(a.normalize()), faces.value(b.normalize()));
+          assert!(va.x != vb.x, "{} {}", va, vb);
+          let distance = (Vector3::new(0.0, va.y, va.z) - Vector3::new(0.0, vb.y, vb.z)).norm();
+          assert!(distance < 1.5, "{} {} {} {}", width, height, va, vb);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn cube_unproject_test() {
+    for face in 0..6 {
+      for &(s, t) in &[(0.0, 0.0), (0.5, -0.3), (-0.9, 0.7)] {
+        let (f, ps, pt) = CubeFaces::project(CubeFaces::unproject(face, s, t).normalize());
+        assert_eq!(f, face);
+        assert!((ps - s).abs() < EPS && (pt - t).abs() < EPS, "{} {} {} {}", face, s, t, ps);
+      }
+    }
+  }
+
+  #[test]
+  fn preetham_test() {
+    let sun = Vector3::new(1.0, 1.0, 0.0).normalize();
+    let sky = PreethamSky::new(sun, 3.0, Vector3::new(0.2, 0.2, 0.2), 1.0);
+    // 太陽の近くは反対側より明るく, 澄んだ空は青い
+    let near = sky.radiance(&ray(Vector3::new(1.0, 0.8, 0.0)));
+    let far = sky.radiance(&ray(Vector3::new(-1.0, 0.8, 0.0)));
+    let zenith = sky.radiance(&ray(Vector3::new(0.0, 1.0, 0.0)));
+    assert!(near.y > far.y, "{} {}", near, far);
+    assert!(zenith.z > zenith.x, "{}", zenith);
+    // 減衰した太陽は白より赤みがかる
+    let sun_radiance = sky.sun_radiance();
+    assert!(sun_radiance.x > sun_radiance.z, "{}", sun_radiance);
+    // 地面は一様
+    let ground = sky.radiance(&ray(Vector3::new(0.3, -1.0, 0.2)));
+    assert!((ground - sky.radiance(&ray(Vector3::new(0.0, -1.0, 0.0)))).norm() < EPS);
   }
 }
@@ -1,8 +1,10 @@
+extern crate rand;
+
 use std::sync::Arc;
 use math::vector::*;
 use ray::Ray;
 use material::material::Material;
-use intersection::Intersection;
+use intersection::{Intersection, SurfacePoint};
 use constant::*;
 use shape::*;
 use aabb::AABB;
@@ -28,6 +30,13 @@ impl Sphere {
     }
   }
 
+  // 緯度経度によるテクスチャ座標
+  fn uv(normal: Vector3) -> (f32, f32) {
+    let phi = normal.z.atan2(normal.x);
+    let theta = normal.y.max(-1.0).min(1.0).acos();
+    ((phi + PI) / (2.0 * PI), 1.0 - theta / PI)
+  }
+
   fn aabb(position: Vector3, radius: f32) -> AABB {
     let r = Vector3::new(radius, radius, radius);
     AABB {
@@ -54,11 +63,22 @@ impl Shape for Sphere {
     let distance = if t1 > EPS { t1 } else { t2 };
     let position = ray.origin + ray.direction * distance;
     let outer_normal = (position - self.position).normalize();
+    let phi = outer_normal.z.atan2(outer_normal.x);
+    let theta = outer_normal.y.max(-1.0).min(1.0).acos();
+    // 経度, 緯度についての位置の偏微分
+    let dpdphi = Vector3::new(-outer_normal.z, 0.0, outer_normal.x) * self.radius;
+    let dpdtheta = Vector3::new(theta.cos() * phi.cos(), -theta.sin(), theta.cos() * phi.sin()) * self.radius;
     Some(Intersection {
       distance: distance,
       position: position,
       normal: outer_normal,
+      shading_normal: outer_normal,
+      tangent: dpdphi,
+      uv: Self::uv(outer_normal),
+      dpdu: dpdphi * (2.0 * PI),
+      dpdv: dpdtheta * -PI,
       material: self.material.clone(),
+      object: 0,
     })
   }
 
@@ -76,10 +96,83 @@ impl SurfaceShape for Sphere {
     self.area
   }
 
-  fn sample(&self) -> Sample<Vector3> {
+  fn sample(&self) -> Sample<SurfacePoint> {
+    let normal = Sampler::sphere_uniform();
     Sample {
-      value: self.position + self.radius * Sampler::sphere_uniform(),
+      value: SurfacePoint::new(self.position + self.radius * normal, normal, Self::uv(normal)),
       pdf: 1.0 / self.area,
     }
   }
+
+  fn sample_solid_angle(&self, position: Vector3) -> Sample<SurfacePoint> {
+    let path = self.position - position;
+    let sqr_distance = path.sqr_norm();
+    if sqr_distance <= self.radius * self.radius * (1.0 + EPS) {
+      // 球の内側からは全体が見えるので面積に対して一様にサンプリング
+      return self.sample()
+    }
+    // 照らされる点から球が見える円錐の中で方向を一様にサンプリング
+    let distance = sqr_distance.sqrt();
+    let w = path / distance;
+    let sin_max2 = self.radius * self.radius / sqr_distance;
+    let cos_max = (1.0 - sin_max2).max(0.0).sqrt();
+    // 遠くの小さな球でも桁落ちしないように 1 - cos を sin^2 / (1 + cos) で求める
+    let one_minus_cos_max = sin_max2 / (1.0 + cos_max);
+    let one_minus_cos = rand::random::<f32>() * one_minus_cos_max;
+    let cos_theta = 1.0 - one_minus_cos;
+    let sin_theta2 = (one_minus_cos * (2.0 - one_minus_cos)).max(0.0);
+    let phi = 2.0 * PI * rand::random::<f32>();
+    // 方向と球の交点を球の中心から見た角度で求める
+    let ds = distance * cos_theta - (self.radius * self.radius - sqr_distance * sin_theta2).max(0.0).sqrt();
+    let cos_alpha = ((sqr_distance + self.radius * self.radius - ds * ds) / (2.0 * distance * self.radius)).max(-1.0).min(1.0);
+    let sin_alpha = (1.0 - cos_alpha * cos_alpha).max(0.0).sqrt();
+    let (u, v) = w.orthonormal_basis();
+    let normal = -(u * (sin_alpha * phi.cos()) + v * (sin_alpha * phi.sin()) + w * cos_alpha);
+    let point = self.position + self.radius * normal;
+    // 立体角測度の確率密度を面積測度に変換
+    let light_path = point - position;
+    let light_cos = normal.dot(-light_path.normalize()).max(0.0);
+    let pdf = light_cos / (2.0 * PI * one_minus_cos_max * light_path.sqr_norm());
+    Sample {
+      value: SurfacePoint::new(point, normal, Self::uv(normal)),
+      pdf: pdf,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use material::material::*;
+  use texture::texture::ConstantTexture;
+
+  #[test]
+  fn sample_solid_angle_test() {
+    let sphere = Sphere::new(
+      Vector3::zero(),
+      1.0,
+      Arc::new(LambertianMaterial { emission: Vector3::zero(), albedo: Arc::new(ConstantTexture { value: Vector3::zero() }) }),
+    );
+    // 照らされる点から見える側の点が円錐内で一様に選ばれ, 面積測度の確率密度は立体角測度から変換したもの
+    let position = Vector3::new(0.0, 0.0, 4.0);
+    let cos_max = (1.0 - 1.0 / 16.0f32).sqrt();
+    let solid_angle = 2.0 * PI * (1.0 - cos_max);
+    let n = 20000;
+    let mut sum = 0.0;
+    for _ in 0..n {
+      let s = sphere.sample_solid_angle(position);
+      let path = s.value.position - position;
+      let light_cos = s.value.normal.dot(-path.normalize());
+      assert!((s.value.position.norm() - 1.0).abs() < 1e-3);
+      assert!(light_cos >= -1e-3);
+      if s.pdf > 0.0 {
+        let omega = light_cos / (path.sqr_norm() * s.pdf);
+        assert!((omega - solid_angle).abs() < solid_angle * 1e-2, "{} {}", omega, solid_angle);
+      }
+      sum += path.normalize().dot(Vector3::new(0.0, 0.0, -1.0));
+    }
+    let mean_cos = sum / n as f32;
+    let expected = (1.0 + cos_max) / 2.0;
+    assert!((mean_cos - expected).abs() < (1.0 - cos_max) * 0.05, "{} {}", mean_cos, expected);
+  }
 }
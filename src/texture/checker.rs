@@ -0,0 +1,26 @@
+use super::procedural::Pattern;
+use math::vector::*;
+
+// 単位立方体ごとに交互に並ぶ市松模様
+pub struct CheckerPattern;
+
+impl Pattern for CheckerPattern {
+  fn value(&self, p: Vector3) -> f32 {
+    let sum = p.x.floor() as i64 + p.y.floor() as i64 + p.z.floor() as i64;
+    if sum % 2 == 0 { 0.0 } else { 1.0 }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn alternate_test() {
+    let c = CheckerPattern;
+    assert!(c.value(Vector3::new(0.5, 0.5, 0.0)) == 0.0);
+    assert!(c.value(Vector3::new(1.5, 0.5, 0.0)) == 1.0);
+    assert!(c.value(Vector3::new(-0.5, 0.5, 0.0)) == 1.0);
+    assert!(c.value(Vector3::new(-0.5, -0.5, 0.0)) == 0.0);
+  }
+}
@@ -0,0 +1,17 @@
+use super::traits::Texture;
+use math::vector::*;
+use intersection::SurfacePoint;
+
+pub struct ConstantTexture {
+  pub value: Vector3,
+}
+
+impl Texture for ConstantTexture {
+  fn value(&self, _point: &SurfacePoint) -> Vector3 {
+    self.value
+  }
+
+  fn max_value(&self) -> Vector3 {
+    self.value
+  }
+}
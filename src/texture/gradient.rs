@@ -0,0 +1,13 @@
+use super::procedural::Pattern;
+use math::vector::*;
+
+// 軸方向に0から1へ線形に変化する模様
+pub struct GradientPattern {
+  pub axis: Vector3,
+}
+
+impl Pattern for GradientPattern {
+  fn value(&self, p: Vector3) -> f32 {
+    p.dot(self.axis)
+  }
+}
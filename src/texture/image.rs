@@ -0,0 +1,106 @@
+extern crate image;
+
+use super::traits::Texture;
+use super::mipmap::MipMap;
+use math::vector::*;
+use intersection::SurfacePoint;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+pub struct ImageTexture {
+  // 線形色空間での画素値のピラミッド
+  mipmap: MipMap,
+  max: Vector3,
+}
+
+impl ImageTexture {
+  pub fn load(path: &str, gamma: Option<f32>) -> ImageTexture {
+    if !Path::new(path).is_file() { panic!(format!("File `{}` is not found.", path)) }
+    let is_hdr = Path::new(path).extension()
+      .map( |ext| ext.to_string_lossy().to_lowercase() == "hdr" )
+      .unwrap_or(false);
+    if is_hdr {
+      // HDRは線形の値をそのままつかう
+      let file = File::open(path).unwrap();
+      let decoder = image::hdr::HDRDecoder::new(BufReader::new(file)).unwrap();
+      let width = decoder.metadata().width as usize;
+      let height = decoder.metadata().height as usize;
+      let data = decoder.read_image_hdr().unwrap().iter().map( |c|
+        Vector3::new(c.data[0], c.data[1], c.data[2])
+      ).collect();
+      Self::new(width, height, data)
+    } else {
+      // PNG, JPEGなどはガンマ補正を戻して線形にする
+      let gamma = gamma.unwrap_or(2.2);
+      let img = image::open(path).expect(&format!("File `{}` is not a supported image.", path)).to_rgb();
+      let (width, height) = img.dimensions();
+      let data = img.pixels().map( |c| {
+        let linear = |v: u8| (v as f32 / 255.0).powf(gamma);
+        Vector3::new(linear(c.data[0]), linear(c.data[1]), linear(c.data[2]))
+      }).collect();
+      Self::new(width as usize, height as usize, data)
+    }
+  }
+
+  pub fn new(width: usize, height: usize, data: Vec<Vector3>) -> ImageTexture {
+    assert!(data.len() == width * height);
+    let max = data.iter().fold(Vector3::zero(), |m, c|
+      Vector3::new(m.x.max(c.x), m.y.max(c.y), m.z.max(c.z))
+    );
+    ImageTexture {
+      mipmap: MipMap::new(width, height, data),
+      max: max,
+    }
+  }
+
+  pub fn average(&self) -> Vector3 {
+    self.mipmap.average()
+  }
+}
+
+impl Texture for ImageTexture {
+  fn value(&self, point: &SurfacePoint) -> Vector3 {
+    // 画面上の1画素に対応するテクスチャ上の範囲 (画素単位)
+    let w = self.mipmap.width() as f32;
+    let h = self.mipmap.height() as f32;
+    let footprint = |d: (f32, f32)| ((d.0 * w).powi(2) + (d.1 * h).powi(2)).sqrt();
+    let width = footprint(point.duvdx).max(footprint(point.duvdy));
+    // テクスチャ座標のvは下から上向きなので画像の行を反転する
+    self.mipmap.trilinear(point.uv.0, 1.0 - point.uv.1, width)
+  }
+
+  fn max_value(&self) -> Vector3 {
+    self.max
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use constant::*;
+
+  #[test]
+  fn bilinear_test() {
+    let tex = ImageTexture::new(2, 1, vec![Vector3::zero(), Vector3::new(1.0, 1.0, 1.0)]);
+    // 画素の中心では画素値と一致する
+    let left = tex.value(&SurfacePoint::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0), (0.25, 0.5)));
+    assert!(left.norm() < EPS, "{}", left);
+    // 画素の間では補間される
+    let mid = tex.value(&SurfacePoint::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0), (0.5, 0.5)));
+    assert!((mid.x - 0.5).abs() < EPS, "{}", mid);
+    // 範囲外は繰り返す
+    let wrap = tex.value(&SurfacePoint::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0), (1.75, 0.5)));
+    assert!((wrap.x - 1.0).abs() < EPS, "{}", wrap);
+  }
+
+  #[test]
+  fn filtered_test() {
+    let tex = ImageTexture::new(2, 1, vec![Vector3::zero(), Vector3::new(1.0, 1.0, 1.0)]);
+    // 画素より十分広い範囲では平均になる
+    let mut point = SurfacePoint::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0), (0.25, 0.5));
+    point.duvdx = (4.0, 0.0);
+    let filtered = tex.value(&point);
+    assert!((filtered.x - 0.5).abs() < EPS, "{}", filtered);
+  }
+}
@@ -0,0 +1,18 @@
+use super::procedural::Pattern;
+use super::perlin::Perlin;
+use math::vector::*;
+use constant::*;
+
+// x軸方向の縞を乱流で歪ませた大理石模様
+pub struct MarblePattern {
+  pub perlin: Perlin,
+  // 縞の歪みの強さ
+  pub distortion: f32,
+}
+
+impl Pattern for MarblePattern {
+  fn value(&self, p: Vector3) -> f32 {
+    let phase = p.x + self.perlin.turbulence(p, 6) * self.distortion;
+    0.5 + 0.5 * (phase * PI).sin()
+  }
+}
@@ -0,0 +1,240 @@
+use math::vector::*;
+
+// 範囲外の画素の扱い
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Address {
+  // 繰り返す
+  Repeat,
+  // 端の画素を延ばす
+  Clamp,
+}
+
+struct Level {
+  width: usize,
+  height: usize,
+  data: Vec<Vector3>,
+}
+
+impl Level {
+  fn texel(&self, x: isize, y: isize, address: (Address, Address)) -> Vector3 {
+    let w = self.width as isize;
+    let h = self.height as isize;
+    let x = Self::address(x, w, address.0);
+    let y = Self::address(y, h, address.1);
+    self.data[(y * w + x) as usize]
+  }
+
+  fn address(i: isize, n: isize, address: Address) -> isize {
+    match address {
+      Address::Repeat => ((i % n) + n) % n,
+      Address::Clamp => i.max(0).min(n - 1),
+    }
+  }
+
+  fn downsample(&self) -> Level {
+    // 縮小後の画素が覆う範囲の平均 (奇数の場合は端の画素も3画素の範囲に含める)
+    let width = (self.width / 2).max(1);
+    let height = (self.height / 2).max(1);
+    let range = |i: usize, from: usize, to: usize| {
+      let start = i * from / to;
+      let end = if i + 1 == to { from } else { ((i + 1) * from / to).max(start + 1) };
+      start..end
+    };
+    let mut data = Vec::with_capacity(width * height);
+    for y in 0..height {
+      for x in 0..width {
+        let mut sum = Vector3::zero();
+        let mut count = 0;
+        for sy in range(y, self.height, height) {
+          for sx in range(x, self.width, width) {
+            sum = sum + self.data[sy * self.width + sx];
+            count += 1;
+          }
+        }
+        data.push(sum / count as f32);
+      }
+    }
+    Level {
+      width: width,
+      height: height,
+      data: data,
+    }
+  }
+}
+
+// 解像度を半分ずつ下げた画像のピラミッド
+pub struct MipMap {
+  levels: Vec<Level>,
+  // 横, 縦方向の範囲外の扱い
+  address: (Address, Address),
+}
+
+impl MipMap {
+  pub fn new(width: usize, height: usize, data: Vec<Vector3>) -> MipMap {
+    assert!(data.len() == width * height);
+    let mut levels = vec![Level {
+      width: width,
+      height: height,
+      data: data,
+    }];
+    loop {
+      let next = match levels.last() {
+        Some(l) if l.width > 1 || l.height > 1 => l.downsample(),
+        _ => break,
+      };
+      levels.push(next);
+    }
+    MipMap {
+      levels: levels,
+      address: (Address::Repeat, Address::Repeat),
+    }
+  }
+
+  pub fn with_address(self, u: Address, v: Address) -> MipMap {
+    MipMap {
+      address: (u, v),
+      ..self
+    }
+  }
+
+  pub fn width(&self) -> usize {
+    self.levels[0].width
+  }
+
+  pub fn height(&self) -> usize {
+    self.levels[0].height
+  }
+
+  // 画像全体の平均 (最も粗い段の画素値)
+  pub fn average(&self) -> Vector3 {
+    self.levels[self.levels.len() - 1].data[0]
+  }
+
+  // フィルタの幅 (最も細かい段の画素単位) に対応する連続的な段
+  pub fn level(&self, width: f32) -> f32 {
+    width.max(1.0).log2().min((self.levels.len() - 1) as f32)
+  }
+
+  // (u, v) は画像の左上が原点で [0, 1) の範囲
+  pub fn nearest(&self, level: usize, u: f32, v: f32) -> Vector3 {
+    let l = &self.levels[level.min(self.levels.len() - 1)];
+    let x = (u * l.width as f32).floor() as isize;
+    let y = (v * l.height as f32).floor() as isize;
+    l.texel(x, y, self.address)
+  }
+
+  pub fn bilinear(&self, level: usize, u: f32, v: f32) -> Vector3 {
+    let l = &self.levels[level.min(self.levels.len() - 1)];
+    let x = u * l.width as f32 - 0.5;
+    let y = v * l.height as f32 - 0.5;
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let fx = x - x0;
+    let fy = y - y0;
+    let x0 = x0 as isize;
+    let y0 = y0 as isize;
+    l.texel(x0, y0, self.address) * (1.0 - fx) * (1.0 - fy) +
+      l.texel(x0 + 1, y0, self.address) * fx * (1.0 - fy) +
+      l.texel(x0, y0 + 1, self.address) * (1.0 - fx) * fy +
+      l.texel(x0 + 1, y0 + 1, self.address) * fx * fy
+  }
+
+  // Catmull-Romスプラインによる4x4画素の補間 (負になる場合は0にする)
+  pub fn bicubic(&self, level: usize, u: f32, v: f32) -> Vector3 {
+    let l = &self.levels[level.min(self.levels.len() - 1)];
+    let x = u * l.width as f32 - 0.5;
+    let y = v * l.height as f32 - 0.5;
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let wx = Self::catmull_rom(x - x0);
+    let wy = Self::catmull_rom(y - y0);
+    let x0 = x0 as isize;
+    let y0 = y0 as isize;
+    let mut sum = Vector3::zero();
+    for j in 0..4 {
+      for i in 0..4 {
+        sum = sum + l.texel(x0 + i as isize - 1, y0 + j as isize - 1, self.address) * (wx[i] * wy[j]);
+      }
+    }
+    Vector3::new(sum.x.max(0.0), sum.y.max(0.0), sum.z.max(0.0))
+  }
+
+  // 端数t -> 前後4画素の重み
+  fn catmull_rom(t: f32) -> [f32; 4] {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    [
+      0.5 * (-t3 + 2.0 * t2 - t),
+      0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+      0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+      0.5 * (t3 - t2),
+    ]
+  }
+
+  // 前後の段のバイリニア補間をさらに線形補間する
+  pub fn trilinear(&self, u: f32, v: f32, width: f32) -> Vector3 {
+    let level = self.level(width);
+    let l0 = level.floor();
+    let t = level - l0;
+    let c0 = self.bilinear(l0 as usize, u, v);
+    if t == 0.0 {
+      return c0
+    }
+    c0 * (1.0 - t) + self.bilinear(l0 as usize + 1, u, v) * t
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use constant::*;
+
+  #[test]
+  fn pyramid_test() {
+    let data = (0..8).map( |i| Vector3::new(i as f32, 0.0, 0.0) ).collect();
+    let mipmap = MipMap::new(4, 2, data);
+    // 4x2, 2x1, 1x1 の3段
+    assert_eq!(mipmap.levels.len(), 3);
+    // 最も粗い段は全体の平均
+    let coarse = mipmap.nearest(2, 0.5, 0.5);
+    assert!((coarse.x - 3.5).abs() < EPS, "{}", coarse);
+    // フィルタの幅が1画素以下なら最も細かい段
+    assert_eq!(mipmap.level(0.5), 0.0);
+    assert!((mipmap.level(2.0) - 1.0).abs() < EPS);
+    assert!((mipmap.level(1000.0) - 2.0).abs() < EPS);
+  }
+
+  #[test]
+  fn odd_size_test() {
+    // 奇数の幅でも端の画素を落とさずに平均する
+    let data = (0..3).map( |i| Vector3::new(i as f32, 0.0, 0.0) ).collect();
+    let mipmap = MipMap::new(3, 1, data);
+    let coarse = mipmap.nearest(1, 0.5, 0.5);
+    assert!((coarse.x - 1.0).abs() < EPS, "{}", coarse);
+  }
+
+  #[test]
+  fn address_test() {
+    // 縦方向だけ端の画素を延ばす
+    let data = (0..4).map( |i| Vector3::new(i as f32, 0.0, 0.0) ).collect();
+    let mipmap = MipMap::new(2, 2, data).with_address(Address::Repeat, Address::Clamp);
+    // 上端の外側は下端と混ざらない
+    let top = mipmap.bilinear(0, 0.25, 0.0);
+    assert!((top.x - 0.0).abs() < EPS, "{}", top);
+    // 左端の外側は右端と混ざる
+    let left = mipmap.bilinear(0, 0.0, 0.25);
+    assert!((left.x - 0.5).abs() < EPS, "{}", left);
+  }
+
+  #[test]
+  fn interpolation_test() {
+    // 横方向に線形な画像はどの補間でも画素の間で線形
+    let data = (0..16).map( |i| Vector3::new((i % 4) as f32, 0.0, 0.0) ).collect();
+    let mipmap = MipMap::new(4, 4, data);
+    let u = 1.75 / 4.0;
+    let bilinear = mipmap.bilinear(0, u, 0.5);
+    let bicubic = mipmap.bicubic(0, u, 0.5);
+    assert!((bilinear.x - 1.25).abs() < EPS, "{}", bilinear);
+    assert!((bicubic.x - 1.25).abs() < EPS, "{}", bicubic);
+  }
+}
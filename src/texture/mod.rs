@@ -0,0 +1,29 @@
+mod traits;
+mod constant;
+mod scaled;
+mod mipmap;
+mod image;
+mod procedural;
+mod perlin;
+mod checker;
+mod noise;
+mod voronoi;
+mod gradient;
+mod wood;
+mod marble;
+
+pub mod texture {
+  pub use super::traits::*;
+  pub use super::constant::*;
+  pub use super::scaled::*;
+  pub use super::mipmap::*;
+  pub use super::image::*;
+  pub use super::procedural::*;
+  pub use super::perlin::*;
+  pub use super::checker::*;
+  pub use super::noise::*;
+  pub use super::voronoi::*;
+  pub use super::gradient::*;
+  pub use super::wood::*;
+  pub use super::marble::*;
+}
@@ -0,0 +1,14 @@
+use super::procedural::Pattern;
+use super::perlin::Perlin;
+use math::vector::*;
+
+pub struct NoisePattern {
+  pub perlin: Perlin,
+  pub octaves: usize,
+}
+
+impl Pattern for NoisePattern {
+  fn value(&self, p: Vector3) -> f32 {
+    self.perlin.fbm(p, self.octaves) * 0.5 + 0.5
+  }
+}
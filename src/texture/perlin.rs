@@ -0,0 +1,92 @@
+use math::vector::*;
+
+// Improved Perlin noise [Perlin 2002]
+pub struct Perlin {
+  // 0..256の順列を2回並べたもの
+  permutation: Vec<usize>,
+}
+
+impl Perlin {
+  pub fn new(seed: u32) -> Perlin {
+    // 線形合同法で決定的にシャッフルする
+    let mut state = seed.wrapping_mul(2654435761).wrapping_add(1);
+    let mut p = (0..256).collect::<Vec<usize>>();
+    for i in (1..256).rev() {
+      state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+      let j = (state >> 8) as usize % (i + 1);
+      p.swap(i, j);
+    }
+    let permutation = p.iter().chain(p.iter()).cloned().collect();
+    Perlin {
+      permutation: permutation,
+    }
+  }
+
+  // [-1, 1] のノイズ
+  pub fn noise(&self, p: Vector3) -> f32 {
+    let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let lerp = |t: f32, a: f32, b: f32| a + t * (b - a);
+    let grad = |hash: usize, x: f32, y: f32, z: f32| {
+      // 立方体の辺の中点方向の12個の勾配ベクトル
+      let h = hash & 15;
+      let u = if h < 8 { x } else { y };
+      let v = if h < 4 { y } else if h == 12 || h == 14 { x } else { z };
+      (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+    };
+    let perm = &self.permutation;
+    let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let xi = (fx as i64 & 255) as usize;
+    let yi = (fy as i64 & 255) as usize;
+    let zi = (fz as i64 & 255) as usize;
+    let (x, y, z) = (p.x - fx, p.y - fy, p.z - fz);
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+    let a = perm[xi] + yi;
+    let aa = perm[a] + zi;
+    let ab = perm[a + 1] + zi;
+    let b = perm[xi + 1] + yi;
+    let ba = perm[b] + zi;
+    let bb = perm[b + 1] + zi;
+    lerp(w,
+      lerp(v,
+        lerp(u, grad(perm[aa], x, y, z), grad(perm[ba], x - 1.0, y, z)),
+        lerp(u, grad(perm[ab], x, y - 1.0, z), grad(perm[bb], x - 1.0, y - 1.0, z)),
+      ),
+      lerp(v,
+        lerp(u, grad(perm[aa + 1], x, y, z - 1.0), grad(perm[ba + 1], x - 1.0, y, z - 1.0)),
+        lerp(u, grad(perm[ab + 1], x, y - 1.0, z - 1.0), grad(perm[bb + 1], x - 1.0, y - 1.0, z - 1.0)),
+      ),
+    )
+  }
+
+  // オクターブを重ねたノイズ (fractional Brownian motion)
+  pub fn fbm(&self, p: Vector3, octaves: usize) -> f32 {
+    (0..octaves).fold((0.0, 1.0, 1.0), |(sum, amplitude, frequency), _| {
+      (sum + self.noise(p * frequency) * amplitude, amplitude * 0.5, frequency * 2.0)
+    }).0
+  }
+
+  // ノイズの絶対値を重ねた乱流
+  pub fn turbulence(&self, p: Vector3, octaves: usize) -> f32 {
+    (0..octaves).fold((0.0, 1.0, 1.0), |(sum, amplitude, frequency), _| {
+      (sum + self.noise(p * frequency).abs() * amplitude, amplitude * 0.5, frequency * 2.0)
+    }).0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use constant::*;
+
+  #[test]
+  fn lattice_zero_test() {
+    // 格子点ではノイズは0になる
+    let perlin = Perlin::new(0);
+    for i in 0..10 {
+      let p = Vector3::new(i as f32, -(i as f32) * 2.0, 3.0);
+      assert!(perlin.noise(p).abs() < EPS, "{}", perlin.noise(p));
+    }
+    let n = perlin.noise(Vector3::new(0.3, 0.7, 0.2));
+    assert!(n.abs() <= 1.0, "{}", n);
+  }
+}
@@ -0,0 +1,87 @@
+use super::traits::Texture;
+use math::vector::*;
+use math::matrix::*;
+use intersection::SurfacePoint;
+
+// パターンを評価する座標の取り方
+pub enum Mapping {
+  // テクスチャ座標 (u, v, 0)
+  Uv,
+  // ワールド座標
+  World,
+  // 物体のローカル座標 (ワールド座標からの逆変換)
+  Object(Matrix4),
+  // 法線ベクトル
+  Normal,
+}
+
+impl Mapping {
+  pub fn coordinate(&self, point: &SurfacePoint) -> Vector3 {
+    match *self {
+      Mapping::Uv => Vector3::new(point.uv.0, point.uv.1, 0.0),
+      Mapping::World => point.position,
+      Mapping::Object(ref inverse) => inverse * point.position,
+      Mapping::Normal => point.normal,
+    }
+  }
+}
+
+pub trait Pattern {
+  // 座標 -> [0, 1] の値
+  fn value(&self, Vector3) -> f32;
+}
+
+// パターンの値で2色を補間するテクスチャ
+pub struct ProceduralTexture {
+  pub mapping: Mapping,
+  // 座標に掛ける倍率 (パターンの細かさ)
+  pub scale: f32,
+  pub pattern: Box<Pattern + Send + Sync>,
+  pub colors: (Vector3, Vector3),
+}
+
+impl Texture for ProceduralTexture {
+  fn value(&self, point: &SurfacePoint) -> Vector3 {
+    let p = self.mapping.coordinate(point) * self.scale;
+    let t = self.pattern.value(p).max(0.0).min(1.0);
+    self.colors.0 * (1.0 - t) + self.colors.1 * t
+  }
+
+  fn max_value(&self) -> Vector3 {
+    let (a, b) = self.colors;
+    Vector3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::checker::CheckerPattern;
+  use constant::*;
+
+  #[test]
+  fn object_mapping_test() {
+    let transform = Matrix4::translate(Vector3::new(10.0, 0.0, 0.0)) * Matrix4::scale(Vector3::new(2.0, 2.0, 2.0));
+    let mapping = Mapping::Object(transform.inverse());
+    let point = SurfacePoint::new(Vector3::new(12.0, 1.0, 0.0), Vector3::new(0.0, 0.0, 1.0), (0.0, 0.0));
+    // 物体のローカル座標に戻る
+    let p = mapping.coordinate(&point);
+    assert!((p - Vector3::new(1.0, 0.5, 0.0)).norm() < EPS, "{}", p);
+  }
+
+  #[test]
+  fn color_interpolation_test() {
+    let texture = ProceduralTexture {
+      mapping: Mapping::Uv,
+      scale: 2.0,
+      pattern: Box::new(CheckerPattern),
+      colors: (Vector3::zero(), Vector3::new(1.0, 0.5, 0.0)),
+    };
+    let normal = Vector3::new(0.0, 0.0, 1.0);
+    let a = texture.value(&SurfacePoint::new(Vector3::zero(), normal, (0.25, 0.25)));
+    let b = texture.value(&SurfacePoint::new(Vector3::zero(), normal, (0.75, 0.25)));
+    assert!(a.norm() < EPS, "{}", a);
+    assert!((b - Vector3::new(1.0, 0.5, 0.0)).norm() < EPS, "{}", b);
+    assert!((texture.max_value() - Vector3::new(1.0, 0.5, 0.0)).norm() < EPS);
+  }
+}
@@ -0,0 +1,20 @@
+use std::sync::Arc;
+use super::traits::Texture;
+use math::vector::*;
+use intersection::SurfacePoint;
+
+// 色ごとの倍率を掛けたテクスチャ
+pub struct ScaledTexture {
+  pub texture: Arc<Texture + Send + Sync>,
+  pub scale: Vector3,
+}
+
+impl Texture for ScaledTexture {
+  fn value(&self, point: &SurfacePoint) -> Vector3 {
+    self.texture.value(point) * self.scale
+  }
+
+  fn max_value(&self) -> Vector3 {
+    self.texture.max_value() * self.scale
+  }
+}
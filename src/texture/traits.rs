@@ -0,0 +1,9 @@
+use math::vector::Vector3;
+use intersection::SurfacePoint;
+
+pub trait Texture {
+  // 表面上の点 -> テクスチャの値
+  fn value(&self, &SurfacePoint) -> Vector3;
+  // 各チャンネルの最大値 (評価点によらない値が必要なときにつかう)
+  fn max_value(&self) -> Vector3;
+}
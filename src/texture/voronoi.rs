@@ -0,0 +1,45 @@
+use super::procedural::Pattern;
+use math::vector::*;
+
+// 最も近い特徴点までの距離による模様 [Worley 1996]
+pub struct VoronoiPattern {
+  pub seed: u32,
+}
+
+impl VoronoiPattern {
+  fn hash(&self, x: i64, y: i64, z: i64, axis: u32) -> f32 {
+    let mut h = (x as u32).wrapping_mul(73856093)
+      ^ (y as u32).wrapping_mul(19349663)
+      ^ (z as u32).wrapping_mul(83492791)
+      ^ self.seed.wrapping_mul(3).wrapping_add(axis).wrapping_mul(2654435761);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1e995);
+    h ^= h >> 15;
+    (h & 0xffffff) as f32 / 16777216.0
+  }
+
+  // 格子の各セルに1つずつ置いた特徴点
+  fn feature_point(&self, x: i64, y: i64, z: i64) -> Vector3 {
+    Vector3::new(
+      x as f32 + self.hash(x, y, z, 0),
+      y as f32 + self.hash(x, y, z, 1),
+      z as f32 + self.hash(x, y, z, 2),
+    )
+  }
+}
+
+impl Pattern for VoronoiPattern {
+  fn value(&self, p: Vector3) -> f32 {
+    let (cx, cy, cz) = (p.x.floor() as i64, p.y.floor() as i64, p.z.floor() as i64);
+    let mut min = ::std::f32::INFINITY;
+    for dx in -1..2 {
+      for dy in -1..2 {
+        for dz in -1..2 {
+          let d = (self.feature_point(cx + dx, cy + dy, cz + dz) - p).norm();
+          min = min.min(d);
+        }
+      }
+    }
+    min
+  }
+}
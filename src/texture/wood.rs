@@ -0,0 +1,18 @@
+use super::procedural::Pattern;
+use super::perlin::Perlin;
+use math::vector::*;
+
+// y軸を中心とした年輪をノイズで歪ませた木目
+pub struct WoodPattern {
+  pub perlin: Perlin,
+  // 年輪の歪みの強さ
+  pub distortion: f32,
+}
+
+impl Pattern for WoodPattern {
+  fn value(&self, p: Vector3) -> f32 {
+    let r = (p.x * p.x + p.z * p.z).sqrt() + self.perlin.fbm(p, 4) * self.distortion;
+    // 年輪の境界を鋭くする
+    (r - r.floor()).powi(3)
+  }
+}
@@ -2,7 +2,7 @@ extern crate test;
 extern crate rand;
 
 use std::sync::Arc;
-use intersection::Intersection;
+use intersection::{Intersection, SurfacePoint};
 use shape::*;
 use constant::*;
 use ray::Ray;
@@ -10,6 +10,11 @@ use material::material::Material;
 use math::vector::*;
 use aabb::AABB;
 use sample::Sample;
+use util::OrthonormalBasis;
+
+// 球面三角形によるサンプリングをつかう立体角の範囲
+const MIN_SPHERICAL_SAMPLE_AREA: f32 = 3e-4;
+const MAX_SPHERICAL_SAMPLE_AREA: f32 = 6.22;
 
 pub struct Triangle {
   pub p0: Vector3,
@@ -18,6 +23,15 @@ pub struct Triangle {
   aabb: AABB,
   pub normal: Vector3,
   pub area: f32,
+  // 各頂点のテクスチャ座標
+  texcoords: [(f32, f32); 3],
+  // テクスチャ座標についての位置の偏微分
+  dpdu: Vector3,
+  dpdv: Vector3,
+  // 各頂点の法線 (陰影計算用, 指定がなければ面の法線)
+  normals: Option<[Vector3; 3]>,
+  // 各頂点の接線 (法線マップ用, 指定がなければdpdu)
+  tangents: Option<[Vector3; 3]>,
   pub material: Arc<dyn Material + Send + Sync>,
 }
 
@@ -28,17 +42,120 @@ impl Triangle {
     p2: Vector3,
     material: Arc<Material + Send + Sync>,
   ) -> Triangle {
-    Triangle {
+    let mut triangle = Triangle {
       p0: p0,
       p1: p1,
       p2: p2,
       aabb: Self::aabb(p0, p1, p2),
       normal: (p1 - p0).cross(p2 - p0).normalize(),
       area: (p1 - p0).cross(p2 - p0).norm() * 0.5,
+      texcoords: [(0.0, 0.0); 3],
+      dpdu: Vector3::zero(),
+      dpdv: Vector3::zero(),
+      normals: None,
+      tangents: None,
       material: material,
+    };
+    triangle.set_texcoords([(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]);
+    triangle
+  }
+
+  pub fn set_texcoords(&mut self, texcoords: [(f32, f32); 3]) {
+    self.texcoords = texcoords;
+    // 2辺とテクスチャ座標の差分から偏微分を求める
+    let duv02 = (texcoords[0].0 - texcoords[2].0, texcoords[0].1 - texcoords[2].1);
+    let duv12 = (texcoords[1].0 - texcoords[2].0, texcoords[1].1 - texcoords[2].1);
+    let dp02 = self.p0 - self.p2;
+    let dp12 = self.p1 - self.p2;
+    let det = duv02.0 * duv12.1 - duv02.1 * duv12.0;
+    if det.abs() < 1e-12 {
+      // テクスチャ座標が縮退している場合は法線に垂直な基底をつかう
+      let (u, v) = self.normal.orthonormal_basis();
+      self.dpdu = u;
+      self.dpdv = v;
+    } else {
+      self.dpdu = (dp02 * duv12.1 - dp12 * duv02.1) / det;
+      self.dpdv = (dp12 * duv02.0 - dp02 * duv12.0) / det;
     }
   }
 
+  pub fn set_normals(&mut self, normals: [Vector3; 3]) {
+    self.normals = Some(normals);
+  }
+
+  pub fn set_tangents(&mut self, tangents: [Vector3; 3]) {
+    self.tangents = Some(tangents);
+  }
+
+  pub fn dpdu(&self) -> Vector3 {
+    self.dpdu
+  }
+
+  // 重心座標 (p1, p2の重み) -> 陰影計算用の法線, 接線
+  fn shading(&self, u: f32, v: f32) -> (Vector3, Vector3) {
+    let w = 1.0 - u - v;
+    let normal = self.normals.map( |n| {
+      let n = (n[0] * w + n[1] * u + n[2] * v).normalize();
+      // 頂点法線が面の裏を向いている場合は面の向きに合わせる
+      if n.dot(self.normal) < 0.0 { -n } else { n }
+    }).unwrap_or(self.normal);
+    let tangent = self.tangents.map( |t| t[0] * w + t[1] * u + t[2] * v ).unwrap_or(self.dpdu);
+    (normal, tangent)
+  }
+
+  // 重心座標 (p1, p2の重み) -> テクスチャ座標
+  fn texcoord(&self, u: f32, v: f32) -> (f32, f32) {
+    let t = &self.texcoords;
+    let w = 1.0 - u - v;
+    (
+      t[0].0 * w + t[1].0 * u + t[2].0 * v,
+      t[0].1 * w + t[1].1 * u + t[2].1 * v,
+    )
+  }
+
+  // 平面上の点 -> 重心座標 (p1, p2の重み)
+  fn barycentric(&self, p: Vector3) -> (f32, f32) {
+    let e1 = self.p1 - self.p0;
+    let e2 = self.p2 - self.p0;
+    let d = p - self.p0;
+    let double_area = self.area * 2.0;
+    let u = d.cross(e2).dot(self.normal) / double_area;
+    let v = e1.cross(d).dot(self.normal) / double_area;
+    // 数値誤差で三角形の外に出た点は内側に戻す
+    let u = u.max(0.0).min(1.0);
+    let v = v.max(0.0).min(1.0 - u);
+    (u, v)
+  }
+
+  // 単位ベクトルの頂点 -> 球面三角形の面積 (Van Oosterom and Strackee)
+  pub fn solid_angle(a: Vector3, b: Vector3, c: Vector3) -> f32 {
+    let numerator = a.dot(b.cross(c)).abs();
+    let denominator = 1.0 + a.dot(b) + b.dot(c) + c.dot(a);
+    2.0 * numerator.atan2(denominator)
+  }
+
+  // 球面三角形内で一様に方向をサンプリング
+  // Arvo 1995, "Stratified Sampling of Spherical Triangles", SIGGRAPH 95.
+  pub fn sample_spherical_triangle(a: Vector3, b: Vector3, c: Vector3, area: f32, u1: f32, u2: f32) -> Vector3 {
+    // 各頂点での内角
+    let angle = |o: Vector3, p: Vector3, q: Vector3| {
+      o.cross(p).normalize().dot(o.cross(q).normalize()).max(-1.0).min(1.0).acos()
+    };
+    let alpha = angle(a, b, c);
+    let cos_c = a.dot(b);
+    // 面積が u1 * area になるように頂点cを辺ac上のc'に動かす
+    let area_sub = u1 * area;
+    let s = (area_sub - alpha).sin();
+    let t = (area_sub - alpha).cos();
+    let u = t - alpha.cos();
+    let v = s + alpha.sin() * cos_c;
+    let q = (((v * t - u * s) * alpha.cos() - v) / ((v * s + u * t) * alpha.sin())).max(-1.0).min(1.0);
+    let c_sub = a * q + (c - a * c.dot(a)).normalize() * (1.0 - q * q).max(0.0).sqrt();
+    // 大円の弧bc'上でcosが一様になるように選ぶ
+    let z = 1.0 - u2 * (1.0 - c_sub.dot(b));
+    (b * z + (c_sub - b * c_sub.dot(b)).normalize() * (1.0 - z * z).max(0.0).sqrt()).normalize()
+  }
+
   fn intersect_3c(&self, ray: &Ray) -> Option<Intersection> {
     let dn = ray.direction.dot(self.normal);
     let t = (self.p0 - ray.origin).dot(self.normal) / dn;
@@ -58,11 +175,22 @@ impl Triangle {
     if c2.dot(self.normal) < 0.0 {
       return None;
     }
+    // 各辺の外積の大きさから重心座標を求める
+    let double_area = self.area * 2.0;
+    let u = c2.dot(self.normal) / double_area;
+    let v = c0.dot(self.normal) / double_area;
+    let (shading_normal, tangent) = self.shading(u, v);
     Some(Intersection {
       distance: t,
       normal: self.normal,
+      shading_normal: shading_normal,
+      tangent: tangent,
       position: p,
+      uv: self.texcoord(u, v),
+      dpdu: self.dpdu,
+      dpdv: self.dpdv,
       material: self.material.clone(),
+      object: 0,
     })
   }
 
@@ -91,11 +219,18 @@ impl Triangle {
       return None;
     }
     let p = ray.origin + ray.direction * t;
+    let (shading_normal, tangent) = self.shading(u, v);
     Some(Intersection {
       distance: t,
       normal: self.normal,
+      shading_normal: shading_normal,
+      tangent: tangent,
       position: p,
+      uv: self.texcoord(u, v),
+      dpdu: self.dpdu,
+      dpdv: self.dpdv,
       material: self.material.clone(),
+      object: 0,
     })
   }
 
@@ -137,22 +272,56 @@ impl SurfaceShape for Triangle {
     self.area
   }
 
-  fn sample(&self) -> Sample<Vector3> {
+  fn sample(&self) -> Sample<SurfacePoint> {
     let u = rand::random::<f32>();
     let v = rand::random::<f32>();
     let min = u.min(v);
     let max = u.max(v);
+    let (normal, _) = self.shading(1.0 - max, max - min);
     Sample {
-      value: self.p0 * min + self.p1 * (1.0 - max) + self.p2 * (max - min),
+      value: SurfacePoint::new(
+        self.p0 * min + self.p1 * (1.0 - max) + self.p2 * (max - min),
+        normal,
+        self.texcoord(1.0 - max, max - min),
+      ),
       pdf: 1.0 / self.area,
     }
   }
+
+  fn sample_solid_angle(&self, position: Vector3) -> Sample<SurfacePoint> {
+    // 照らされる点を中心とする単位球面に射影した球面三角形
+    let a = (self.p0 - position).normalize();
+    let b = (self.p1 - position).normalize();
+    let c = (self.p2 - position).normalize();
+    let solid_angle = Self::solid_angle(a, b, c);
+    if !(solid_angle > MIN_SPHERICAL_SAMPLE_AREA && solid_angle < MAX_SPHERICAL_SAMPLE_AREA) {
+      // 小さすぎる (遠い) または大きすぎる球面三角形は数値誤差が大きいので面積に対してサンプリング
+      return self.sample()
+    }
+    let direction = Self::sample_spherical_triangle(a, b, c, solid_angle, rand::random::<f32>(), rand::random::<f32>());
+    // 方向と三角形の平面との交点
+    let dn = direction.dot(self.normal);
+    if dn == 0.0 {
+      return self.sample()
+    }
+    let t = (self.p0 - position).dot(self.normal) / dn;
+    let point = position + direction * t;
+    let (u, v) = self.barycentric(point);
+    let (normal, _) = self.shading(u, v);
+    // 立体角測度の確率密度を面積測度に変換
+    let pdf = dn.abs() / (solid_angle * t * t);
+    Sample {
+      value: SurfacePoint::new(point, normal, self.texcoord(u, v)),
+      pdf: pdf,
+    }
+  }
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
   use material::material::*;
+  use texture::texture::ConstantTexture;
 
   #[test]
   fn intersect_mt_front() {
@@ -160,11 +329,12 @@ mod tests {
       Vector3::new(5.0, 0.0, 0.0),
       Vector3::new(0.0, 0.0, 0.0),
       Vector3::new(0.0, 0.0, 5.0),
-      Arc::new(LambertianMaterial { emission: Vector3::zero(), albedo: Vector3::zero() }),
+      Arc::new(LambertianMaterial { emission: Vector3::zero(), albedo: Arc::new(ConstantTexture { value: Vector3::zero() }) }),
     );
     let ray = Ray {
       origin: Vector3::new(1.0, 5.0, 1.0),
       direction: Vector3::new(0.0, -1.0, 0.0).normalize(),
+      differential: None,
     };
     let i1 = t.intersect_3c(&ray).unwrap();
     let i2 = t.intersect_mt(&ray).unwrap();
@@ -179,11 +349,12 @@ mod tests {
       Vector3::new(5.0, 0.0, 0.0),
       Vector3::new(0.0, 0.0, 0.0),
       Vector3::new(0.0, 0.0, 5.0),
-      Arc::new(LambertianMaterial { emission: Vector3::zero(), albedo: Vector3::zero() }),
+      Arc::new(LambertianMaterial { emission: Vector3::zero(), albedo: Arc::new(ConstantTexture { value: Vector3::zero() }) }),
     );
     let ray = Ray {
       origin: Vector3::new(1.0, -5.0, 1.0),
       direction: Vector3::new(0.0, 1.0, 0.0).normalize(),
+      differential: None,
     };
     let i1 = t.intersect_3c(&ray).unwrap();
     let i2 = t.intersect_mt(&ray).unwrap();
@@ -198,16 +369,18 @@ mod tests {
       Vector3::new(5.0, 0.0, 0.0),
       Vector3::new(0.0, 0.0, 0.0),
       Vector3::new(0.0, 0.0, 5.0),
-      Arc::new(LambertianMaterial { emission: Vector3::zero(), albedo: Vector3::zero() }),
+      Arc::new(LambertianMaterial { emission: Vector3::zero(), albedo: Arc::new(ConstantTexture { value: Vector3::zero() }) }),
     );
     let ray = Ray {
       origin: Vector3::new(1.0, 5.0, 1.0),
       direction: Vector3::new(0.0, -1.0, 0.0).normalize(),
+      differential: None,
     };
     let i1 = t.intersect_3c(&ray).unwrap();
     let near_ray = Ray {
       origin: i1.position,
       direction: Vector3::new(0.0, 1.0, 0.0),
+      differential: None,
     };
     let i2 = t.intersect_3c(&near_ray);
     assert!(i2.is_none());
@@ -219,18 +392,87 @@ mod tests {
       Vector3::new(5.0, 0.0, 0.0),
       Vector3::new(0.0, 0.0, 0.0),
       Vector3::new(0.0, 0.0, 5.0),
-      Arc::new(LambertianMaterial { emission: Vector3::zero(), albedo: Vector3::zero() }),
+      Arc::new(LambertianMaterial { emission: Vector3::zero(), albedo: Arc::new(ConstantTexture { value: Vector3::zero() }) }),
     );
     let ray = Ray {
       origin: Vector3::new(1.0, 5.0, 1.0),
       direction: Vector3::new(0.0, -1.0, 0.0).normalize(),
+      differential: None,
     };
     let i1 = t.intersect_mt(&ray).unwrap();
     let near_ray = Ray {
       origin: i1.position,
       direction: Vector3::new(0.0, 1.0, 0.0),
+      differential: None,
     };
     let i2 = t.intersect_mt(&near_ray);
     assert!(i2.is_none());
   }
+
+  #[test]
+  fn texcoord_test() {
+    let mut t = Triangle::new(
+      Vector3::new(5.0, 0.0, 0.0),
+      Vector3::new(0.0, 0.0, 0.0),
+      Vector3::new(0.0, 0.0, 5.0),
+      Arc::new(LambertianMaterial { emission: Vector3::zero(), albedo: Arc::new(ConstantTexture { value: Vector3::zero() }) }),
+    );
+    t.set_texcoords([(1.0, 0.0), (0.0, 0.0), (0.0, 1.0)]);
+    let ray = Ray {
+      origin: Vector3::new(1.0, 5.0, 2.0),
+      direction: Vector3::new(0.0, -1.0, 0.0).normalize(),
+      differential: None,
+    };
+    // テクスチャ座標は xz 平面上の座標を 1/5 したもの
+    let i1 = t.intersect_3c(&ray).unwrap();
+    let i2 = t.intersect_mt(&ray).unwrap();
+    assert!((i1.uv.0 - 0.2).abs() < 1e-3 && (i1.uv.1 - 0.4).abs() < 1e-3, "{:?}", i1.uv);
+    assert!((i2.uv.0 - 0.2).abs() < 1e-3 && (i2.uv.1 - 0.4).abs() < 1e-3, "{:?}", i2.uv);
+  }
+
+  #[test]
+  fn shading_normal_test() {
+    let mut t = Triangle::new(
+      Vector3::new(5.0, 0.0, 0.0),
+      Vector3::new(0.0, 0.0, 0.0),
+      Vector3::new(0.0, 0.0, 5.0),
+      Arc::new(LambertianMaterial { emission: Vector3::zero(), albedo: Arc::new(ConstantTexture { value: Vector3::zero() }) }),
+    );
+    let tilted = Vector3::new(1.0, 1.0, 0.0).normalize();
+    let up = Vector3::new(0.0, 1.0, 0.0);
+    t.set_normals([tilted, up, up]);
+    let ray = Ray {
+      origin: Vector3::new(4.999, 5.0, 0.0005),
+      direction: Vector3::new(0.0, -1.0, 0.0),
+      differential: None,
+    };
+    // 頂点付近では頂点法線, 幾何的な法線はそのまま
+    let i1 = t.intersect_3c(&ray).unwrap();
+    let i2 = t.intersect_mt(&ray).unwrap();
+    assert!((i1.shading_normal - tilted).norm() < 1e-3, "{}", i1.shading_normal);
+    assert!((i2.shading_normal - tilted).norm() < 1e-3, "{}", i2.shading_normal);
+    assert!((i2.normal - up).norm() < 1e-3, "{}", i2.normal);
+  }
+
+  #[test]
+  fn sample_solid_angle_test() {
+    let t = Triangle::new(
+      Vector3::new(5.0, 0.0, 0.0),
+      Vector3::new(0.0, 0.0, 0.0),
+      Vector3::new(0.0, 0.0, 5.0),
+      Arc::new(LambertianMaterial { emission: Vector3::zero(), albedo: Arc::new(ConstantTexture { value: Vector3::zero() }) }),
+    );
+    // 面積測度の確率密度の逆数の期待値は三角形の面積, サンプルは三角形上
+    let position = Vector3::new(1.0, 2.0, 1.0);
+    let n = 20000;
+    let mut sum = 0.0;
+    for _ in 0..n {
+      let s = t.sample_solid_angle(position);
+      let p = s.value.position;
+      assert!(p.y.abs() < 1e-3 && p.x >= -1e-3 && p.z >= -1e-3 && p.x + p.z <= 5.0 + 1e-3, "{}", p);
+      sum += 1.0 / s.pdf;
+    }
+    let area = sum / n as f32;
+    assert!((area - t.area).abs() < t.area * 0.02, "{} {}", area, t.area);
+  }
 }